@@ -0,0 +1,34 @@
+//! Animated tiles: a cell of a tile sheet that cycles through other cells of the same sheet.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Frame {
+    pub sheet_id: (u32, u32),
+    /// How long the frame shows, in milliseconds
+    pub duration: u32
+}
+
+/// Total length of one loop of `frames` in milliseconds.
+pub fn length(frames: &[Frame]) -> u64 {
+    frames.iter().map(|f| f.duration as u64).sum()
+}
+
+/// The cell `frames` shows `time` seconds after it started, looping. `None` if there are no
+/// frames or they all last 0ms.
+pub fn frame_at(frames: &[Frame], time: f64) -> Option<(u32, u32)> {
+    let length = length(frames);
+    if length == 0 {
+        return None;
+    }
+
+    let mut at = (time.max(0.0) * 1000.0) as u64 % length;
+    for frame in frames {
+        if at < frame.duration as u64 {
+            return Some(frame.sheet_id);
+        }
+        at -= frame.duration as u64;
+    }
+
+    None
+}
@@ -1,19 +1,29 @@
-extern crate pathdiff;
-use glam::Vec2;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::Path, process::Command, thread, time::Duration};
-use rfd::FileDialog;
+use serde::{de::{Error as _, MapAccess, Visitor}, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use std::{cell::Cell, collections::{HashMap, HashSet}, fmt, fs, path::{Path, PathBuf}, process::{Child, Command}};
 use serde_with::serde_as;
 use indexmap::IndexMap;
 
-use crate::renderer::{LilahTexture, Sprite, ShaderProgram};
-
-const CARGO_REPLACE: &'static str = "[dependencies]\nlilah = { git = \"https://github.com/dollerama/lilah.git\" }\nrusttype = \"*\"";
-const MAIN_REPLACE: &'static str = r#"
+use crate::animation::Frame;
+use crate::brush::Stamp;
+use crate::chunk::ChunkedTiles;
+use crate::collision::CollisionShape;
+use crate::error::{EditorError, Result, WithPath};
+use crate::history::{moved_index, Edit, History, TileChange};
+use crate::orientation::Orientation;
+use crate::prefab::Prefab;
+use crate::{collision, ldtk, prefab, schema, terrain, tiled, validate};
+use crate::validate::Problem;
+use crate::schema::{Extra, FieldName, CONFIG_VERSION, SCENE_VERSION};
+use crate::properties::{self, Properties, Property};
+use crate::terrain::Terrain;
+
+const CARGO_REPLACE: &str = "[dependencies]\nlilah = { git = \"https://github.com/dollerama/lilah.git\" }\nrusttype = \"*\"";
+const MAIN_REPLACE: &str = r#"
     use lilah::application::*;
     use lilah::math::Vec2;
     use lilah::world::*;
 
+//TILE_PROPERTIES
     fn setup(app : &mut App, state : &mut WorldState, scripting : &mut Scripting) {
 
 //ASSETS
@@ -29,14 +39,6 @@ const MAIN_REPLACE: &'static str = r#"
     }
 "#;
 
-pub enum PropertySelect {
-    None,
-    Layer,
-    Tilesheet(usize),
-    Marker(usize),
-    Script
-}
-
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum AssetType {
     Script,
@@ -59,101 +61,708 @@ pub struct Asset {
     pub absolute_path: String,
     pub type_of: AssetType,
     pub load_type: LoadType,
-    pub load_order: Option<usize>
+    pub load_order: Option<usize>,
+    #[serde(flatten)]
+    pub extra: Extra
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub format_version: u32,
     pub assets: HashMap<String, Asset>,
-    pub window_size: (f32, f32)
+    pub window_size: (f32, f32),
+    /// Stamps saved for every scene of the project
+    #[serde(default)]
+    pub brushes: Vec<Stamp>,
+    /// Entity templates for every scene of the project
+    #[serde(default)]
+    pub prefabs: Vec<Prefab>,
+    #[serde(flatten)]
+    pub extra: Extra,
+    /// The version the file was upgraded from when loaded, until it is first saved
+    #[serde(skip)]
+    upgraded_from: Cell<Option<u32>>
 }
 
 impl Config {
     pub fn new() -> Self {
-        Self { assets: HashMap::new(), window_size: (800f32, 600f32) }
+        Self { format_version: CONFIG_VERSION, assets: HashMap::new(), window_size: (800f32, 600f32), brushes: Vec::new(), prefabs: Vec::new(), extra: Extra::new(), upgraded_from: Cell::new(None) }
+    }
+
+    /// Reads the config at `file`, upgrading it in memory if it is from an older editor. The
+    /// file itself is only upgraded by `App::write_config`.
+    pub fn load(file: &Path) -> Result<Self> {
+        let (config, version): (Self, Option<u32>) = schema::load_config(file)?;
+        config.upgraded_from.set(version);
+        Ok(config)
+    }
+
+    /// The version the file was upgraded from when loaded, `None` if it was current or has been
+    /// saved since.
+    pub fn upgraded_from(&self) -> Option<u32> {
+        self.upgraded_from.get()
     }
 }
 
-#[derive(Serialize, Deserialize)]
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TileSheet {
     pub filename: String,
     pub path: String,
     pub absolute_path: String,
     pub tile_size: (u32, u32),
-    pub sheet_size: (u32, u32)
+    pub sheet_size: (u32, u32),
+    /// Pixels around the edge of the image before the first cell
+    #[serde(default)]
+    pub margin: u32,
+    /// Pixels between neighbouring cells
+    #[serde(default)]
+    pub spacing: u32,
+    /// Autotiling rules, painted with the terrain brush
+    #[serde(default)]
+    pub terrains: Vec<Terrain>,
+    /// Custom properties of single cells, by `(column, row)`
+    #[serde_as(as = "Vec<(_, _)>")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<(u32, u32), Properties>,
+    /// Collision shapes of single cells, by `(column, row)`
+    #[serde_as(as = "Vec<(_, _)>")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub collision: HashMap<(u32, u32), Vec<CollisionShape>>,
+    /// Frames of animated cells, by `(column, row)`. Tiles of an animated cell play them
+    #[serde_as(as = "Vec<(_, _)>")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub animations: HashMap<(u32, u32), Vec<Frame>>,
+    #[serde(flatten)]
+    pub extra: Extra
 }
 
 impl TileSheet {
     pub fn get_num_of_tiles(&self) -> (u32, u32) {
-        (self.sheet_size.0/self.tile_size.0, self.sheet_size.1/self.tile_size.1) 
+        let count = |sheet: u32, tile: u32| {
+            (sheet.saturating_sub(self.margin * 2) + self.spacing)
+                .checked_div(tile + self.spacing)
+                .unwrap_or(0)
+        };
+
+        (count(self.sheet_size.0, self.tile_size.0), count(self.sheet_size.1, self.tile_size.1))
+    }
+
+    /// Pixel rectangle `[x, y, width, height]` of a cell, measured from the top left of the image.
+    pub fn cell_rect(&self, cell: (u32, u32)) -> [u32; 4] {
+        [
+            self.margin + cell.0 * (self.tile_size.0 + self.spacing),
+            self.margin + cell.1 * (self.tile_size.1 + self.spacing),
+            self.tile_size.0,
+            self.tile_size.1
+        ]
+    }
+
+    /// Texture coordinates `[u0, v0, u1, v1]` of a cell, inset half a texel so neighbours don't bleed in.
+    pub fn cell_uv(&self, cell: (u32, u32)) -> [f32; 4] {
+        let [x, y, w, h] = self.cell_rect(cell);
+        let size = (self.sheet_size.0.max(1) as f32, self.sheet_size.1.max(1) as f32);
+
+        [
+            (x as f32 + 0.5) / size.0,
+            (y as f32 + 0.5) / size.1,
+            ((x + w) as f32 - 0.5) / size.0,
+            ((y + h) as f32 - 0.5) / size.1
+        ]
+    }
+
+    /// The custom property `key` of `cell`, if it has one.
+    pub fn property(&self, cell: (u32, u32), key: &str) -> Option<&Property> {
+        self.properties.get(&cell).and_then(|p| p.get(key))
+    }
+
+    /// Sets the custom property `key` of `cell`, or removes it if `property` is `None`.
+    pub fn set_property(&mut self, cell: (u32, u32), key: &str, property: Option<Property>) {
+        match property {
+            Some(property) => {
+                self.properties.entry(cell).or_default().insert(key.to_string(), property);
+            }
+            None => {
+                if let Some(properties) = self.properties.get_mut(&cell) {
+                    properties.remove(key);
+                    if properties.is_empty() {
+                        self.properties.remove(&cell);
+                    }
+                }
+            }
+        }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// A tile to write at a position, `None` erasing whatever is there.
+pub type TileWrite = ((i32, i32), Option<Tile>);
+
+/// Read and written by hand rather than with a flattened `extra`: scenes have far too many tiles
+/// to pay for buffering each one. The flags are left out of the file while false.
+#[derive(Clone, PartialEq)]
 pub struct Tile {
     pub sheet: String,
     pub sheet_id: (u32, u32),
-    pub position: (f32, f32)
+    /// Mirrored left to right, after `rotate`
+    pub flip_x: bool,
+    /// Mirrored top to bottom, after `rotate`
+    pub flip_y: bool,
+    /// Turned 90° clockwise
+    pub rotate: bool,
+    pub extra: Extra
 }
 
-#[derive(Serialize, Deserialize)]
+impl Serialize for Tile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("sheet", &self.sheet)?;
+        map.serialize_entry("sheet_id", &self.sheet_id)?;
+        for (name, set) in [("flip_x", self.flip_x), ("flip_y", self.flip_y), ("rotate", self.rotate)] {
+            if set {
+                map.serialize_entry(name, &true)?;
+            }
+        }
+        for (name, value) in &self.extra {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Tile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct TileVisitor;
+
+        impl<'de> Visitor<'de> for TileVisitor {
+            type Value = Tile;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a tile")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Tile, A::Error> {
+                let (mut sheet, mut sheet_id) = (None, None);
+                let (mut flip_x, mut flip_y, mut rotate) = (false, false, false);
+                let mut extra = Extra::new();
+                while let Some(FieldName(name)) = map.next_key()? {
+                    match name.as_ref() {
+                        "sheet" => sheet = Some(map.next_value()?),
+                        "sheet_id" => sheet_id = Some(map.next_value()?),
+                        "flip_x" => flip_x = map.next_value()?,
+                        "flip_y" => flip_y = map.next_value()?,
+                        "rotate" => rotate = map.next_value()?,
+                        _ => {
+                            extra.insert(name.into_owned(), map.next_value()?);
+                        }
+                    }
+                }
+
+                Ok(Tile {
+                    sheet: sheet.ok_or_else(|| A::Error::missing_field("sheet"))?,
+                    sheet_id: sheet_id.ok_or_else(|| A::Error::missing_field("sheet_id"))?,
+                    flip_x,
+                    flip_y,
+                    rotate,
+                    extra
+                })
+            }
+        }
+
+        deserializer.deserialize_map(TileVisitor)
+    }
+}
+
+impl Tile {
+    /// `orient_uv` with this tile's flags.
+    pub fn corner_uvs(&self, uv: [f32; 4]) -> [[f32; 2]; 4] {
+        orient_uv(uv, self.flip_x, self.flip_y, self.rotate)
+    }
+}
+
+/// Texture coordinates for the top left, top right, bottom right and bottom left corners of a
+/// quad showing `uv` (`[u0, v0, u1, v1]`), turned 90° clockwise if `rotate` and then mirrored.
+pub fn orient_uv(uv: [f32; 4], flip_x: bool, flip_y: bool, rotate: bool) -> [[f32; 2]; 4] {
+    let mut corners = [[uv[0], uv[1]], [uv[2], uv[1]], [uv[2], uv[3]], [uv[0], uv[3]]];
+
+    // Turned clockwise, each corner shows what was at the corner before it
+    if rotate {
+        corners.rotate_right(1);
+    }
+    if flip_x {
+        corners = [corners[1], corners[0], corners[3], corners[2]];
+    }
+    if flip_y {
+        corners = [corners[3], corners[2], corners[1], corners[0]];
+    }
+
+    corners
+}
+
+/// Adds the .json files under `dir` to `files`, leaving out cargo's target directory and
+/// hidden directories.
+fn find_json(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_path(dir)? {
+        let path = entry.with_path(dir)?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if name != "target" && !name.starts_with('.') {
+                find_json(&path, files)?;
+            }
+        } else if path.extension().is_some_and(|e| e == "json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Where the merged collision of the scene saved at `scene` goes: `level.json` gets
+/// `level.collision.json`.
+pub fn collision_path(scene: &Path) -> PathBuf {
+    scene.with_extension("collision.json")
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Marker {
     pub position: [f32; 2],
-    pub name: String
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: Extra
+}
+
+/// The picture an entity is drawn with: a cell of one of the scene's tile sheets.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntitySprite {
+    pub sheet: String,
+    pub sheet_id: (u32, u32)
+}
+
+/// Something placed freely on an object layer, off the tile grid.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entity {
+    pub name: String,
+    /// Centre, in scene pixels
+    pub position: [f32; 2],
+    /// Degrees, anticlockwise
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "Entity::default_scale")]
+    pub scale: [f32; 2],
+    /// Multiplied into the sprite's colour
+    #[serde(default = "Entity::default_tint")]
+    pub tint: [f32; 4],
+    /// Entities with a higher z draw over the others on their layer
+    #[serde(default)]
+    pub z: i32,
+    /// Name of the prefab this is an instance of. `sprite` and `properties` then only hold
+    /// what the instance overrides.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefab: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<EntitySprite>,
+    #[serde(default, skip_serializing_if = "Properties::is_empty")]
+    pub properties: Properties,
+    #[serde(flatten)]
+    pub extra: Extra
 }
 
-#[serde_as]
-#[derive(Serialize, Deserialize)]
+impl Entity {
+    /// Size of entities without a sprite, or whose tile sheet is missing
+    pub const PLACEHOLDER_SIZE: (u32, u32) = (16, 16);
+
+    pub fn new(name: &str, position: [f32; 2]) -> Self {
+        Self {
+            name: name.to_string(),
+            position,
+            rotation: 0.0,
+            scale: Self::default_scale(),
+            tint: Self::default_tint(),
+            z: 0,
+            prefab: None,
+            sprite: None,
+            properties: Properties::new(),
+            extra: Extra::new()
+        }
+    }
+
+    fn default_scale() -> [f32; 2] {
+        [1.0, 1.0]
+    }
+
+    fn default_tint() -> [f32; 4] {
+        [1.0, 1.0, 1.0, 1.0]
+    }
+
+    /// The prefab the entity is an instance of, if `prefabs` has it.
+    pub fn prefab<'a>(&self, prefabs: &'a [Prefab]) -> Option<&'a Prefab> {
+        prefab::find(prefabs, self.prefab.as_ref()?)
+    }
+
+    /// The sprite the entity draws with: its own, or else its prefab's.
+    pub fn resolved_sprite<'a>(&'a self, prefabs: &'a [Prefab]) -> Option<&'a EntitySprite> {
+        self.sprite.as_ref().or_else(|| self.prefab(prefabs)?.sprite.as_ref())
+    }
+
+    /// Its prefab's properties with the entity's own laid over them.
+    pub fn resolved_properties(&self, prefabs: &[Prefab]) -> Properties {
+        let mut properties = self.prefab(prefabs).map(|p| p.properties.clone()).unwrap_or_default();
+        properties.extend(self.properties.iter().map(|(k, v)| (k.clone(), v.clone())));
+        properties
+    }
+
+    /// The tile sheet of the entity's sprite, if it has one and `tile_sheets` has it.
+    pub fn sheet<'a>(&self, tile_sheets: &'a [TileSheet], prefabs: &[Prefab]) -> Option<&'a TileSheet> {
+        let sprite = self.resolved_sprite(prefabs)?;
+        tile_sheets.iter().find(|s| s.path == sprite.sheet)
+    }
+
+    /// Unscaled size in pixels: the sprite's cell, or `PLACEHOLDER_SIZE`.
+    pub fn base_size(&self, tile_sheets: &[TileSheet], prefabs: &[Prefab]) -> (u32, u32) {
+        self.sheet(tile_sheets, prefabs).map_or(Self::PLACEHOLDER_SIZE, |s| s.tile_size)
+    }
+
+    /// Corners of the entity once scaled and rotated, anticlockwise from the bottom left, in
+    /// scene pixels.
+    pub fn corners(&self, tile_sheets: &[TileSheet], prefabs: &[Prefab]) -> [[f32; 2]; 4] {
+        let size = self.base_size(tile_sheets, prefabs);
+        let half = [size.0 as f32 * self.scale[0] / 2.0, size.1 as f32 * self.scale[1] / 2.0];
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]].map(|[x, y]| {
+            let (x, y) = (x * half[0], y * half[1]);
+            [self.position[0] + x * cos - y * sin, self.position[1] + x * sin + y * cos]
+        })
+    }
+
+    /// Whether `point`, in scene pixels, is on the entity.
+    pub fn contains(&self, tile_sheets: &[TileSheet], prefabs: &[Prefab], point: [f32; 2]) -> bool {
+        let size = self.base_size(tile_sheets, prefabs);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let d = [point[0] - self.position[0], point[1] - self.position[1]];
+        let local = [d[0] * cos + d[1] * sin, -d[0] * sin + d[1] * cos];
+
+        local[0].abs() <= (size.0 as f32 * self.scale[0]).abs() / 2.0 &&
+        local[1].abs() <= (size.1 as f32 * self.scale[1]).abs() / 2.0
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub visible: bool,
+    pub entities: Vec<Entity>,
+    #[serde(flatten)]
+    pub extra: Extra
+}
+
+impl ObjectLayer {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), visible: true, entities: Vec::new(), extra: Extra::new() }
+    }
+
+    /// Indices of the entities in the order they draw, lowest z first.
+    pub fn draw_order(&self) -> Vec<usize> {
+        let mut order = (0..self.entities.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|i| self.entities[*i].z);
+        order
+    }
+
+    /// The entity drawn on top at `point`, if any.
+    pub fn entity_at(&self, tile_sheets: &[TileSheet], prefabs: &[Prefab], point: [f32; 2]) -> Option<usize> {
+        self.draw_order().into_iter().rev().find(|i| self.entities[*i].contains(tile_sheets, prefabs, point))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Layer {
-    #[serde_as(as = "Vec<(_, _)>")]
-    pub tiles: HashMap<(i32, i32), Tile>,
+    /// Empty for layers from before layers had names, see `label`
+    #[serde(default)]
+    pub name: String,
+    /// Keyed by cell, `(column, row)` with y up, see `cell_position`
+    #[serde(rename = "chunks")]
+    pub tiles: ChunkedTiles,
+    /// Width and height of a cell in pixels. Tiles of a sheet with another tile size are
+    /// centred on their cell
+    #[serde(default = "Layer::default_grid_size")]
+    pub grid_size: (u32, u32),
     pub visible: bool,
     pub collision: bool,
+    /// The sheet the palette paints from while this is the current layer. Tiles keep their
+    /// own sheets, so a layer can mix tiles from several
     pub tile_sheet: String,
-    pub current_tile_item: i32
+    /// Index of `tile_sheet` in the scene's tile sheets
+    pub current_tile_item: i32,
+    /// 0 is transparent, 1 opaque
+    #[serde(default = "Layer::default_opacity")]
+    pub opacity: f32,
+    /// Moves the whole layer, in pixels
+    #[serde(default)]
+    pub offset: [f32; 2],
+    /// How fast the layer scrolls with the camera, 1 being with the tiles of other layers
+    #[serde(default = "Layer::default_parallax")]
+    pub parallax: [f32; 2],
+    /// Multiplied into the colour of every tile
+    #[serde(default = "Layer::default_tint")]
+    pub tint: [f32; 4],
+    /// Tools don't paint on locked layers
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(flatten)]
+    pub extra: Extra
+}
+
+/// What the Layer tab edits: everything about a layer but its tiles, its visibility and the
+/// sheet the palette paints from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LayerSettings {
+    pub name: String,
+    pub grid_size: (u32, u32),
+    pub collision: bool,
+    pub locked: bool,
+    pub opacity: f32,
+    pub tint: [f32; 4],
+    pub offset: [f32; 2],
+    pub parallax: [f32; 2]
 }
 
 impl Layer {
     pub fn new() -> Self {
         Self { 
-            tiles: HashMap::new(),
+            name: String::new(),
+            tiles: ChunkedTiles::new(),
+            grid_size: Self::default_grid_size(),
             visible: true,
             collision: false,
             tile_sheet: String::from(""),
-            current_tile_item: 0
+            current_tile_item: 0,
+            opacity: Self::default_opacity(),
+            offset: [0.0, 0.0],
+            parallax: Self::default_parallax(),
+            tint: Self::default_tint(),
+            locked: false,
+            extra: Extra::new()
+        }
+    }
+
+    pub fn settings(&self) -> LayerSettings {
+        LayerSettings {
+            name: self.name.clone(),
+            grid_size: self.grid_size,
+            collision: self.collision,
+            locked: self.locked,
+            opacity: self.opacity,
+            tint: self.tint,
+            offset: self.offset,
+            parallax: self.parallax
         }
     }
+
+    pub fn set_settings(&mut self, settings: &LayerSettings) {
+        self.name = settings.name.clone();
+        self.grid_size = settings.grid_size;
+        self.collision = settings.collision;
+        self.locked = settings.locked;
+        self.opacity = settings.opacity;
+        self.tint = settings.tint;
+        self.offset = settings.offset;
+        self.parallax = settings.parallax;
+    }
+
+    fn default_grid_size() -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn default_opacity() -> f32 {
+        1.0
+    }
+
+    fn default_parallax() -> [f32; 2] {
+        [1.0, 1.0]
+    }
+
+    fn default_tint() -> [f32; 4] {
+        [1.0, 1.0, 1.0, 1.0]
+    }
+
+    /// The layer's name, or "Layer `index`" if it has none.
+    pub fn label(&self, index: usize) -> String {
+        match self.name.is_empty() {
+            true => format!("Layer {}", index),
+            false => self.name.clone()
+        }
+    }
+
+    /// The middle of `cell` in scene pixels, before the layer's `shift`.
+    pub fn cell_position(&self, orientation: Orientation, cell: (i32, i32)) -> [f32; 2] {
+        orientation.cell_position(cell, self.grid_size)
+    }
+
+    /// The cell under `point`, in scene pixels before the layer's `shift`.
+    pub fn cell_at(&self, orientation: Orientation, point: [f32; 2]) -> (i32, i32) {
+        orientation.cell_at(point, self.grid_size)
+    }
+
+    /// The lowest and highest cells under the scene pixels from `min` to `max`, before the
+    /// layer's `shift`.
+    pub fn cell_range(&self, orientation: Orientation, min: [f32; 2], max: [f32; 2]) -> ((i32, i32), (i32, i32)) {
+        orientation.cell_range(min, max, self.grid_size)
+    }
+
+    /// `tint` with `opacity` folded into its alpha.
+    pub fn color(&self) -> [f32; 4] {
+        [self.tint[0], self.tint[1], self.tint[2], self.tint[3] * self.opacity]
+    }
+
+    /// How far the layer is drawn from where its tiles are when the camera is at `camera`:
+    /// its offset, plus whatever parallax holds it back from (or pushes it past) the camera.
+    pub fn shift(&self, camera: [f32; 2]) -> [f32; 2] {
+        [
+            self.offset[0] + camera[0] * (1.0 - self.parallax[0]),
+            self.offset[1] + camera[1] * (1.0 - self.parallax[1])
+        ]
+    }
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How exports give tile positions.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Coordinates {
+    /// Cells of the layer's grid, as the scene keeps them
+    Grid,
+    /// The middle of the tile in scene pixels, as scenes did before layers had a grid
+    Pixel
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Scene {
+    #[serde(default)]
+    pub format_version: u32,
     pub name: String,
     pub path: String,
     pub tile_sheets: Vec<TileSheet>,
     pub layers: Vec<Layer>,
-    pub markers: Vec<Marker>
+    pub markers: Vec<Marker>,
+    /// Layers of freely placed entities, drawn over the tile layers
+    #[serde(default)]
+    pub object_layers: Vec<ObjectLayer>,
+    /// Whether the merged collision written on save includes outlines as well as rectangles
+    #[serde(default)]
+    pub collision_outlines: bool,
+    /// How every layer lays out its cells
+    #[serde(default)]
+    pub orientation: Orientation,
+    #[serde(flatten)]
+    pub extra: Extra,
+    /// The version the file was upgraded from when loaded, until it is first saved
+    #[serde(skip)]
+    upgraded_from: Cell<Option<u32>>
 }
 
 impl Scene {
-    fn new(name: &str, path: &str) -> Self {
+    pub fn new(name: &str, path: &str) -> Self {
         Self {
+            format_version: SCENE_VERSION,
             name: name.to_string(),
             path: path.to_string(),
             tile_sheets: Vec::new(),
             markers: Vec::new(),
-            layers: Vec::new()
+            layers: Vec::new(),
+            object_layers: Vec::new(),
+            collision_outlines: false,
+            orientation: Orientation::Orthogonal,
+            extra: Extra::new(),
+            upgraded_from: Cell::new(None)
         }
     }
+
+    /// Reads the scene at `file`, upgrading it in memory if it is from an older editor. The
+    /// file itself is only upgraded by `write`.
+    pub fn load(file: &Path) -> Result<Self> {
+        let (scene, version): (Self, Option<u32>) = schema::load_scene(file)?;
+        scene.upgraded_from.set(version);
+        Ok(scene)
+    }
+
+    /// The version the file was upgraded from when loaded, `None` if it was current or has been
+    /// saved since.
+    pub fn upgraded_from(&self) -> Option<u32> {
+        self.upgraded_from.get()
+    }
+
+    /// The scene as pretty printed json for other tools. With `Coordinates::Pixel` each layer
+    /// lists its `tiles` as `[[x, y], tile]` pairs in pixels instead of chunks of cells, which
+    /// the editor can't read back.
+    pub fn export_json(&self, coordinates: Coordinates) -> Result<String> {
+        let mut value = serde_json::to_value(self).with_path(&self.path)?;
+        if coordinates == Coordinates::Pixel {
+            let layers = value["layers"].as_array_mut().into_iter().flatten();
+            for (layer, exported) in self.layers.iter().zip(layers) {
+                // Top to bottom, which is also the order to draw them in on any orientation
+                let mut tiles = layer.tiles.iter()
+                    .map(|(cell, tile)| (layer.cell_position(self.orientation, *cell), tile))
+                    .collect::<Vec<([f32; 2], &Tile)>>();
+                tiles.sort_by(|(a, _), (b, _)| b[1].total_cmp(&a[1]).then(a[0].total_cmp(&b[0])));
+                let tiles = tiles.into_iter()
+                    .map(|(position, tile)| serde_json::json!([position, tile]))
+                    .collect::<Vec<serde_json::Value>>();
+
+                if let Some(exported) = exported.as_object_mut() {
+                    exported.remove("chunks");
+                    exported.insert(String::from("tiles"), serde_json::Value::Array(tiles));
+                }
+            }
+        }
+        serde_json::to_string_pretty(&value).with_path(&self.path)
+    }
+
+    /// Writes the scene to `file`, and the merged geometry of its collision layers next to it
+    /// (see `collision_path`). The first write of an upgraded scene backs up the old file (see
+    /// `schema::backup_path`). The whole file is rewritten; unchanged chunks only skip being
+    /// serialized again.
+    pub fn write(&self, file: &Path) -> Result<()> {
+        if let Some(version) = self.upgraded_from.get() {
+            schema::back_up(file, version)?;
+        }
+        fs::write(
+            file,
+            serde_json::to_string(self).with_path(file)?
+        ).with_path(file)?;
+        self.upgraded_from.set(None);
+
+        let collision_path = collision_path(file);
+        let merged = collision::merge(self, self.collision_outlines);
+        if !merged.is_empty() {
+            fs::write(
+                &collision_path,
+                serde_json::to_string(&serde_json::json!({ "layers": merged })).with_path(&collision_path)?
+            ).with_path(&collision_path)?;
+        } else if collision_path.exists() {
+            fs::remove_file(&collision_path).with_path(&collision_path)?;
+        }
+
+        Ok(())
+    }
 }
 
+/// Project and scene state shared by the editor window and the command line, with no GL or dialogs.
 pub struct App {
     pub config: Config,
     pub current_project: String,
-    pub textures: IndexMap<String, LilahTexture>,
     pub current_tile_sheet: String,
     pub current_scene: Option<Scene>,
     pub current_layer: usize,
-    pub sprite_buffer: Vec<HashMap<(i32, i32), Sprite>>
+    pub history: History
 }
 
 impl App {
@@ -161,11 +770,10 @@ impl App {
         Self {
             config: Config::new(),
             current_project: String::from(""),
-            textures: IndexMap::new(),
             current_tile_sheet: String::from(""),
             current_scene: None,
-            sprite_buffer: Vec::new(),
-            current_layer: 0
+            current_layer: 0,
+            history: History::new()
         }
     }
 
@@ -173,213 +781,200 @@ impl App {
         self.current_tile_sheet.clone()
     }
 
-    pub fn load_texture_internal(&mut self, gl: &glow::Context, file : &str) {
-        let mut new_texture = unsafe { 
-            LilahTexture::new(gl) 
-        };
-
-        unsafe {
-            new_texture.set_wrapping(gl, glow::REPEAT as i32);
-            new_texture.set_filtering(gl, glow::LINEAR as i32);
-        }
-
-        unsafe {
-            if let Err(e) = new_texture.load(gl, &Path::new(file)) {
-                eprintln!("{}", e);
-            }
+    fn project_dir(&self) -> Result<&str> {
+        if self.current_project.is_empty() {
+            return Err(EditorError::NoProject);
         }
 
-        //let file = String::from(Path::new(file).file_name().unwrap().to_str().unwrap());
-
-        self.textures.insert(format!("lilah__editor__internal__ignore__{}", file), new_texture);
+        Ok(&self.current_project)
     }
 
-    pub fn load_texture(&mut self, gl: &glow::Context, file : &str) {
-        let mut new_texture = unsafe { 
-            LilahTexture::new(gl) 
-        };
+    /// Saves the project's config.json, backing up the old file first if it was upgraded.
+    pub fn write_config(&self) -> Result<()> {
+        let path = format!("{}/config.json", self.project_dir()?);
+        if let Some(version) = self.config.upgraded_from.get() {
+            schema::back_up(Path::new(&path), version)?;
+        }
+        fs::write(
+            &path, 
+            serde_json::to_string(&self.config).with_path(&path)?
+        ).with_path(&path)?;
+        self.config.upgraded_from.set(None);
+        Ok(())
+    }
 
-        unsafe {
-            new_texture.set_wrapping(gl, glow::REPEAT as i32);
-            new_texture.set_filtering(gl, glow::LINEAR as i32);
+    /// Saves the current scene, see `Scene::write`.
+    pub fn write_current_scene(&self) -> Result<()> {
+        if let Some(scene) = self.current_scene.as_ref() {
+            scene.write(Path::new(&format!("{}/{}", self.project_dir()?, scene.path)))?;
         }
 
-        unsafe {
-            if let Err(e) = new_texture.load(gl, &Path::new(file)) {
-                eprintln!("{}", e);
-            }
+        Ok(())
+    }
+ 
+    /// Reads the scene at `file` and makes it the current scene.
+    pub fn load_scene(&mut self, file: &Path) -> Result<()> {
+        let scene = Scene::load(file)?;
+
+        if let Some(layer) = scene.layers.first() {
+            self.current_tile_sheet = layer.tile_sheet.clone();
         }
 
-        //let file = String::from(Path::new(file).file_name().unwrap().to_str().unwrap());
+        self.current_scene = Some(scene);
+        self.current_layer = 0;
+        self.history.clear();
 
-        self.textures.insert(file.to_string(), new_texture);
+        Ok(())
     }
 
-    pub fn write_config(&self) {
-        let _ = fs::write(
-            format!("{}/config.json", 
-            self.current_project).as_str(), 
-            serde_json::to_string(&self.config).unwrap()
-        );
-    }
+    /// Creates a scene with one empty layer at `file` (".json" is appended) and makes it the current scene.
+    pub fn create_scene(&mut self, file: &Path) -> Result<()> {
+        let mut new_scene = Scene::new("", "");
+        new_scene.layers.push(Layer::new());
 
-    pub fn write_current_scene(&self) {
-        if let Some(scene) = self.current_scene.as_ref() {
-            let _ = fs::write(
-                format!("{}/{}", self.current_project, scene.path),
-                serde_json::to_string(&scene).unwrap()
-            );
-        }
+        self.add_scene(new_scene, file)
     }
- 
-    pub fn open_scene(&mut self, gl: &glow::Context, program: &ShaderProgram) {
-        if let Some(file) = FileDialog::new()
-        .set_directory(format!("{}", self.current_project))
-        .pick_file() {
-            let path_base = Path::new(&self.current_project);
-            let file_path_str = file.as_path().to_str().unwrap();
-            let path = Path::new(file_path_str);
-            let relative_path_to = pathdiff::diff_paths(path, path_base).unwrap();
-            let _file_path = relative_path_to.as_path();
-
-            match fs::read(file) {
-                Ok(v) => {
-                    self.current_scene = Some(serde_json::from_slice(&v).unwrap());
-                }
-                Err(_) => {
-                }
-            } 
 
-            if let Some(scene) = self.current_scene.as_ref() {
-                let mut to_load = vec!();
-                for i in &scene.tile_sheets {
-                    to_load.push(i.path.clone());
-                }
-                for i in to_load {
-                    self.load_texture(gl, &i);
-                }
-            }
+    /// Saves `scene` at `file` (".json" is appended), naming it after the file, and makes it the current scene.
+    pub fn add_scene(&mut self, mut scene: Scene, file: &Path) -> Result<()> {
+        let relative_path_to = pathdiff::diff_paths(file, Path::new(self.project_dir()?))
+            .unwrap_or_else(|| file.to_path_buf());
 
-            if let Some(scene) = self.current_scene.as_ref() {
-                self.current_tile_sheet = scene.layers[0].tile_sheet.clone();
+        scene.name = relative_path_to.file_name().unwrap_or_default().to_string_lossy().to_string();
+        scene.path = format!("{}.json", relative_path_to.to_string_lossy());
 
-                let mut tiles = vec!();
-                self.sprite_buffer.clear();
-                for j in scene.layers.iter().enumerate() {
-                    for i in &j.1.tiles {
-                        tiles.push((j.0, i.clone()));
-                    }
-                    self.sprite_buffer.push(HashMap::new());
-                }
-                
-                for i in tiles {
-                    let mut new_spr = Sprite::new(&i.1.1.sheet);
-                    let sheet = scene.tile_sheets.iter()
-                        .find(|&a| a.path == i.1.1.sheet);
-
-                    new_spr.load(gl, &program, &self.textures);
-                    if let Some(sheet) = sheet {
-                        new_spr.cut_sprite_sheet(0, 0, sheet.get_num_of_tiles().0, sheet.get_num_of_tiles().1);
-                    }
-                    new_spr.anim_sprite_sheet(
-                        gl, 
-                        &program,  
-                        i.1.1.sheet_id.0 as i32, i.1.1.sheet_id.1 as i32
-                    );
-                    new_spr.position = Vec2::new(
-                        i.1.0.0 as f32, 
-                        i.1.0.1 as f32
-                    );
-
-                    self.sprite_buffer[i.0].insert(* i.1.0, new_spr);
-                }
-            }
-        }
-    }
+        scene.write(Path::new(&format!("{}.json", file.to_string_lossy())))?;
 
-    pub fn new_scene(&mut self) {
-        if let Some(file) = FileDialog::new()
-        .set_directory(format!("{}", self.current_project))
-        .save_file() {
-            let file_path_str = file.as_path().to_str().unwrap();
+        self.current_tile_sheet = scene.layers.first().map(|l| l.tile_sheet.clone()).unwrap_or_default();
+        self.current_scene = Some(scene);
+        self.current_layer = 0;
+        self.history.clear();
 
-            let path_base = Path::new(&self.current_project);
-            let path = Path::new(file_path_str);
-            let relative_path_to = pathdiff::diff_paths(path, path_base).unwrap();
+        self.write_config()
+    }
 
-            let file_name = relative_path_to.as_path().file_name().unwrap().to_str().unwrap();
-            let file_path = relative_path_to.as_path().to_str().unwrap();
+    /// Imports the Tiled map at `map` as a new scene at `file` (see `add_scene`) and registers its
+    /// tile sheet images as external textures. Returns what the import couldn't carry over.
+    pub fn import_tiled(&mut self, map: &Path, file: &Path) -> Result<Vec<String>> {
+        let import = tiled::import(map)?;
+        let mut warnings = import.warnings;
 
-            let mut new_scene = Scene::new(file_name, &format!("{}.json", file_path));
-            new_scene.layers.push(Layer::new());
-            self.sprite_buffer.push(HashMap::new());
+        let images = Self::sheet_images(&import.scene);
+        self.add_scene(import.scene, file)?;
+        self.add_sheet_images(images, &mut warnings);
 
-            let _ = fs::write(
-                format!("{}.json", file.as_path().to_str().unwrap()),
-                serde_json::to_string(&new_scene).unwrap()
-            );
+        Ok(warnings)
+    }
 
-            self.current_scene = Some(new_scene);
+    /// Imports every level of the LDtk project at `project` as a scene in `dir`, named after the
+    /// level, and registers the tile sheet images as external textures. The last level becomes
+    /// the current scene. Returns what the import couldn't carry over.
+    pub fn import_ldtk(&mut self, project: &Path, dir: &Path) -> Result<Vec<String>> {
+        let import = ldtk::import(project)?;
+        let mut warnings = import.warnings;
+
+        let mut images = vec!();
+        for scene in import.scenes {
+            for image in Self::sheet_images(&scene) {
+                if !images.contains(&image) {
+                    images.push(image);
+                }
+            }
 
-            self.write_config();
+            let file = dir.join(&scene.name);
+            self.add_scene(scene, &file)?;
         }
-    }
+        self.add_sheet_images(images, &mut warnings);
 
-    pub fn new_project(&mut self) -> &str {
-        if let Some(file) = FileDialog::new()
-        .set_directory("/")
-        .save_file() {
-            Command::new( "cargo" )
-            .args(["new", file.as_path().to_str().unwrap().clone()])
-            .spawn( )
-            .unwrap( ); 
+        Ok(warnings)
+    }
 
-            self.current_project = file.as_path().to_str().unwrap().to_string();
+    fn sheet_images(scene: &Scene) -> Vec<PathBuf> {
+        scene.tile_sheets.iter().map(|s| PathBuf::from(&s.path)).collect()
+    }
 
-            while fs::read_to_string(format!("{}/Cargo.toml", self.current_project)).is_err() {
-                thread::sleep(Duration::from_millis(10));
+    /// Adds the images of imported tile sheets as external assets unless the project already has them.
+    fn add_sheet_images(&mut self, images: Vec<PathBuf>, warnings: &mut Vec<String>) {
+        for image in images {
+            let known = self.config.assets.values().any(|a| Path::new(&a.absolute_path) == image);
+            if !known {
+                if let Err(e) = self.add_asset(&image, LoadType::External) {
+                    warnings.push(e.to_string());
+                }
             }
+        }
+    }
 
-            let cargo_file = fs::read_to_string(format!("{}/Cargo.toml", self.current_project)).unwrap();
-            let _ = fs::write(
-                format!("{}/Cargo.toml", self.current_project),
-                cargo_file.replace("[dependencies]", CARGO_REPLACE)
-            );
+    /// Runs `cargo new` at `dir`, points it at lilah and makes it the current project.
+    pub fn create_project(&mut self, dir: &Path) -> Result<()> {
+        let status = Command::new( "cargo" )
+        .args(["new", &dir.to_string_lossy()])
+        .status()
+        .map_err(|e| EditorError::Cargo { command: "new", path: dir.to_path_buf(), reason: e.to_string() })?;
 
-            let _ = fs::write(format!("{}/src/main.rs", self.current_project), MAIN_REPLACE);
-            let _ = fs::create_dir(format!("{}/assets", self.current_project));
-            let _ = fs::create_dir(format!("{}/src/scripts", self.current_project));
-            let _ = fs::create_dir(format!("{}/src/assets", self.current_project));
+        if !status.success() {
+            return Err(EditorError::Cargo { command: "new", path: dir.to_path_buf(), reason: status.to_string() });
+        }
 
-            self.write_config();
+        self.current_project = dir.to_string_lossy().to_string();
+        self.config = Config::new();
+
+        let cargo_path = format!("{}/Cargo.toml", self.current_project);
+        let cargo_file = fs::read_to_string(&cargo_path).with_path(&cargo_path)?;
+        fs::write(
+            &cargo_path,
+            cargo_file.replace("[dependencies]", CARGO_REPLACE)
+        ).with_path(&cargo_path)?;
+
+        let main_path = format!("{}/src/main.rs", self.current_project);
+        fs::write(&main_path, MAIN_REPLACE).with_path(&main_path)?;
+        for dir in ["assets", "src/scripts", "src/assets"] {
+            let path = format!("{}/{}", self.current_project, dir);
+            fs::create_dir_all(&path).with_path(&path)?;
         }
 
-        &self.current_project
+        self.history.clear();
+        self.write_config()
     }
 
-    pub fn open_project(&mut self) -> &str {
-        if let Some(file) = FileDialog::new()
-        .set_directory("/")
-        .pick_folder() {
-            self.current_project = file.as_path().to_str().unwrap().to_string();
+    /// Makes `dir` the current project, reading its config.json or writing a default one.
+    pub fn load_project(&mut self, dir: &Path) -> Result<()> {
+        if !dir.is_dir() {
+            return Err(EditorError::Io { 
+                path: dir.to_path_buf(), 
+                source: std::io::Error::new(std::io::ErrorKind::NotFound, "not a directory") 
+            });
+        }
 
-            match fs::read(format!("{}/config.json", self.current_project).as_str()) {
-                Ok(v) => {
-                    self.config = serde_json::from_slice(&v).unwrap();
-                }
-                Err(_) => {
-                    self.write_config();
-                }
+        let config_path = dir.join("config.json");
+        let config = match config_path.exists() {
+            true => Some(Config::load(&config_path)?),
+            false => None
+        };
+
+        self.current_project = dir.to_string_lossy().to_string();
+        self.history.clear();
+
+        match config {
+            Some(config) => {
+                self.config = config;
+                Ok(())
+            }
+            None => {
+                self.config = Config::new();
+                self.write_config()
             }
         }
-
-        &self.current_project
     }
 
-    pub fn wrangle_main(&self) {
+    /// Generates the project's main.rs source from its config, in a stable order. Tile properties
+    /// come from the tile sheets of every scene in the project, see `other_scenes`.
+    pub fn generate_main(&self) -> Result<String> {
         let mut assets_str = String::from("");
         let mut sorted_scripts = vec!();
-        for asset in &self.config.assets {
+        let mut keys = self.config.assets.keys().collect::<Vec<&String>>();
+        keys.sort();
+        for asset in keys.into_iter().map(|k| (k, &self.config.assets[k])) {
             match asset.1.type_of {
                 AssetType::Script => {
                     match asset.1.load_type {
@@ -388,10 +983,12 @@ impl App {
                             //     format!("\t\tembed_script!(\"{}\", scripting);\n", 
                             //     asset.1.path).as_str()
                             // )
-                            sorted_scripts.push((asset.1.load_order.unwrap(), format!("\t\tembed_script!(\"{}\", scripting);\n", asset.1.path)));
+                            let load_order = asset.1.load_order
+                                .ok_or_else(|| EditorError::MissingLoadOrder { path: PathBuf::from(&asset.1.path) })?;
+                            sorted_scripts.push((load_order, format!("\t\tembed_script!(\"{}\", scripting);\n", asset.1.path)));
                         }
                         LoadType::External => {
-                            panic!("Script cannot be external");
+                            return Err(EditorError::ExternalAsset { path: PathBuf::from(&asset.1.path), type_of: asset.1.type_of.clone() });
                         }
                     }
                 }
@@ -452,193 +1049,462 @@ impl App {
                             )
                         }
                         LoadType::External => {
-                            panic!("Script cannot be external");
+                            return Err(EditorError::ExternalAsset { path: PathBuf::from(&asset.1.path), type_of: asset.1.type_of.clone() });
                         }
                     }
                 }
             }
         }
 
-        sorted_scripts.sort_by(|a, b| a.0.cmp(&b.0));
+        sorted_scripts.sort_by_key(|s| s.0);
 
         for s in sorted_scripts {
             assets_str.push_str(&s.1);
         }
         
-        let _ = fs::write(format!("{}/src/main.rs", self.current_project), MAIN_REPLACE);
-        let main_file = fs::read_to_string(format!("{}/src/main.rs", self.current_project)).unwrap();
-        let _ = fs::write(
-            format!("{}/src/main.rs", self.current_project), 
-            main_file.replace("//ASSETS", &assets_str).replace("WINDOW_SIZE", format!("Vec2.new({}, {})", self.config.window_size.0, self.config.window_size.1).as_str())
-        );
+        // Each scene has its own copy of a sheet, the properties of all of them go in with the
+        // current scene's first
+        let others = self.other_scenes()?;
+        let mut tile_sheets: Vec<TileSheet> = vec!();
+        for sheet in self.current_scene.iter().chain(&others).flat_map(|s| s.tile_sheets.iter().cloned()) {
+            match tile_sheets.iter_mut().find(|s| s.path == sheet.path) {
+                Some(first) => {
+                    for (cell, properties) in sheet.properties {
+                        let cell_properties = first.properties.entry(cell).or_default();
+                        for (key, property) in properties {
+                            cell_properties.entry(key).or_insert(property);
+                        }
+                    }
+                }
+                None => tile_sheets.push(sheet)
+            }
+        }
 
-        while fs::read_to_string(format!("{}/src/main.rs", self.current_project)).is_err() {
-            thread::sleep(Duration::from_millis(10));
+        Ok(MAIN_REPLACE
+            .replace("//TILE_PROPERTIES", &properties::generate(&tile_sheets))
+            .replace("//ASSETS", &assets_str)
+            .replace("WINDOW_SIZE", format!("Vec2.new({}, {})", self.config.window_size.0, self.config.window_size.1).as_str()))
+    }
+
+    /// The scenes saved in the project other than the current one: each .json file under it
+    /// that reads as a scene, in path order. None without a project.
+    pub fn other_scenes(&self) -> Result<Vec<Scene>> {
+        if self.current_project.is_empty() {
+            return Ok(vec!());
+        }
+
+        let dir = Path::new(&self.current_project);
+        let current = self.current_scene.as_ref().map(|s| dir.join(&s.path));
+        let mut files = vec!();
+        find_json(dir, &mut files)?;
+        files.sort();
+
+        let mut scenes = vec!();
+        for file in files {
+            let sidecar = file.to_string_lossy().ends_with(".collision.json");
+            if current.as_ref() == Some(&file) || file == dir.join("config.json") || sidecar {
+                continue;
+            }
+            // Anything else that isn't a scene fails to read
+            if let Ok(scene) = Scene::load(&file) {
+                scenes.push(scene);
+            }
         }
+        Ok(scenes)
     }
 
-    pub fn run_project(&mut self) {
-        self.wrangle_main();
+    /// Regenerates the project's src/main.rs from its config.
+    pub fn wrangle_main(&self) -> Result<()> {
+        let path = format!("{}/src/main.rs", self.project_dir()?);
+        fs::write(&path, self.generate_main()?).with_path(&path)
+    }
 
-        let status = Command::new( "cargo" )
+    /// Regenerates main.rs and starts `cargo run` on the project without waiting for it.
+    pub fn run_project(&mut self) -> Result<Child> {
+        self.wrangle_main()?;
+
+        Command::new( "cargo" )
         .args(["run", "--manifest-path", format!("{}/Cargo.toml", self.current_project).as_str()])
-        .spawn().expect("failed");
+        .spawn()
+        .map_err(|e| EditorError::Cargo { command: "run", path: PathBuf::from(&self.current_project), reason: e.to_string() })
     }
 
-    pub fn add_texture(&mut self, gl: &glow::Context, abs_path: String, path: String, tile_count: &[i32; 2]) {
-        self.load_texture(gl, &path);
-
-        let size = self.textures.get(&path).unwrap().size;
+    /// Adds a tile sheet to the current scene for a texture of `sheet_size` pixels cut into `tile_count` cells.
+    pub fn add_tile_sheet(&mut self, abs_path: String, path: String, sheet_size: (u32, u32), tile_count: &[i32; 2]) -> Result<()> {
+        if tile_count[0] < 1 || tile_count[1] < 1 {
+            return Err(EditorError::InvalidTileCount { path: PathBuf::from(path), count: *tile_count });
+        }
 
         let filename_split = path.split("/").collect::<Vec<&str>>();
         let filename = filename_split[filename_split.len()-1];
 
         if let Some(scene) = self.current_scene.as_mut() {
-            scene.tile_sheets.push(
-                TileSheet { 
-                    filename: filename.to_string(),
-                    absolute_path: abs_path, 
-                    path: path,
-                    tile_size: ((size.x/tile_count[0] as f32) as u32, (size.y/tile_count[1] as f32) as u32), 
-                    sheet_size: (size.x as u32, size.y as u32) 
-                }
-            );
+            let sheet = TileSheet { 
+                filename: filename.to_string(),
+                absolute_path: abs_path, 
+                path: path.clone(),
+                tile_size: ((sheet_size.0 as f32/tile_count[0] as f32) as u32, (sheet_size.1 as f32/tile_count[1] as f32) as u32), 
+                sheet_size,
+                margin: 0,
+                spacing: 0,
+                terrains: Vec::new(),
+                properties: HashMap::new(),
+                collision: HashMap::new(),
+                animations: HashMap::new(),
+                extra: Extra::new()
+            };
+
+            self.history.push(Edit::AddTileSheet { index: scene.tile_sheets.len(), sheet: sheet.clone() });
+            scene.tile_sheets.push(sheet);
+        } else {
+            return Err(EditorError::NoScene);
         }
 
-        self.write_current_scene();
-        self.write_config();
+        self.write_current_scene()?;
+        self.write_config()
     }
 
-    pub fn add_external_asset(&mut self) {
-        if let Some(files) = FileDialog::new()
-        .set_directory(format!("{}", self.current_project))
-        .add_filter("Type", &["png", "wav", "mp3"])
-        .pick_files() {
-            for file in &files {
-                let type_of = match file.extension().unwrap().to_str() {
-                    Some("wren") => {
-                        return;
-                    }
-                    Some("png") => {
-                        AssetType::Texture
-                    }
-                    Some("wav") => {
-                        AssetType::Sfx
-                    }
-                    Some("mp3") => {
-                        AssetType::Music
-                    }
-                    Some("ttf") => {
-                        return;
-                    }
-                    Some(&_) => todo!(),
-                    None => todo!(),
-                };
+    /// Registers the file at `file` as a project asset, picking its type from the extension.
+    pub fn add_asset(&mut self, file: &Path, load_type: LoadType) -> Result<()> {
+        let project = self.project_dir()?;
+        let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let type_of = match (extension, &load_type) {
+            ("wren", LoadType::Emdedded) => {
+                AssetType::Script
+            }
+            ("png", _) => {
+                AssetType::Texture
+            }
+            ("wav", _) => {
+                AssetType::Sfx
+            }
+            ("mp3", _) => {
+                AssetType::Music
+            }
+            ("ttf", LoadType::Emdedded) => {
+                AssetType::Font
+            }
+            _ => {
+                return Err(EditorError::UnsupportedAsset { 
+                    path: file.to_path_buf(), 
+                    extension: extension.to_string(), 
+                    load_type 
+                });
+            }
+        };
 
-                let path_base = Path::new(&self.current_project);
-                let file_path_str = file.as_path().to_str().unwrap();
-                let path = Path::new(file_path_str);
-                let relative_path_to = pathdiff::diff_paths(path, path_base);
+        // Embedded assets are included from src/, external ones are loaded relative to the project root
+        let base = match load_type {
+            LoadType::External => project.to_string(),
+            LoadType::Emdedded => format!("{}/src", project)
+        };
+        let relative_path_to = pathdiff::diff_paths(file, Path::new(&base))
+            .unwrap_or_else(|| file.to_path_buf());
 
-                let a =
-                Asset {
-                    name: file.file_name().unwrap().to_str().unwrap().to_string(),
-                    path: relative_path_to.expect("Path").as_path().to_str().unwrap().to_string(),
-                    absolute_path: file.as_os_str().to_str().unwrap().to_string(),
-                    type_of: type_of,
-                    load_type: LoadType::External,
-                    load_order: None
-                };
+        let order_to_use = if let AssetType::Script = type_of {
+            Some(self.config.assets.values().filter(|a| matches!(a.type_of, AssetType::Script)).count())
+        } else {
+            None
+        };
 
+        let a =
+        Asset {
+            name: file.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path: relative_path_to.to_string_lossy().to_string(),
+            absolute_path: file.to_string_lossy().to_string(),
+            type_of,
+            load_type: load_type.clone(),
+            load_order: order_to_use,
+            extra: Extra::new()
+        };
 
-                self.config.assets.insert(format!("{}_{:?}", a.path.clone(), LoadType::External), a);
+        let before = self.config.assets.clone();
+        self.config.assets.insert(format!("{}_{:?}", a.path.clone(), load_type), a);
+        self.record_assets(before);
 
-                self.write_config();
+        self.write_config()
+    }
+
+    /// Pushes an undo step for a change to `config.assets`, given the assets from before it.
+    pub fn record_assets(&mut self, before: HashMap<String, Asset>) {
+        self.history.push(Edit::Assets { before, after: self.config.assets.clone() });
+    }
+
+    /// Writes `tiles` into the current layer, `None` erasing, as part of the current stroke.
+    /// Every tile tool paints through here. Returns the positions that changed.
+    pub fn set_tiles(&mut self, tiles: Vec<TileWrite>) -> Vec<(i32, i32)> {
+        self.set_layer_tiles(self.current_layer, tiles)
+    }
+
+    /// `set_tiles` on any layer. Locked layers are left alone.
+    pub fn set_layer_tiles(&mut self, index: usize, tiles: Vec<TileWrite>) -> Vec<(i32, i32)> {
+        let Some(layer) = self.current_scene.as_mut().and_then(|s| s.layers.get_mut(index)).filter(|l| !l.locked) else {
+            return vec!();
+        };
+
+        let mut positions = vec!();
+        for (position, tile) in tiles {
+            if layer.tiles.get(&position) == tile.as_ref() {
+                continue;
             }
+
+            let before = match tile.clone() {
+                Some(tile) => layer.tiles.insert(position, tile),
+                None => layer.tiles.remove(&position)
+            };
+            self.history.record_tile(index, position, before, tile);
+            positions.push(position);
         }
+
+        positions
     }
 
-    pub fn add_embedded_asset(&mut self) {
-        if let Some(files) = FileDialog::new()
-        .set_directory(format!("{}", self.current_project))
-        .add_filter("Type", &["png", "wav", "mp3", "wren", "ttf"])
-        .pick_files() {
-            for file in &files {
-                let type_of = match file.extension().unwrap().to_str() {
-                    Some("wren") => {
-                        AssetType::Script
-                    }
-                    Some("png") => {
-                        AssetType::Texture
-                    }
-                    Some("wav") => {
-                        AssetType::Sfx
-                    }
-                    Some("mp3") => {
-                        AssetType::Music
-                    }
-                    Some("ttf") => {
-                        AssetType::Font
-                    }
-                    Some(&_) => todo!(),
-                    None => todo!(),
-                };
-
-                let script_base = format!("{}/src", self.current_project);
-                //let path_base = Path::new(&self.current_project);
-                let path_base_from_src = Path::new(&script_base);
-                let file_path_str = file.as_path().to_str().unwrap();
-                let path = Path::new(file_path_str);
-                let relative_path_to = pathdiff::diff_paths(path, path_base_from_src);
-
-                let mut order = -1;
-                if let AssetType::Script = type_of {
-                    order = 0;
-                    for i in &self.config.assets {
-                        if let AssetType::Script = i.1.type_of {
-                            order += 1;
-                        } 
-                    }
+    /// Paints `stamp` with its top left at `cell`, its first layer on the current layer, or
+    /// erases the cells it covers if not `fill`. Layers past the top of the scene and tiles of
+    /// sheets the scene doesn't have are left out. Returns the positions that changed on each layer.
+    pub fn paint_stamp(&mut self, stamp: &Stamp, cell: (i32, i32), fill: bool) -> Vec<(usize, Vec<(i32, i32)>)> {
+        let Some(scene) = self.current_scene.as_ref() else {
+            return vec!();
+        };
+
+        let layers = stamp.tiles(cell, fill).into_iter()
+            .enumerate()
+            .map(|(i, tiles)| (self.current_layer + i, tiles))
+            .filter(|(index, _)| *index < scene.layers.len())
+            .map(|(index, tiles)| {
+                let tiles = tiles.into_iter()
+                    .filter(|(_, tile)| match tile {
+                        Some(t) => scene.tile_sheets.iter().any(|s| s.path == t.sheet),
+                        None => true
+                    })
+                    .collect::<Vec<TileWrite>>();
+                (index, tiles)
+            })
+            .collect::<Vec<(usize, Vec<TileWrite>)>>();
+
+        layers.into_iter()
+            .map(|(index, tiles)| (index, self.set_layer_tiles(index, tiles)))
+            .collect()
+    }
+
+    /// Adds `stamp` to the project's brush library.
+    pub fn save_brush(&mut self, stamp: Stamp) -> Result<()> {
+        self.project_dir()?;
+        self.config.brushes.push(stamp);
+        self.write_config()
+    }
+
+    pub fn remove_brush(&mut self, index: usize) -> Result<()> {
+        if index < self.config.brushes.len() {
+            self.config.brushes.remove(index);
+        }
+        self.write_config()
+    }
+
+    /// Deletes layer `index` of the current scene and returns it. The last layer is kept, the
+    /// tools always need one to paint on.
+    pub fn remove_layer(&mut self, index: usize) -> Option<Layer> {
+        let scene = self.current_scene.as_mut().filter(|s| index < s.layers.len() && s.layers.len() > 1)?;
+        let layer = scene.layers.remove(index);
+        self.history.push(Edit::RemoveLayer { index, layer: layer.clone() });
+        if self.current_layer > index {
+            self.current_layer -= 1;
+        }
+        self.clamp_current_layer();
+        Some(layer)
+    }
+
+    /// Inserts a copy of layer `index` above it and makes the copy the current layer. Returns
+    /// the copy's index.
+    pub fn duplicate_layer(&mut self, index: usize) -> Option<usize> {
+        let scene = self.current_scene.as_mut()?;
+        let mut layer = scene.layers.get(index)?.clone();
+        layer.name = format!("{} copy", layer.label(index));
+        self.history.push(Edit::AddLayer { index: index + 1, layer: layer.clone() });
+        scene.layers.insert(index + 1, layer);
+        self.current_layer = index + 1;
+        Some(index + 1)
+    }
+
+    /// Moves layer `from` to `to`, shifting the layers between them. The current layer moves
+    /// with its tiles.
+    pub fn move_layer(&mut self, from: usize, to: usize) -> bool {
+        let Some(scene) = self.current_scene.as_mut().filter(|s| from < s.layers.len() && to < s.layers.len() && from != to) else {
+            return false;
+        };
+        let layer = scene.layers.remove(from);
+        scene.layers.insert(to, layer);
+        self.history.push(Edit::MoveLayer { from, to });
+        self.current_layer = moved_index(self.current_layer, from, to);
+        true
+    }
+
+    /// Paints layer `index` onto the layer below it, its tiles covering the ones there, and
+    /// deletes it. On a different grid or offset a tile goes to the cell nearest where it was
+    /// drawn. Returns the positions that changed on the layer below, `None` if there is no layer
+    /// below or it is locked. Fails if the layers differ in visibility, opacity, tint or parallax,
+    /// since the layer below can't take those on for just some of its tiles.
+    pub fn merge_layer_down(&mut self, index: usize) -> Result<Option<Vec<(i32, i32)>>> {
+        let Some(scene) = self.current_scene.as_mut().filter(|s| index > 0 && index < s.layers.len()) else {
+            return Ok(None);
+        };
+        let (upper, lower) = (&scene.layers[index], &scene.layers[index - 1]);
+        if lower.locked {
+            return Ok(None);
+        }
+
+        let differ = [
+            ("visibility", upper.visible != lower.visible),
+            ("opacity", upper.opacity != lower.opacity),
+            ("tint", upper.tint != lower.tint),
+            ("parallax", upper.parallax != lower.parallax)
+        ];
+        let differ = differ.iter().filter(|(_, d)| *d).map(|(what, _)| *what).collect::<Vec<&str>>();
+        if !differ.is_empty() {
+            return Err(EditorError::MergeLayers {
+                index,
+                reason: format!("its {} differ from the layer below", differ.join(", "))
+            });
+        }
+
+        let upper = scene.layers.remove(index);
+        let lower = &mut scene.layers[index - 1];
+        // On another grid several tiles can land in one cell, the first change there has the
+        // tile to undo back to
+        let mut changes: IndexMap<(i32, i32), TileChange> = IndexMap::new();
+        let same_cells = upper.grid_size == lower.grid_size && upper.offset == lower.offset;
+        for (cell, tile) in &upper.tiles {
+            let position = match same_cells {
+                true => *cell,
+                false => {
+                    let [x, y] = upper.cell_position(scene.orientation, *cell);
+                    let point = [x + upper.offset[0] - lower.offset[0], y + upper.offset[1] - lower.offset[1]];
+                    lower.cell_at(scene.orientation, point)
                 }
-                let order_to_use = if order == -1 {
-                    None
-                } else {
-                    Some(order as usize)
-                };
-
-                let a =
-                Asset {
-                    name: file.file_name().unwrap().to_str().unwrap().to_string(),
-                    path: relative_path_to.expect("Path").as_path().to_str().unwrap().to_string(),
-                    absolute_path: file.as_os_str().to_str().unwrap().to_string(),
-                    type_of: type_of,
-                    load_type: LoadType::Emdedded,
-                    load_order: order_to_use
-                };
-
-                self.config.assets.insert(format!("{}_{:?}", a.path.clone(), LoadType::Emdedded), a);
-
-                self.write_config();
+            };
+            let before = lower.tiles.insert(position, tile.clone());
+            changes.entry(position)
+                .or_insert(TileChange { position, before, after: None })
+                .after = Some(tile.clone());
+        }
+        let changes = changes.into_values()
+            .filter(|c| c.before != c.after)
+            .collect::<Vec<TileChange>>();
+
+        let positions = changes.iter().map(|c| c.position).collect();
+        self.history.push(Edit::Group(vec!(
+            Edit::Tiles { layer: index - 1, changes },
+            Edit::RemoveLayer { index, layer: upper }
+        )));
+        if self.current_layer >= index {
+            self.current_layer -= 1;
+        }
+        Ok(Some(positions))
+    }
+
+    /// Appends a copy of layer `index` to the scene at `file`, along with any tile sheets its
+    /// tiles use that the scene doesn't have. Not undoable, the other scene is written straight
+    /// away.
+    pub fn copy_layer_to_scene(&self, index: usize, file: &Path) -> Result<()> {
+        let scene = self.current_scene.as_ref().ok_or(EditorError::NoScene)?;
+        let Some(layer) = scene.layers.get(index) else {
+            return Ok(());
+        };
+
+        let mut target = Scene::load(file)?;
+        let used = layer.tiles.values().map(|t| &t.sheet).chain(std::iter::once(&layer.tile_sheet)).collect::<HashSet<&String>>();
+        for sheet in scene.tile_sheets.iter().filter(|s| used.contains(&s.path)) {
+            if !target.tile_sheets.iter().any(|s| s.path == sheet.path) {
+                target.tile_sheets.push(sheet.clone());
             }
         }
+
+        let mut layer = layer.clone();
+        layer.name = layer.label(index);
+        layer.current_tile_item = target.tile_sheets.iter().position(|s| s.path == layer.tile_sheet).unwrap_or(0) as i32;
+        target.layers.push(layer);
+        target.write(file)
+    }
+
+    /// Adds `prefab` to the project, unless one already has its name.
+    pub fn add_prefab(&mut self, prefab: Prefab) -> Result<()> {
+        self.project_dir()?;
+        if prefab::find(&self.config.prefabs, &prefab.name).is_none() {
+            self.config.prefabs.push(prefab);
+        }
+        self.write_config()
+    }
+
+    /// Removes a prefab. Its instances keep their overrides and are reported by `check`.
+    pub fn remove_prefab(&mut self, index: usize) -> Result<()> {
+        if index < self.config.prefabs.len() {
+            self.config.prefabs.remove(index);
+        }
+        self.write_config()
+    }
+
+    /// Fills (or erases) terrain `terrain` of tile sheet `sheet` at `position` on the current layer,
+    /// fixing up the tiles around it. Returns the positions that changed.
+    pub fn paint_terrain(&mut self, sheet: usize, terrain: usize, position: (i32, i32), fill: bool) -> Vec<(i32, i32)> {
+        let tiles = match self.current_scene.as_ref() {
+            Some(scene) => {
+                let tile_sheet = scene.tile_sheets.get(sheet);
+                match (tile_sheet, tile_sheet.and_then(|s| s.terrains.get(terrain)), scene.layers.get(self.current_layer)) {
+                    (Some(tile_sheet), Some(terrain), Some(layer)) => terrain::paint(layer, tile_sheet, terrain, position, fill),
+                    _ => vec!()
+                }
+            }
+            None => vec!()
+        };
+
+        self.set_tiles(tiles)
+    }
+
+    /// Checks the project config, if a project is open, and the current scene.
+    pub fn check(&self) -> Vec<Problem> {
+        let mut problems = vec!();
+        if let Ok(project) = self.project_dir() {
+            problems.extend(validate::check_config(&self.config, Path::new(project)));
+        }
+        if let Some(scene) = self.current_scene.as_ref() {
+            problems.extend(validate::check_scene(scene));
+            problems.extend(validate::check_instances(scene, &self.config.prefabs));
+        }
+
+        problems
+    }
+
+    /// Reverts the most recent edit and returns it. Nothing is written to disk.
+    pub fn undo(&mut self) -> Option<&Edit> {
+        self.history.undo(self.current_scene.as_mut(), &mut self.config)?;
+        if let Some(Edit::MoveLayer { from, to }) = self.history.last_redo() {
+            self.current_layer = moved_index(self.current_layer, *to, *from);
+        }
+        self.clamp_current_layer();
+        self.history.last_redo()
     }
-}
 
-pub fn aabb(a_pos: Vec2, a_size: Vec2, b_pos: Vec2, b_size: Vec2) -> bool {
-    //The sides of the rectangles
-    let left_a = a_pos.x;
-    let left_b = b_pos.x;
-    let right_a = left_a + a_size.x;
-    let right_b = left_b + b_size.x;
-    let top_a = a_pos.y;
-    let top_b = b_pos.y;
-    let bottom_a = top_a - a_size.y;
-    let bottom_b = top_b - b_size.y;
+    /// Re-applies the most recently undone edit and returns it. Nothing is written to disk.
+    pub fn redo(&mut self) -> Option<&Edit> {
+        self.history.redo(self.current_scene.as_mut(), &mut self.config)?;
+        if let Some(Edit::MoveLayer { from, to }) = self.history.last_undo() {
+            self.current_layer = moved_index(self.current_layer, *from, *to);
+        }
+        self.clamp_current_layer();
+        self.history.last_undo()
+    }
 
-    //If any of the sides from A are outside of B
-    if bottom_a <= top_b && top_a >= bottom_b && right_a >= left_b && left_a <= right_b {
-        return true;
+    fn clamp_current_layer(&mut self) {
+        if let Some(scene) = self.current_scene.as_ref() {
+            if self.current_layer >= scene.layers.len() {
+                self.current_layer = scene.layers.len().saturating_sub(1);
+            }
+        }
     }
+}
 
-    false
-}
\ No newline at end of file
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
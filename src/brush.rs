@@ -0,0 +1,133 @@
+//! Stamps: blocks of tiles, over one or more layers, painted in one go.
+//!
+//! Cells are relative to the stamp's top left, `(column, row)` with y up, so every cell but the
+//! first has a row of 0 or below.
+
+use serde::{Deserialize, Serialize};
+
+use crate::application::{Layer, Tile, TileSheet, TileWrite};
+use crate::schema::Extra;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct StampTile {
+    pub cell: (i32, i32),
+    pub sheet: String,
+    pub sheet_id: (u32, u32),
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flip_x: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flip_y: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rotate: bool
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stamp {
+    pub name: String,
+    /// Size of a cell in pixels on the layer the stamp came from. Stamps are painted cell for
+    /// cell whatever the grid of the layer they go on
+    pub tile_size: (u32, u32),
+    /// The first is painted on the current layer, the rest on the layers above it
+    pub layers: Vec<Vec<StampTile>>,
+    #[serde(flatten)]
+    pub extra: Extra
+}
+
+impl Stamp {
+    /// A stamp of the cells of `sheet` between corners `a` and `b`, as laid out in the image.
+    pub fn from_sheet(sheet: &TileSheet, a: (u32, u32), b: (u32, u32)) -> Self {
+        let min = (a.0.min(b.0), a.1.min(b.1));
+        let max = (a.0.max(b.0), a.1.max(b.1));
+
+        let mut tiles = vec!();
+        for row in min.1..=max.1 {
+            for column in min.0..=max.0 {
+                tiles.push(StampTile {
+                    cell: ((column - min.0) as i32, -((row - min.1) as i32)),
+                    sheet: sheet.path.clone(),
+                    sheet_id: (column, row),
+                    flip_x: false,
+                    flip_y: false,
+                    rotate: false
+                });
+            }
+        }
+
+        Self {
+            name: format!("{} {}x{}", sheet.filename, max.0 - min.0 + 1, max.1 - min.1 + 1),
+            tile_size: sheet.tile_size,
+            layers: vec!(tiles),
+            extra: Extra::new()
+        }
+    }
+
+    /// A stamp of what `layers` hold between cells `a` and `b`.
+    pub fn capture(layers: &[Layer], a: (i32, i32), b: (i32, i32)) -> Self {
+        let min = (a.0.min(b.0), a.1.min(b.1));
+        let max = (a.0.max(b.0), a.1.max(b.1));
+
+        let mut stamp_layers = vec!();
+        for layer in layers {
+            let mut tiles = vec!();
+            for row in (min.1..=max.1).rev() {
+                for column in min.0..=max.0 {
+                    if let Some(tile) = layer.tiles.get(&(column, row)) {
+                        tiles.push(StampTile {
+                            cell: (column - min.0, row - max.1),
+                            sheet: tile.sheet.clone(),
+                            sheet_id: tile.sheet_id,
+                            flip_x: tile.flip_x,
+                            flip_y: tile.flip_y,
+                            rotate: tile.rotate
+                        });
+                    }
+                }
+            }
+            stamp_layers.push(tiles);
+        }
+
+        Self {
+            name: format!("Stamp {}x{}", max.0 - min.0 + 1, max.1 - min.1 + 1),
+            tile_size: layers.first().map_or((16, 16), |l| l.grid_size),
+            layers: stamp_layers,
+            extra: Extra::new()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(|l| l.is_empty())
+    }
+
+    /// Tile sheets the stamp uses that aren't in `tile_sheets`.
+    pub fn missing_sheets(&self, tile_sheets: &[TileSheet]) -> Vec<String> {
+        let mut missing = vec!();
+        for tile in self.layers.iter().flatten() {
+            if !tile_sheets.iter().any(|s| s.path == tile.sheet) && !missing.contains(&tile.sheet) {
+                missing.push(tile.sheet.clone());
+            }
+        }
+
+        missing
+    }
+
+    /// The tiles to write for each of the stamp's layers with its top left at `cell`, or `None`
+    /// over the same cells if `fill` is false.
+    pub fn tiles(&self, cell: (i32, i32), fill: bool) -> Vec<Vec<TileWrite>> {
+        self.layers.iter()
+            .map(|tiles| tiles.iter()
+                .map(|t| {
+                    let key = (cell.0 + t.cell.0, cell.1 + t.cell.1);
+                    let tile = fill.then(|| Tile {
+                        sheet: t.sheet.clone(),
+                        sheet_id: t.sheet_id,
+                        flip_x: t.flip_x,
+                        flip_y: t.flip_y,
+                        rotate: t.rotate,
+                        extra: Extra::new()
+                    });
+                    (key, tile)
+                })
+                .collect())
+            .collect()
+    }
+}
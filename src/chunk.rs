@@ -0,0 +1,257 @@
+//! Chunked tile storage for layers.
+//!
+//! Tiles are kept in square chunks of `CHUNK_SIZE` keyed by chunk coordinate, so the editor can
+//! find the tiles in view without walking the whole layer. Each chunk keeps the JSON it was last
+//! serialized to, so saving only re-serializes the chunks that changed since the last save. The
+//! scene file itself is still written out whole every time.
+
+use std::{cell::OnceCell, collections::{hash_map, HashMap}, fmt, ops::Index};
+use serde::{de::{Error as _, MapAccess, Visitor}, ser::{Error as _, SerializeSeq}, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+
+use crate::application::Tile;
+use crate::schema::{Extra, FieldName};
+
+/// Width and height of a chunk, in cells.
+pub const CHUNK_SIZE: i32 = 32;
+
+/// The chunk the tile at cell `position` is in.
+pub fn chunk_of(position: (i32, i32)) -> (i32, i32) {
+    (position.0.div_euclid(CHUNK_SIZE), position.1.div_euclid(CHUNK_SIZE))
+}
+
+/// Up to `CHUNK_SIZE` by `CHUNK_SIZE` cells worth of tiles.
+#[derive(Clone, Default)]
+pub struct Chunk {
+    tiles: HashMap<(i32, i32), Tile>,
+    /// Fields of the chunk the editor doesn't know about, kept while it has tiles
+    extra: Extra,
+    /// The chunk as it was last serialized, cleared by any change to `tiles`
+    saved: OnceCell<Box<RawValue>>
+}
+
+/// How a chunk is written: its coordinate, for people reading the file, and its tiles.
+#[derive(Serialize)]
+struct ChunkData<'a> {
+    chunk: (i32, i32),
+    tiles: Vec<(&'a (i32, i32), &'a Tile)>,
+    #[serde(flatten)]
+    extra: &'a Extra
+}
+
+/// A chunk as read. Read by hand, a flattened `extra` would buffer all of its tiles first.
+struct OwnedChunkData {
+    chunk: Option<(i32, i32)>,
+    tiles: Vec<((i32, i32), Tile)>,
+    extra: Extra
+}
+
+impl<'de> Deserialize<'de> for OwnedChunkData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChunkVisitor;
+
+        impl<'de> Visitor<'de> for ChunkVisitor {
+            type Value = OwnedChunkData;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a chunk")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OwnedChunkData, A::Error> {
+                let mut chunk = OwnedChunkData { chunk: None, tiles: Vec::new(), extra: Extra::new() };
+                while let Some(FieldName(name)) = map.next_key()? {
+                    match name.as_ref() {
+                        "chunk" => chunk.chunk = Some(map.next_value()?),
+                        "tiles" => chunk.tiles = map.next_value()?,
+                        _ => {
+                            chunk.extra.insert(name.into_owned(), map.next_value()?);
+                        }
+                    }
+                }
+                Ok(chunk)
+            }
+        }
+
+        deserializer.deserialize_map(ChunkVisitor)
+    }
+}
+
+pub type Iter<'a> = std::iter::FlatMap<
+    hash_map::Values<'a, (i32, i32), Chunk>,
+    hash_map::Iter<'a, (i32, i32), Tile>,
+    fn(&'a Chunk) -> hash_map::Iter<'a, (i32, i32), Tile>
+>;
+
+/// The tiles of a layer by cell, stored in chunks. Reads like a `HashMap<(i32, i32), Tile>`.
+#[derive(Clone, Default)]
+pub struct ChunkedTiles {
+    chunks: HashMap<(i32, i32), Chunk>,
+    len: usize
+}
+
+impl ChunkedTiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, position: &(i32, i32)) -> Option<&Tile> {
+        self.chunks.get(&chunk_of(*position)).and_then(|c| c.tiles.get(position))
+    }
+
+    pub fn contains_key(&self, position: &(i32, i32)) -> bool {
+        self.get(position).is_some()
+    }
+
+    /// Puts `tile` at `position`, returning the tile it replaced.
+    pub fn insert(&mut self, position: (i32, i32), tile: Tile) -> Option<Tile> {
+        let chunk = self.chunks.entry(chunk_of(position)).or_default();
+        chunk.saved.take();
+        let replaced = chunk.tiles.insert(position, tile);
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        replaced
+    }
+
+    pub fn remove(&mut self, position: &(i32, i32)) -> Option<Tile> {
+        let key = chunk_of(*position);
+        let chunk = self.chunks.get_mut(&key)?;
+        let removed = chunk.tiles.remove(position)?;
+        chunk.saved.take();
+        if chunk.tiles.is_empty() {
+            self.chunks.remove(&key);
+        }
+        self.len -= 1;
+        Some(removed)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.chunks.values().flat_map(|c| c.tiles.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &(i32, i32)> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Tile> {
+        self.iter().map(|(_, t)| t)
+    }
+
+    /// Coordinates of the chunks that have tiles.
+    pub fn chunks(&self) -> impl Iterator<Item = &(i32, i32)> {
+        self.chunks.keys()
+    }
+
+    /// The tiles of chunk `chunk`, if it has any.
+    pub fn chunk(&self, chunk: &(i32, i32)) -> Option<&HashMap<(i32, i32), Tile>> {
+        self.chunks.get(chunk).map(|c| &c.tiles)
+    }
+
+    /// Coordinates of the chunks with tiles that overlap the cells from `min` to `max`.
+    pub fn chunks_in(&self, min: (i32, i32), max: (i32, i32)) -> Vec<(i32, i32)> {
+        let (low, high) = (chunk_of(min), chunk_of(max));
+        let area = (high.0 - low.0 + 1) as i64 * (high.1 - low.1 + 1) as i64;
+
+        // Zoomed far out there can be more chunk coordinates in view than chunks
+        if area > self.chunks.len() as i64 {
+            return self.chunks.keys()
+                .filter(|c| (low.0..=high.0).contains(&c.0) && (low.1..=high.1).contains(&c.1))
+                .copied()
+                .collect();
+        }
+        (low.0..=high.0)
+            .flat_map(|x| (low.1..=high.1).map(move |y| (x, y)))
+            .filter(|c| self.chunks.contains_key(c))
+            .collect()
+    }
+
+    /// Chunks changed since they were last serialized.
+    pub fn dirty_chunks(&self) -> usize {
+        self.chunks.values().filter(|c| c.saved.get().is_none()).count()
+    }
+}
+
+impl Index<&(i32, i32)> for ChunkedTiles {
+    type Output = Tile;
+
+    fn index(&self, position: &(i32, i32)) -> &Tile {
+        self.get(position).expect("no tile at position")
+    }
+}
+
+impl<'a> IntoIterator for &'a ChunkedTiles {
+    type Item = (&'a (i32, i32), &'a Tile);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl FromIterator<((i32, i32), Tile)> for ChunkedTiles {
+    fn from_iter<I: IntoIterator<Item = ((i32, i32), Tile)>>(iter: I) -> Self {
+        let mut tiles = Self::new();
+        for (position, tile) in iter {
+            tiles.insert(position, tile);
+        }
+        tiles
+    }
+}
+
+impl Serialize for ChunkedTiles {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut keys = self.chunks.keys().collect::<Vec<&(i32, i32)>>();
+        keys.sort();
+
+        let mut seq = serializer.serialize_seq(Some(keys.len()))?;
+        for key in keys {
+            let chunk = &self.chunks[key];
+            let saved = match chunk.saved.get() {
+                Some(saved) => saved,
+                None => {
+                    let mut tiles = chunk.tiles.iter().collect::<Vec<(&(i32, i32), &Tile)>>();
+                    tiles.sort_by_key(|(k, _)| **k);
+                    let raw = serde_json::value::to_raw_value(&ChunkData { chunk: *key, tiles, extra: &chunk.extra }).map_err(S::Error::custom)?;
+                    chunk.saved.get_or_init(|| raw)
+                }
+            };
+            seq.serialize_element(saved)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkedTiles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let chunks = Vec::<OwnedChunkData>::deserialize(deserializer)?;
+
+        // Tiles are filed by their own key, whatever chunk the file put them in
+        let mut tiles = Self::new();
+        let mut extras = vec!();
+        for chunk in chunks {
+            let first = chunk.tiles.first().map(|(position, _)| chunk_of(*position));
+            for (position, tile) in chunk.tiles {
+                if tiles.insert(position, tile).is_some() {
+                    return Err(D::Error::custom(format!("two tiles at {:?}", position)));
+                }
+            }
+            if let Some(key) = chunk.chunk.or(first).filter(|_| !chunk.extra.is_empty()) {
+                extras.push((key, chunk.extra));
+            }
+        }
+        for (key, extra) in extras {
+            if let Some(chunk) = tiles.chunks.get_mut(&key) {
+                chunk.extra.extend(extra);
+            }
+        }
+        Ok(tiles)
+    }
+}
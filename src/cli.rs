@@ -0,0 +1,228 @@
+use std::{fs, io::{self, Write}, path::PathBuf, process::ExitCode};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use lilah_editor::{ldtk, tiled, validate, App, Coordinates, Scene};
+use lilah_editor::tiled::TiledFormat;
+use lilah_editor::error::{Result, WithPath};
+
+/// Headless entry points, so projects and scenes can be scripted without a display.
+#[derive(Parser)]
+#[command(name = "lilah_editor", version, about = "Lilah tile map editor. Opens the editor window when no command is given.")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Create a new lilah project
+    New {
+        dir: PathBuf
+    },
+    /// Load a project and print a summary of its config
+    Open {
+        dir: PathBuf
+    },
+    /// Regenerate a project's src/main.rs from its config
+    Wrangle {
+        /// Scene whose tile sheet properties `tile_property` answers from
+        #[arg(short, long)]
+        scene: Option<PathBuf>,
+        dir: PathBuf
+    },
+    /// Check a project's assets and any number of scenes for problems
+    Validate {
+        /// Project whose config.json assets are checked
+        #[arg(short, long)]
+        project: Option<PathBuf>,
+        scenes: Vec<PathBuf>
+    },
+    /// Write a scene out in another format
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// How json exports give tile positions. Tiled maps are always on a grid
+        #[arg(long, value_enum, default_value_t = ExportCoordinates::Grid)]
+        coordinates: ExportCoordinates,
+        /// Where to write the export, stdout when omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        scene: PathBuf
+    },
+    /// Convert a Tiled map (.tmx or .tmj) into a scene, or an LDtk project (.ldtk) into one scene per level
+    Import {
+        map: PathBuf,
+        /// The scene file to write, or the directory to write an LDtk project's scenes into
+        output: PathBuf
+    },
+    /// Regenerate a project's src/main.rs and cargo run it
+    Run {
+        /// Scene whose tile sheet properties `tile_property` answers from
+        #[arg(short, long)]
+        scene: Option<PathBuf>,
+        dir: PathBuf
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Pretty printed scene json
+    Json,
+    /// Tiled json map, with a .tsx per tile sheet next to it
+    Tmj,
+    /// Tiled xml map, with a .tsx per tile sheet next to it
+    Tmx
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportCoordinates {
+    /// Cells of each layer's grid, as the scene keeps them
+    Grid,
+    /// Tile middles in scene pixels
+    Pixel
+}
+
+pub fn run(command: Command) -> ExitCode {
+    match execute(command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn execute(command: Command) -> Result<ExitCode> {
+    let mut app = App::new();
+
+    match command {
+        Command::New { dir } => {
+            app.create_project(&dir)?;
+            println!("created project {}", dir.display());
+        }
+        Command::Open { dir } => {
+            app.load_project(&dir)?;
+            println!("project: {}", app.current_project);
+            println!("window size: {} x {}", app.config.window_size.0, app.config.window_size.1);
+
+            let mut assets = app.config.assets.values().collect::<Vec<_>>();
+            assets.sort_by(|a, b| a.path.cmp(&b.path));
+            println!("assets: {}", assets.len());
+            for asset in assets {
+                println!("  [{:?}|{:?}] {}", asset.type_of, asset.load_type, asset.path);
+            }
+        }
+        Command::Wrangle { scene, dir } => {
+            app.load_project(&dir)?;
+            if let Some(scene) = scene {
+                app.load_scene(&scene)?;
+            }
+            app.wrangle_main()?;
+            println!("wrote {}/src/main.rs", app.current_project);
+        }
+        Command::Validate { project, scenes } => {
+            let mut found = false;
+            if let Some(dir) = project {
+                app.load_project(&dir)?;
+                let config = dir.join("config.json");
+                for problem in validate::check_config(&app.config, &dir) {
+                    println!("{}: {}", config.display(), problem);
+                    found = true;
+                }
+            }
+
+            for scene in scenes {
+                let loaded = Scene::load(&scene)?;
+                let mut problems = validate::check_scene(&loaded);
+                // Prefabs live in the project, so instances can only be checked against one
+                if !app.current_project.is_empty() {
+                    problems.extend(validate::check_instances(&loaded, &app.config.prefabs));
+                }
+                for problem in &problems {
+                    println!("{}: {}", scene.display(), problem);
+                }
+
+                if problems.is_empty() {
+                    println!("{}: ok", scene.display());
+                }
+                found |= !problems.is_empty();
+            }
+
+            if found {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Export { format, coordinates, output, scene: path } => {
+            let scene = Scene::load(&path)?;
+            let tiled_format = match format {
+                ExportFormat::Json => None,
+                ExportFormat::Tmj => Some(TiledFormat::Tmj),
+                ExportFormat::Tmx => Some(TiledFormat::Tmx)
+            };
+
+            if let Some(tiled_format) = tiled_format {
+                if coordinates == ExportCoordinates::Pixel {
+                    eprintln!("warning: Tiled maps are always on a grid, --coordinates pixel only applies to json");
+                }
+                let Some(output) = output else {
+                    eprintln!("error: Tiled exports write several files and need --output");
+                    return Ok(ExitCode::FAILURE);
+                };
+
+                for warning in tiled::export(&scene, &output, tiled_format)? {
+                    eprintln!("warning: {}: {}", path.display(), warning);
+                }
+                println!("wrote {}", output.display());
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let data = scene.export_json(match coordinates {
+                ExportCoordinates::Grid => Coordinates::Grid,
+                ExportCoordinates::Pixel => Coordinates::Pixel
+            })?;
+
+            match output {
+                Some(output) => fs::write(&output, data).with_path(&output)?,
+                None => io::stdout().write_all(data.as_bytes()).with_path("<stdout>")?
+            }
+        }
+        Command::Import { map, output } if map.extension().is_some_and(|e| e == "ldtk") => {
+            let import = ldtk::import(&map)?;
+            for warning in &import.warnings {
+                eprintln!("warning: {}: {}", map.display(), warning);
+            }
+
+            fs::create_dir_all(&output).with_path(&output)?;
+            for scene in &import.scenes {
+                let path = output.join(&scene.path);
+                scene.write(&path)?;
+                println!("wrote {}", path.display());
+            }
+        }
+        Command::Import { map, output } => {
+            let mut import = tiled::import(&map)?;
+            for warning in &import.warnings {
+                eprintln!("warning: {}: {}", map.display(), warning);
+            }
+
+            import.scene.name = output.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            import.scene.path = output.file_name().unwrap_or_default().to_string_lossy().to_string();
+            import.scene.write(&output)?;
+            println!("wrote {}", output.display());
+        }
+        Command::Run { scene, dir } => {
+            app.load_project(&dir)?;
+            if let Some(scene) = scene {
+                app.load_scene(&scene)?;
+            }
+            let status = app.run_project()?.wait().with_path(&dir)?;
+            // Statuses that don't fit an exit code, and runs killed by a signal, still failed
+            return Ok(match status.code() {
+                Some(code) => u8::try_from(code).map_or(ExitCode::FAILURE, ExitCode::from),
+                None => ExitCode::FAILURE
+            });
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,337 @@
+//! Collision shapes for the cells of a tile sheet, tracing them from a texture's alpha, and the
+//! merged geometry written next to a scene for its collision layers.
+//!
+//! Shapes are in pixels of the cell as it sits in the image: the origin is its top left and y
+//! points down.
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::application::{Scene, Tile};
+
+/// Merged geometry of one collision layer, in scene pixels with y up.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MergedCollision {
+    pub layer: usize,
+    /// `[x, y, width, height]`, `x, y` being the bottom left corner
+    pub rects: Vec<[f32; 4]>,
+    /// Closed outlines of the same area, anticlockwise around solid ground and clockwise
+    /// around holes. Only filled in when the scene asks for them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outlines: Vec<Vec<[f32; 2]>>,
+    /// Cells of tiles left out of the merge, because their cell has collision shapes other than
+    /// a box, their sheet's tile size isn't the layer's grid or the scene isn't orthogonal. Their
+    /// shapes are on the tile sheet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles: Vec<(i32, i32)>,
+    /// The layer's offset. `rects` and `outlines` are already moved by it, the tiles at `tiles`
+    /// have to be: they sit at the middle of their cell plus `offset`.
+    #[serde(default)]
+    pub offset: [f32; 2]
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum CollisionShape {
+    /// The whole cell
+    Box,
+    /// `[x, y, width, height]`
+    Rect([f32; 4]),
+    /// Solid below a line from `left` pixels up the left edge to `right` pixels up the right edge
+    Slope { left: f32, right: f32 },
+    /// A ledge `y` pixels down from the top, only solid from above
+    OneWay { y: f32 },
+    /// A closed outline, clockwise on screen
+    Polygon(Vec<[f32; 2]>)
+}
+
+impl CollisionShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CollisionShape::Box => "Box",
+            CollisionShape::Rect(_) => "Rectangle",
+            CollisionShape::Slope { .. } => "Slope",
+            CollisionShape::OneWay { .. } => "One Way",
+            CollisionShape::Polygon(_) => "Polygon"
+        }
+    }
+
+    /// One of each shape that can be added by hand, covering a cell of `size`.
+    pub fn defaults(size: (u32, u32)) -> [CollisionShape; 4] {
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        [
+            CollisionShape::Box,
+            CollisionShape::Rect([0.0, 0.0, w, h]),
+            CollisionShape::Slope { left: 0.0, right: h },
+            CollisionShape::OneWay { y: 0.0 }
+        ]
+    }
+
+    /// The shape's edges in a cell of `size`, as pairs of points.
+    pub fn edges(&self, size: (u32, u32)) -> Vec<([f32; 2], [f32; 2])> {
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        let points = match self {
+            CollisionShape::Box => vec!([0.0, 0.0], [w, 0.0], [w, h], [0.0, h]),
+            CollisionShape::Rect([x, y, rw, rh]) => vec!([*x, *y], [x + rw, *y], [x + rw, y + rh], [*x, y + rh]),
+            CollisionShape::Slope { left, right } => vec!([0.0, h - left], [w, h - right], [w, h], [0.0, h]),
+            CollisionShape::OneWay { y } => return vec!(([0.0, *y], [w, *y])),
+            CollisionShape::Polygon(points) => points.clone()
+        };
+
+        (0..points.len())
+            .map(|i| (points[i], points[(i + 1) % points.len()]))
+            .collect()
+    }
+}
+
+/// Where `point` of a cell of `size` ends up once `tile`'s rotation and flips are applied, in
+/// the same order `orient_uv` applies them. Rotated cells swap their width and height.
+pub fn orient(point: [f32; 2], size: (u32, u32), tile: &Tile) -> [f32; 2] {
+    let (mut w, mut h) = (size.0 as f32, size.1 as f32);
+    let mut p = point;
+
+    if tile.rotate {
+        p = [h - p[1], p[0]];
+        (w, h) = (h, w);
+    }
+    if tile.flip_x {
+        p[0] = w - p[0];
+    }
+    if tile.flip_y {
+        p[1] = h - p[1];
+    }
+
+    p
+}
+
+/// Outlines of the opaque parts of a `width` by `height` image, one alpha byte per pixel,
+/// where pixels above `threshold` count as opaque. Holes are filled in. Outlines are
+/// simplified until no point is more than `tolerance` pixels off the traced edge. A fully
+/// opaque image gives a single `Box`.
+pub fn trace(alpha: &[u8], width: u32, height: u32, threshold: u8, tolerance: f32) -> Vec<CollisionShape> {
+    let (w, h) = (width as i32, height as i32);
+    let solid = |x: i32, y: i32| x >= 0 && y >= 0 && x < w && y < h && alpha[(y * w + x) as usize] > threshold;
+
+    if (0..h).all(|y| (0..w).all(|x| solid(x, y))) {
+        return if w > 0 && h > 0 { vec!(CollisionShape::Box) } else { vec!() };
+    }
+
+    let pixels = (0..h).flat_map(|y| (0..w).map(move |x| (x, y))).filter(|p| solid(p.0, p.1)).collect::<Vec<(i32, i32)>>();
+
+    outline_loops(&pixels, solid).into_iter()
+        .filter(|outline| area(outline) > 0)
+        .map(|outline| simplify(&outline, tolerance))
+        .filter(|points| points.len() >= 3)
+        .map(CollisionShape::Polygon)
+        .collect()
+}
+
+/// The closed outlines around `cells`, as corners of the unit squares they cover. `solid` says
+/// whether a cell is one of them. Outer outlines run clockwise with y down, holes the other way.
+fn outline_loops(cells: &[(i32, i32)], solid: impl Fn(i32, i32) -> bool) -> Vec<Vec<(i32, i32)>> {
+    // Edges with the cells on their right, so outer outlines run clockwise
+    let mut edges: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+    let mut starts = vec!();
+    for &(x, y) in cells {
+        for (open, from, to) in [
+            (!solid(x, y - 1), (x, y), (x + 1, y)),
+            (!solid(x + 1, y), (x + 1, y), (x + 1, y + 1)),
+            (!solid(x, y + 1), (x + 1, y + 1), (x, y + 1)),
+            (!solid(x - 1, y), (x, y + 1), (x, y))
+        ] {
+            if open {
+                edges.entry(from).or_default().push(to);
+                starts.push(from);
+            }
+        }
+    }
+
+    let mut outlines = vec!();
+    for start in starts {
+        let mut outline = vec!(start);
+        let mut direction = (0, 0);
+        let mut at = start;
+        while let Some(next) = take_edge(&mut edges, at, direction) {
+            direction = (next.0 - at.0, next.1 - at.1);
+            at = next;
+            if at == start {
+                break;
+            }
+            outline.push(at);
+        }
+
+        if outline.len() >= 3 {
+            outlines.push(outline);
+        }
+    }
+
+    outlines
+}
+
+/// Removes and returns the edge leaving `at`. Where two leave the same corner, the outlines
+/// touch diagonally, and turning right keeps each one to its own pixels.
+fn take_edge(edges: &mut HashMap<(i32, i32), Vec<(i32, i32)>>, at: (i32, i32), direction: (i32, i32)) -> Option<(i32, i32)> {
+    let out = edges.get_mut(&at)?;
+    let right = (at.0 - direction.1, at.1 + direction.0);
+    let index = out.iter().position(|p| *p == right).unwrap_or(0);
+    let next = (!out.is_empty()).then(|| out.swap_remove(index));
+    if out.is_empty() {
+        edges.remove(&at);
+    }
+    next
+}
+
+/// Twice the signed area of `outline`, positive when clockwise on screen.
+fn area(outline: &[(i32, i32)]) -> i32 {
+    (0..outline.len())
+        .map(|i| {
+            let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum()
+}
+
+/// Ramer-Douglas-Peucker over a closed outline, split at its first point and the point
+/// farthest from it.
+fn simplify(outline: &[(i32, i32)], tolerance: f32) -> Vec<[f32; 2]> {
+    // Points in the middle of a straight run never matter
+    let turns = (0..outline.len())
+        .filter(|&i| {
+            let (a, p, b) = (outline[(i + outline.len() - 1) % outline.len()], outline[i], outline[(i + 1) % outline.len()]);
+            (p.0 - a.0) * (b.1 - p.1) != (p.1 - a.1) * (b.0 - p.0)
+        })
+        .map(|i| outline[i]);
+    let points = turns.map(|p| [p.0 as f32, p.1 as f32]).collect::<Vec<[f32; 2]>>();
+    if points.len() < 3 {
+        return points;
+    }
+    let distance = |a: [f32; 2], b: [f32; 2]| ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt();
+    let far = (1..points.len())
+        .max_by(|a, b| distance(points[0], points[*a]).total_cmp(&distance(points[0], points[*b])))
+        .unwrap_or(0);
+
+    let mut keep = vec!(false; points.len());
+    keep[0] = true;
+    keep[far] = true;
+
+    let mut looped = points.clone();
+    looped.push(points[0]);
+    let mut stack = vec!((0, far), (far, points.len()));
+    while let Some((from, to)) = stack.pop() {
+        let (a, b) = (looped[from], looped[to]);
+        let length = distance(a, b).max(f32::EPSILON);
+        let farthest = (from + 1..to)
+            .map(|i| {
+                let p = looped[i];
+                (i, ((b[0] - a[0]) * (a[1] - p[1]) - (a[0] - p[0]) * (b[1] - a[1])).abs() / length)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((i, d)) = farthest {
+            if d > tolerance.max(0.0) {
+                keep[i] = true;
+                stack.push((from, i));
+                stack.push((i, to));
+            }
+        }
+    }
+
+    points.into_iter().zip(keep).filter(|(_, keep)| *keep).map(|(p, _)| p).collect()
+}
+
+/// Covers `cells` with rectangles, each `[x, y, width, height]` in cells from its lowest
+/// corner. Greedy: each row is run out as far right as it goes and then stacked up while the
+/// whole run above is free.
+pub fn merge_rects(cells: &HashSet<(i32, i32)>) -> Vec<[i32; 4]> {
+    let mut sorted = cells.iter().copied().collect::<Vec<(i32, i32)>>();
+    sorted.sort_by_key(|c| (c.1, c.0));
+
+    let mut used = HashSet::new();
+    let free = |cell: (i32, i32), used: &HashSet<(i32, i32)>| cells.contains(&cell) && !used.contains(&cell);
+
+    let mut rects = vec!();
+    for start in sorted {
+        if used.contains(&start) {
+            continue;
+        }
+
+        let mut width = 1;
+        while free((start.0 + width, start.1), &used) {
+            width += 1;
+        }
+        let mut height = 1;
+        while (0..width).all(|x| free((start.0 + x, start.1 + height), &used)) {
+            height += 1;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                used.insert((start.0 + x, start.1 + y));
+            }
+        }
+        rects.push([start.0, start.1, width, height]);
+    }
+
+    rects
+}
+
+/// Merged geometry for each of `scene`'s collision layers, on the layer's grid. Tiles whose
+/// cell has no collision shapes count as a full box. Only orthogonal cells merge into
+/// rectangles, other orientations list every tile.
+pub fn merge(scene: &Scene, outlines: bool) -> Vec<MergedCollision> {
+    let mut merged = vec!();
+    for (index, layer) in scene.layers.iter().enumerate().filter(|(_, l)| l.collision) {
+        let (w, h) = (layer.grid_size.0 as i32, layer.grid_size.1 as i32);
+        if w == 0 || h == 0 {
+            continue;
+        }
+
+        let mut cells = HashSet::new();
+        let mut tiles = vec!();
+        for (cell, tile) in &layer.tiles {
+            let sheet = scene.tile_sheets.iter().find(|s| s.path == tile.sheet);
+            let boxed = scene.orientation.is_orthogonal() && sheet.is_some_and(|s| {
+                s.tile_size == layer.grid_size &&
+                match s.collision.get(&tile.sheet_id) {
+                    Some(shapes) => shapes.iter().all(|c| *c == CollisionShape::Box),
+                    None => true
+                }
+            });
+
+            match boxed {
+                true => cells.insert(*cell),
+                false => {
+                    tiles.push(*cell);
+                    false
+                }
+            };
+        }
+        tiles.sort();
+
+        // Cell positions are the middle of the cell, before the layer's offset
+        let (fw, fh) = (w as f32, h as f32);
+        let offset = layer.offset;
+        let corner = |x: i32, y: i32| [x as f32 * fw - fw / 2.0 + offset[0], y as f32 * fh - fh / 2.0 + offset[1]];
+
+        let rects = merge_rects(&cells).into_iter()
+            .map(|[x, y, cw, ch]| {
+                let [left, bottom] = corner(x, y);
+                [left, bottom, (cw * w) as f32, (ch * h) as f32]
+            })
+            .collect();
+
+        let outlines = match outlines {
+            true => {
+                let mut sorted = cells.iter().copied().collect::<Vec<(i32, i32)>>();
+                sorted.sort_by_key(|c| (c.1, c.0));
+                outline_loops(&sorted, |x, y| cells.contains(&(x, y))).into_iter()
+                    .map(|outline| simplify(&outline, 0.0).into_iter().map(|p| corner(p[0] as i32, p[1] as i32)).collect())
+                    .collect()
+            }
+            false => vec!()
+        };
+
+        merged.push(MergedCollision { layer: index, rects, outlines, tiles, offset });
+    }
+
+    merged
+}
@@ -0,0 +1,768 @@
+use glam::Vec2;
+use std::{collections::{HashMap, HashSet}, fmt::Display, fs, path::Path};
+use rfd::FileDialog;
+use indexmap::IndexMap;
+use lilah_editor::{App, Coordinates, LoadType, Orientation, Tile, TileSheet};
+use lilah_editor::error::WithPath;
+use lilah_editor::application::TileWrite;
+use lilah_editor::animation;
+use lilah_editor::brush::Stamp;
+use lilah_editor::chunk::chunk_of;
+use lilah_editor::collision::{self, MergedCollision};
+use lilah_editor::history::Edit;
+use lilah_editor::tiled::{self, TiledFormat};
+use lilah_editor::validate::Problem;
+
+use crate::renderer::{Color, LilahTexture, Sprite, ShaderProgram};
+
+pub type ChunkSprites = HashMap<(i32, i32), HashMap<(i32, i32), Sprite>>;
+
+/// Most chunks `cull` builds sprites for in a frame, so scrolling into a full part of a big map
+/// spreads the work over a few frames instead of stalling.
+const CHUNKS_PER_FRAME: usize = 8;
+
+/// Cells past the edge of the view `cull` still builds, for tiles bigger than the grid.
+const OVERHANG: i32 = 2;
+
+pub enum PropertySelect {
+    None,
+    Layer,
+    Tilesheet(usize),
+    Marker(usize),
+    ObjectLayer(usize),
+    /// Object layer and entity index
+    Entity(usize, usize),
+    /// Index into `Config::prefabs`
+    Prefab(usize),
+    Script
+}
+
+/// The GUI shell around `App`: owns everything that needs a GL context or a file dialog.
+pub struct Editor {
+    pub app: App,
+    pub textures: IndexMap<String, LilahTexture>,
+    /// Sprites of the tiles in view, by layer, chunk and tile key. Chunks out of view have
+    /// none, see `cull`
+    pub sprite_buffer: Vec<ChunkSprites>,
+    /// Errors waiting to be dismissed in the notification panel
+    pub notifications: Vec<String>,
+    /// Results of the last `check`, listed in the problems panel
+    pub problems: Vec<Problem>,
+    /// What the active tool would write if it were let go now, drawn over the current layer
+    pub preview: Vec<TileWrite>,
+    pub preview_sprites: HashMap<(i32, i32), Sprite>,
+    /// Sprites of entities that have one, by object layer and entity index
+    pub entity_sprites: HashMap<(usize, usize), Sprite>,
+    /// The current scene's merged collision for the overlay. Set to `None` after anything it
+    /// depends on changes, and merged again the next time it is drawn
+    pub merged_collision: Option<Vec<MergedCollision>>
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            app: App::new(),
+            textures: IndexMap::new(),
+            sprite_buffer: Vec::new(),
+            notifications: Vec::new(),
+            problems: Vec::new(),
+            preview: Vec::new(),
+            preview_sprites: HashMap::new(),
+            entity_sprites: HashMap::new(),
+            merged_collision: None
+        }
+    }
+
+    /// Re-runs the project and scene checks for the problems panel.
+    pub fn check(&mut self) {
+        self.problems = self.app.check();
+    }
+
+    /// Queues an error for the notification panel.
+    pub fn report(&mut self, e: impl Display) {
+        self.notifications.push(e.to_string());
+    }
+
+    pub fn load_texture_internal(&mut self, gl: &glow::Context, file : &str) {
+        let mut new_texture = unsafe {
+            LilahTexture::new(gl)
+        };
+
+        unsafe {
+            new_texture.set_wrapping(gl, glow::REPEAT as i32);
+            new_texture.set_filtering(gl, glow::LINEAR as i32);
+        }
+
+        unsafe {
+            if let Err(e) = new_texture.load(gl, &Path::new(file)) {
+                self.report(format!("{}: {}", file, e));
+            }
+        }
+
+        //let file = String::from(Path::new(file).file_name().unwrap().to_str().unwrap());
+
+        self.textures.insert(format!("lilah__editor__internal__ignore__{}", file), new_texture);
+    }
+
+    pub fn load_texture(&mut self, gl: &glow::Context, file : &str) {
+        let mut new_texture = unsafe {
+            LilahTexture::new(gl)
+        };
+
+        unsafe {
+            new_texture.set_wrapping(gl, glow::REPEAT as i32);
+            new_texture.set_filtering(gl, glow::LINEAR as i32);
+        }
+
+        unsafe {
+            if let Err(e) = new_texture.load(gl, &Path::new(file)) {
+                self.report(format!("{}: {}", file, e));
+            }
+        }
+
+        //let file = String::from(Path::new(file).file_name().unwrap().to_str().unwrap());
+
+        self.textures.insert(file.to_string(), new_texture);
+    }
+
+    pub fn open_scene(&mut self, gl: &glow::Context) {
+        if let Some(file) = FileDialog::new()
+        .set_directory(format!("{}", self.app.current_project))
+        .pick_file() {
+            if let Err(e) = self.app.load_scene(&file) {
+                self.report(e);
+                return;
+            }
+
+            self.load_scene_sprites(gl);
+        }
+    }
+
+    /// Replaces the collision shapes of `cell` of tile sheet `sheet` with outlines traced from the
+    /// alpha of its texture. See `collision::trace` for `threshold` and `tolerance`.
+    pub fn trace_collision(&mut self, sheet: usize, cell: (u32, u32), threshold: u8, tolerance: f32) {
+        let Some(tile_sheet) = self.app.current_scene.as_mut().and_then(|s| s.tile_sheets.get_mut(sheet)) else {
+            return;
+        };
+
+        let image = match image::open(&tile_sheet.path) {
+            Ok(image) => image.into_rgba8(),
+            Err(e) => {
+                let message = format!("{}: {}", tile_sheet.path, e);
+                self.report(message);
+                return;
+            }
+        };
+
+        let [x, y, w, h] = tile_sheet.cell_rect(cell);
+        let (w, h) = (w.min(image.width().saturating_sub(x)), h.min(image.height().saturating_sub(y)));
+        let alpha = image::imageops::crop_imm(&image, x, y, w, h).to_image().pixels().map(|p| p[3]).collect::<Vec<u8>>();
+
+        let shapes = collision::trace(&alpha, w, h, threshold, tolerance);
+        match shapes.is_empty() {
+            true => tile_sheet.collision.remove(&cell),
+            false => tile_sheet.collision.insert(cell, shapes)
+        };
+        self.merged_collision = None;
+    }
+
+    /// Points the sprites of animated tiles at the frame they show `time` seconds in. Tiles
+    /// whose cell is no longer animated go back to it.
+    pub fn animate(&mut self, gl: &glow::Context, time: f64) {
+        let Some(scene) = self.app.current_scene.as_ref() else {
+            return;
+        };
+        let sheets = scene.tile_sheets.iter().map(|s| (&s.path, s)).collect::<HashMap<&String, &TileSheet>>();
+
+        for (layer, buffer) in scene.layers.iter().zip(self.sprite_buffer.iter_mut()) {
+            for (chunk, sprites) in buffer.iter_mut() {
+                let Some(tiles) = layer.tiles.chunk(chunk) else {
+                    continue;
+                };
+                for (position, sprite) in sprites.iter_mut() {
+                    let Some((tile, sheet)) = tiles.get(position).and_then(|t| Some((t, sheets.get(&t.sheet)?))) else {
+                        continue;
+                    };
+                    let cell = sheet.animations.get(&tile.sheet_id)
+                        .and_then(|frames| animation::frame_at(frames, time))
+                        .unwrap_or(tile.sheet_id);
+                    sprite.set_region(gl, sheet.cell_uv(cell), sheet.tile_size);
+                }
+            }
+        }
+    }
+
+    /// Builds sprites for the chunks of each visible layer that are in a `window_size` view
+    /// centred on `camera`, and frees those of the rest.
+    pub fn cull(&mut self, gl: &glow::Context, program: &ShaderProgram, camera: Vec2, window_size: Vec2) {
+        let Some(scene) = self.app.current_scene.as_ref() else {
+            return;
+        };
+
+        let mut missing = vec!();
+        for (l, (layer, buffer)) in scene.layers.iter().zip(self.sprite_buffer.iter_mut()).enumerate() {
+            // Tiles from sheets bigger than the grid hang over the edge of their cell
+            let centre = camera - Vec2::from(layer.shift([camera.x, camera.y]));
+            let (min, max) = layer.cell_range(scene.orientation, (centre - window_size / 2.0).into(), (centre + window_size / 2.0).into());
+            let (min, max) = ((min.0 - OVERHANG, min.1 - OVERHANG), (max.0 + OVERHANG, max.1 + OVERHANG));
+            let visible = match layer.visible {
+                true => layer.tiles.chunks_in(min, max).into_iter().collect(),
+                false => HashSet::new()
+            };
+
+            buffer.retain(|chunk, sprites| {
+                if visible.contains(chunk) {
+                    return true;
+                }
+                for sprite in sprites.values_mut() {
+                    sprite.unload(gl);
+                }
+                false
+            });
+
+            let centre = chunk_of(layer.cell_at(scene.orientation, centre.into()));
+            missing.extend(visible.into_iter()
+                .filter(|c| !buffer.contains_key(c))
+                .map(|c| ((c.0 - centre.0).abs().max((c.1 - centre.1).abs()), l, c)));
+        }
+
+        // Nearest the middle of the view first
+        missing.sort();
+        for (_, l, chunk) in missing.into_iter().take(CHUNKS_PER_FRAME) {
+            let layer = &scene.layers[l];
+            let Some(tiles) = layer.tiles.chunk(&chunk) else {
+                continue;
+            };
+
+            let mut sprites = HashMap::new();
+            for (position, tile) in tiles {
+                let mut new_spr = tile_sprite(gl, program, &self.textures, &scene.tile_sheets, tile, layer.cell_position(scene.orientation, *position));
+                new_spr.visible = layer.visible;
+                sprites.insert(*position, new_spr);
+            }
+            self.sprite_buffer[l].insert(chunk, sprites);
+        }
+    }
+
+    /// Brings `entity_sprites` in line with the current scene's object layers. Cheap when
+    /// nothing changed, so it runs every frame.
+    pub fn sync_entity_sprites(&mut self, gl: &glow::Context, program: &ShaderProgram) {
+        let Some(scene) = self.app.current_scene.as_ref() else {
+            for sprite in self.entity_sprites.values_mut() {
+                sprite.unload(gl);
+            }
+            self.entity_sprites.clear();
+            return;
+        };
+
+        let mut seen = HashSet::new();
+        for (l, layer) in scene.object_layers.iter().enumerate() {
+            for (e, entity) in layer.entities.iter().enumerate() {
+                let prefabs = &self.app.config.prefabs;
+                let (Some(sheet), Some(sprite)) = (entity.sheet(&scene.tile_sheets, prefabs), entity.resolved_sprite(prefabs)) else {
+                    continue;
+                };
+                if !self.textures.contains_key(&sheet.path) {
+                    continue;
+                }
+
+                let spr = self.entity_sprites.entry((l, e)).or_insert_with(|| Sprite::new(&sheet.path));
+                if spr.texture_id != sheet.path {
+                    spr.unload(gl);
+                    *spr = Sprite::new(&sheet.path);
+                }
+                if !spr.is_loaded() {
+                    spr.load(gl, program, &self.textures);
+                }
+                spr.set_region(gl, sheet.cell_uv(sprite.sheet_id), sheet.tile_size);
+                spr.position = Vec2::new(entity.position[0], entity.position[1]);
+                spr.rotation = entity.rotation.to_radians();
+                spr.scale = Vec2::new(entity.scale[0], entity.scale[1]);
+                spr.tint = Color::new(entity.tint[0], entity.tint[1], entity.tint[2], entity.tint[3]);
+                spr.visible = layer.visible;
+                seen.insert((l, e));
+            }
+        }
+
+        self.entity_sprites.retain(|key, sprite| {
+            if !seen.contains(key) {
+                sprite.unload(gl);
+            }
+            seen.contains(key)
+        });
+    }
+
+    /// Loads the tile sheet textures of the current scene and empties `sprite_buffer` for its layers.
+    pub fn load_scene_sprites(&mut self, gl: &glow::Context) {
+        if let Some(scene) = self.app.current_scene.as_ref() {
+            let mut to_load = vec!();
+            for i in &scene.tile_sheets {
+                to_load.push(i.path.clone());
+            }
+            for i in to_load {
+                self.load_texture(gl, &i);
+            }
+        }
+
+        // `cull` builds the sprites of whatever is in view
+        self.unload_sprites(gl);
+        if let Some(scene) = self.app.current_scene.as_ref() {
+            self.sprite_buffer = vec![HashMap::new(); scene.layers.len()];
+        }
+
+        self.check();
+    }
+
+    /// Lays the current scene's cells out by `orientation`, rebuilding the sprites that moved.
+    pub fn set_orientation(&mut self, gl: &glow::Context, orientation: Orientation) {
+        let Some(scene) = self.app.current_scene.as_mut().filter(|s| s.orientation != orientation) else {
+            return;
+        };
+        scene.orientation = orientation;
+        let layers = scene.layers.len();
+
+        self.unload_sprites(gl);
+        self.sprite_buffer = vec![HashMap::new(); layers];
+        self.preview.clear();
+        self.preview_sprites.clear();
+    }
+
+    /// Frees the sprites of every layer.
+    fn unload_sprites(&mut self, gl: &glow::Context) {
+        for sprite in self.sprite_buffer.iter_mut().flat_map(|b| b.values_mut()).flat_map(|s| s.values_mut()) {
+            sprite.unload(gl);
+        }
+        self.sprite_buffer.clear();
+        self.clear_preview(gl);
+        self.merged_collision = None;
+    }
+
+    /// Drops the sprites of layer `index` from `sprite_buffer`, freeing them.
+    fn remove_layer_sprites(&mut self, gl: &glow::Context, index: usize) {
+        self.merged_collision = None;
+        if index < self.sprite_buffer.len() {
+            for sprite in self.sprite_buffer.remove(index).values_mut().flat_map(|c| c.values_mut()) {
+                sprite.unload(gl);
+            }
+        }
+    }
+
+    /// Frees the sprites of layer `index`, `cull` builds them again where its tiles are now.
+    fn clear_layer_sprites(&mut self, gl: &glow::Context, index: usize) {
+        if let Some(buffer) = self.sprite_buffer.get_mut(index) {
+            for sprite in buffer.values_mut().flat_map(|c| c.values_mut()) {
+                sprite.unload(gl);
+            }
+            buffer.clear();
+        }
+    }
+
+    /// Takes the preview down and frees its sprites.
+    fn clear_preview(&mut self, gl: &glow::Context) {
+        for sprite in self.preview_sprites.values_mut() {
+            sprite.unload(gl);
+        }
+        self.preview_sprites.clear();
+        self.preview.clear();
+    }
+
+    pub fn new_scene(&mut self, gl: &glow::Context) {
+        if let Some(file) = FileDialog::new()
+        .set_directory(format!("{}", self.app.current_project))
+        .save_file() {
+            if let Err(e) = self.app.create_scene(&file) {
+                self.report(e);
+                return;
+            }
+
+            self.unload_sprites(gl);
+            self.sprite_buffer.push(HashMap::new());
+            self.check();
+        }
+    }
+
+    pub fn new_project(&mut self) -> &str {
+        if let Some(file) = FileDialog::new()
+        .set_directory("/")
+        .save_file() {
+            if let Err(e) = self.app.create_project(&file) {
+                self.report(e);
+            }
+        }
+
+        &self.app.current_project
+    }
+
+    pub fn open_project(&mut self) -> &str {
+        if let Some(file) = FileDialog::new()
+        .set_directory("/")
+        .pick_folder() {
+            if let Err(e) = self.app.load_project(&file) {
+                self.report(e);
+            }
+            self.check();
+        }
+
+        &self.app.current_project
+    }
+
+    /// Imports a Tiled map picked from a dialog as a new scene saved where the user chooses.
+    pub fn import_tiled(&mut self, gl: &glow::Context) {
+        let Some(map) = FileDialog::new()
+        .set_directory(&self.app.current_project)
+        .add_filter("Tiled Map", &["tmx", "tmj", "json"])
+        .pick_file() else {
+            return;
+        };
+
+        if let Some(file) = FileDialog::new()
+        .set_directory(&self.app.current_project)
+        .set_file_name(map.file_stem().unwrap_or_default().to_string_lossy())
+        .save_file() {
+            match self.app.import_tiled(&map, &file) {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        self.report(format!("{}: {}", map.display(), warning));
+                    }
+                    self.load_scene_sprites(gl);
+                }
+                Err(e) => self.report(e)
+            }
+        }
+    }
+
+    /// Imports every level of an LDtk project picked from a dialog as scenes in a folder the user chooses.
+    pub fn import_ldtk(&mut self, gl: &glow::Context) {
+        let Some(project) = FileDialog::new()
+        .set_directory(&self.app.current_project)
+        .add_filter("LDtk Project", &["ldtk"])
+        .pick_file() else {
+            return;
+        };
+
+        if let Some(dir) = FileDialog::new()
+        .set_directory(&self.app.current_project)
+        .pick_folder() {
+            match self.app.import_ldtk(&project, &dir) {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        self.report(format!("{}: {}", project.display(), warning));
+                    }
+                }
+                Err(e) => self.report(e)
+            }
+            self.load_scene_sprites(gl);
+        }
+    }
+
+    /// Exports the current scene as a Tiled map saved where the user chooses.
+    pub fn export_tiled(&mut self, format: TiledFormat) {
+        let Some(scene) = self.app.current_scene.as_ref() else {
+            return;
+        };
+
+        let extension = match format {
+            TiledFormat::Tmj => "tmj",
+            TiledFormat::Tmx => "tmx"
+        };
+        if let Some(file) = FileDialog::new()
+        .set_directory(&self.app.current_project)
+        .add_filter("Tiled Map", &[extension])
+        .set_file_name(format!("{}.{}", scene.name, extension))
+        .save_file() {
+            match tiled::export(scene, &file, format) {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        self.report(format!("{}: {}", file.display(), warning));
+                    }
+                }
+                Err(e) => self.report(e)
+            }
+        }
+    }
+
+    /// Asks where to write the current scene as json, see `Scene::export_json`.
+    pub fn export_json(&mut self, coordinates: Coordinates) {
+        let Some(scene) = self.app.current_scene.as_ref() else {
+            return;
+        };
+
+        if let Some(file) = FileDialog::new()
+        .set_directory(&self.app.current_project)
+        .add_filter("Scene", &["json"])
+        .set_file_name(format!("{}_export.json", scene.name))
+        .save_file() {
+            if let Err(e) = scene.export_json(coordinates).and_then(|data| fs::write(&file, data).with_path(&file)) {
+                self.report(e);
+            }
+        }
+    }
+
+    pub fn add_texture(&mut self, gl: &glow::Context, abs_path: String, path: String, tile_count: &[i32; 2]) {
+        self.load_texture(gl, &path);
+
+        let size = self.textures.get(&path).unwrap().size;
+
+        if let Err(e) = self.app.add_tile_sheet(abs_path, path, (size.x as u32, size.y as u32), tile_count) {
+            self.report(e);
+        }
+        self.merged_collision = None;
+    }
+
+    pub fn add_external_asset(&mut self) {
+        if let Some(files) = FileDialog::new()
+        .set_directory(format!("{}", self.app.current_project))
+        .add_filter("Type", &["png", "wav", "mp3"])
+        .pick_files() {
+            for file in &files {
+                if let Err(e) = self.app.add_asset(file, LoadType::External) {
+                    self.report(e);
+                }
+            }
+        }
+    }
+
+    pub fn add_embedded_asset(&mut self) {
+        if let Some(files) = FileDialog::new()
+        .set_directory(format!("{}", self.app.current_project))
+        .add_filter("Type", &["png", "wav", "mp3", "wren", "ttf"])
+        .pick_files() {
+            for file in &files {
+                if let Err(e) = self.app.add_asset(file, LoadType::Emdedded) {
+                    self.report(e);
+                }
+            }
+        }
+    }
+
+    pub fn undo(&mut self, gl: &glow::Context, program: &ShaderProgram) {
+        if let Some(edit) = self.app.undo().cloned() {
+            self.sync_edit(gl, program, &edit, false);
+        }
+    }
+
+    pub fn redo(&mut self, gl: &glow::Context, program: &ShaderProgram) {
+        if let Some(edit) = self.app.redo().cloned() {
+            self.sync_edit(gl, program, &edit, true);
+        }
+    }
+
+    /// Writes tiles into the current layer and its sprites, see `App::set_tiles`.
+    pub fn set_tiles(&mut self, gl: &glow::Context, program: &ShaderProgram, tiles: Vec<TileWrite>) {
+        let positions = self.app.set_tiles(tiles);
+        self.refresh_tiles(gl, program, self.app.current_layer, &positions);
+    }
+
+    /// Paints or erases a stamp from the current layer up, see `App::paint_stamp`.
+    pub fn paint_stamp(&mut self, gl: &glow::Context, program: &ShaderProgram, stamp: &Stamp, cell: (i32, i32), fill: bool) {
+        for (layer, positions) in self.app.paint_stamp(stamp, cell, fill) {
+            self.refresh_tiles(gl, program, layer, &positions);
+        }
+    }
+
+    /// Paints or erases a terrain on the current layer, see `App::paint_terrain`.
+    pub fn paint_terrain(&mut self, gl: &glow::Context, program: &ShaderProgram, sheet: usize, terrain: usize, position: (i32, i32), fill: bool) {
+        let positions = self.app.paint_terrain(sheet, terrain, position, fill);
+        self.refresh_tiles(gl, program, self.app.current_layer, &positions);
+    }
+
+    /// Deletes a layer and its sprites, see `App::remove_layer`.
+    pub fn remove_layer(&mut self, gl: &glow::Context, index: usize) {
+        if self.app.remove_layer(index).is_some() {
+            self.remove_layer_sprites(gl, index);
+        }
+    }
+
+    /// Copies a layer, see `App::duplicate_layer`.
+    pub fn duplicate_layer(&mut self, index: usize) {
+        if let Some(copy) = self.app.duplicate_layer(index) {
+            self.merged_collision = None;
+            self.sprite_buffer.insert(copy.min(self.sprite_buffer.len()), HashMap::new());
+        }
+    }
+
+    /// Moves a layer and its sprites, see `App::move_layer`.
+    pub fn move_layer(&mut self, from: usize, to: usize) {
+        if self.app.move_layer(from, to) && from < self.sprite_buffer.len() && to < self.sprite_buffer.len() {
+            self.merged_collision = None;
+            let buffer = self.sprite_buffer.remove(from);
+            self.sprite_buffer.insert(to, buffer);
+        }
+    }
+
+    /// Merges a layer into the one below, see `App::merge_layer_down`.
+    pub fn merge_layer_down(&mut self, gl: &glow::Context, program: &ShaderProgram, index: usize) {
+        let positions = match self.app.merge_layer_down(index) {
+            Ok(Some(positions)) => positions,
+            Ok(None) => return,
+            Err(e) => return self.report(e)
+        };
+
+        self.remove_layer_sprites(gl, index);
+        self.refresh_tiles(gl, program, index - 1, &positions);
+    }
+
+    /// Asks for another scene of the project and copies a layer into it, see
+    /// `App::copy_layer_to_scene`.
+    pub fn copy_layer_to_scene(&mut self, index: usize) {
+        if let Some(file) = FileDialog::new()
+        .add_filter("Scene", &["json"])
+        .set_directory(&self.app.current_project)
+        .pick_file() {
+            if let Err(e) = self.app.copy_layer_to_scene(index, &file) {
+                self.report(e);
+            }
+        }
+    }
+
+    /// Shows `tiles` over the scene until the preview is replaced or cleared. Sprites are only
+    /// rebuilt when the tiles change, so this can be called every frame.
+    pub fn set_preview(&mut self, gl: &glow::Context, program: &ShaderProgram, tiles: Vec<TileWrite>) {
+        if tiles == self.preview {
+            return;
+        }
+
+        self.clear_preview(gl);
+        if let Some((scene, layer)) = self.app.current_scene.as_ref().and_then(|s| Some((s, s.layers.get(self.app.current_layer)?))) {
+            // A stamp from the library can use sheets this scene doesn't have, and so no texture
+            for (position, tile) in &tiles {
+                if let Some(tile) = tile.as_ref().filter(|t| scene.tile_sheets.iter().any(|s| s.path == t.sheet)) {
+                    let new_spr = tile_sprite(gl, program, &self.textures, &scene.tile_sheets, tile, layer.cell_position(scene.orientation, *position));
+                    self.preview_sprites.insert(*position, new_spr);
+                }
+            }
+        }
+        self.preview = tiles;
+    }
+
+    /// Rebuilds the sprites at `positions` of `layer` from the scene.
+    fn refresh_tiles(&mut self, gl: &glow::Context, program: &ShaderProgram, layer: usize, positions: &[(i32, i32)]) {
+        let Some(scene) = self.app.current_scene.as_ref() else {
+            return;
+        };
+
+        self.merged_collision = None;
+        if let (Some(buffer), Some(l)) = (self.sprite_buffer.get_mut(layer), scene.layers.get(layer)) {
+            for position in positions {
+                // Chunks without sprites are out of view, `cull` builds them when they come in
+                let Some(sprites) = buffer.get_mut(&chunk_of(*position)) else {
+                    continue;
+                };
+                let old = match l.tiles.get(position) {
+                    Some(tile) => {
+                        let mut new_spr = tile_sprite(gl, program, &self.textures, &scene.tile_sheets, tile, l.cell_position(scene.orientation, *position));
+                        new_spr.visible = l.visible;
+                        sprites.insert(*position, new_spr)
+                    }
+                    None => sprites.remove(position)
+                };
+                if let Some(mut old) = old {
+                    old.unload(gl);
+                }
+            }
+        }
+    }
+
+    /// Brings `sprite_buffer` and config.json back in line after `edit` was undone or redone.
+    fn sync_edit(&mut self, gl: &glow::Context, program: &ShaderProgram, edit: &Edit, forward: bool) {
+        self.merged_collision = None;
+        if let Edit::Assets { .. } = edit {
+            if let Err(e) = self.app.write_config() {
+                self.report(e);
+            }
+        }
+
+        if self.app.current_scene.is_none() {
+            return;
+        }
+
+        match edit {
+            Edit::Group(edits) => {
+                // In the order they were applied
+                let edits = match forward {
+                    true => edits.iter().collect::<Vec<&Edit>>(),
+                    false => edits.iter().rev().collect::<Vec<&Edit>>()
+                };
+                for edit in edits {
+                    self.sync_edit(gl, program, edit, forward);
+                }
+            }
+            Edit::Tiles { layer, changes } => {
+                let positions = changes.iter().map(|c| c.position).collect::<Vec<(i32, i32)>>();
+                self.refresh_tiles(gl, program, *layer, &positions);
+            }
+            Edit::AddLayer { index, .. } | Edit::RemoveLayer { index, .. } => {
+                let adding = matches!(edit, Edit::AddLayer { .. }) == forward;
+                if adding {
+                    self.sprite_buffer.insert((*index).min(self.sprite_buffer.len()), HashMap::new());
+                } else {
+                    self.remove_layer_sprites(gl, *index);
+                }
+            }
+            Edit::ChangeLayer { index, before, after } if before.grid_size != after.grid_size => {
+                self.clear_layer_sprites(gl, *index);
+            }
+            Edit::MoveLayer { from, to } => {
+                let (from, to) = if forward { (*from, *to) } else { (*to, *from) };
+                if from < self.sprite_buffer.len() && to < self.sprite_buffer.len() {
+                    let buffer = self.sprite_buffer.remove(from);
+                    self.sprite_buffer.insert(to, buffer);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Builds the sprite that draws `tile` at `position` in the viewport.
+pub fn tile_sprite(
+    gl: &glow::Context,
+    program: &ShaderProgram,
+    textures: &IndexMap<String, LilahTexture>,
+    tile_sheets: &[TileSheet],
+    tile: &Tile,
+    position: [f32; 2]
+) -> Sprite {
+    let mut new_spr = Sprite::new(&tile.sheet);
+    new_spr.flip_x = tile.flip_x;
+    new_spr.flip_y = tile.flip_y;
+    new_spr.rotate = tile.rotate;
+    let sheet = tile_sheets.iter()
+        .find(|&a| a.path == tile.sheet);
+
+    new_spr.load(gl, program, textures);
+    match sheet {
+        Some(sheet) => {
+            new_spr.set_region(gl, sheet.cell_uv(tile.sheet_id), sheet.tile_size);
+        }
+        None => {
+            new_spr.anim_sprite_sheet(
+                gl,
+                program,
+                tile.sheet_id.0 as i32, tile.sheet_id.1 as i32
+            );
+        }
+    }
+    new_spr.position = Vec2::from(position);
+
+    new_spr
+}
+
+pub fn aabb(a_pos: Vec2, a_size: Vec2, b_pos: Vec2, b_size: Vec2) -> bool {
+    //The sides of the rectangles
+    let left_a = a_pos.x;
+    let left_b = b_pos.x;
+    let right_a = left_a + a_size.x;
+    let right_b = left_b + b_size.x;
+    let top_a = a_pos.y;
+    let top_b = b_pos.y;
+    let bottom_a = top_a - a_size.y;
+    let bottom_b = top_b - b_size.y;
+
+    //If any of the sides from A are outside of B
+    if bottom_a <= top_b && top_a >= bottom_b && right_a >= left_b && left_a <= right_b {
+        return true;
+    }
+
+    false
+}
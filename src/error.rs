@@ -0,0 +1,58 @@
+use std::{io, path::{Path, PathBuf}};
+use thiserror::Error;
+
+use crate::application::{AssetType, LoadType};
+
+#[derive(Debug, Error)]
+pub enum EditorError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{}: invalid json: {source}", path.display())]
+    Json { path: PathBuf, source: serde_json::Error },
+    #[error("{}: .{extension} files cannot be added as {load_type:?} assets", path.display())]
+    UnsupportedAsset { path: PathBuf, extension: String, load_type: LoadType },
+    #[error("{}: {type_of:?} assets cannot be external", path.display())]
+    ExternalAsset { path: PathBuf, type_of: AssetType },
+    #[error("{}: script has no load order", path.display())]
+    MissingLoadOrder { path: PathBuf },
+    #[error("{}: tile count must be at least 1x1, got {}x{}", path.display(), count[0], count[1])]
+    InvalidTileCount { path: PathBuf, count: [i32; 2] },
+    #[error("{}: written by a newer editor (format {version}, this one reads up to {supported})", path.display())]
+    NewerFormat { path: PathBuf, version: u32, supported: u32 },
+    /// A map from another editor that couldn't be read
+    #[error("{}: {reason}", path.display())]
+    Import { path: PathBuf, reason: String },
+    #[error("cargo {command} failed for {}: {reason}", path.display())]
+    Cargo { command: &'static str, path: PathBuf, reason: String },
+    #[error("layer {index} can't be merged down, {reason}")]
+    MergeLayers { index: usize, reason: String },
+    #[error("no project is open")]
+    NoProject,
+    #[error("no scene is open")]
+    NoScene
+}
+
+impl EditorError {
+    pub(crate) fn import(path: &Path, reason: impl ToString) -> Self {
+        EditorError::Import { path: path.to_path_buf(), reason: reason.to_string() }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, EditorError>;
+
+/// Attaches the offending path to io and serde_json errors.
+pub trait WithPath<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T> WithPath<T> for io::Result<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| EditorError::Io { path: path.as_ref().to_path_buf(), source })
+    }
+}
+
+impl<T> WithPath<T> for serde_json::Result<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| EditorError::Json { path: path.as_ref().to_path_buf(), source })
+    }
+}
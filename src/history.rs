@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use indexmap::IndexMap;
+
+use crate::application::{Asset, Config, Entity, Layer, LayerSettings, Marker, ObjectLayer, Scene, Tile, TileSheet};
+
+/// How many edits are kept before the oldest ones are dropped.
+const MAX_HISTORY: usize = 256;
+
+#[derive(Clone)]
+pub struct TileChange {
+    pub position: (i32, i32),
+    pub before: Option<Tile>,
+    pub after: Option<Tile>
+}
+
+/// A single reversible change to the open scene or the project config.
+#[derive(Clone)]
+pub enum Edit {
+    /// One paint or erase stroke on a layer
+    Tiles { layer: usize, changes: Vec<TileChange> },
+    AddMarker { index: usize, marker: Marker },
+    RemoveMarker { index: usize, marker: Marker },
+    /// Covers moving and renaming
+    ChangeMarker { index: usize, before: Marker, after: Marker },
+    AddLayer { index: usize, layer: Layer },
+    RemoveLayer { index: usize, layer: Layer },
+    /// Layer `from` taken out and put back in at `to`
+    MoveLayer { from: usize, to: usize },
+    SetLayerVisible { index: usize, visible: bool },
+    /// Anything set in the Layer tab
+    ChangeLayer { index: usize, before: LayerSettings, after: LayerSettings },
+    AddObjectLayer { index: usize, layer: ObjectLayer },
+    RemoveObjectLayer { index: usize, layer: ObjectLayer },
+    RenameObjectLayer { index: usize, before: String, after: String },
+    SetObjectLayerVisible { index: usize, visible: bool },
+    AddEntity { layer: usize, index: usize, entity: Entity },
+    RemoveEntity { layer: usize, index: usize, entity: Entity },
+    /// Covers moving, transforming and anything set in the Properties window
+    ChangeEntity { layer: usize, index: usize, before: Entity, after: Entity },
+    AddTileSheet { index: usize, sheet: TileSheet },
+    RemoveTileSheet { index: usize, sheet: TileSheet },
+    Assets { before: HashMap<String, Asset>, after: HashMap<String, Asset> },
+    /// Edits undone and redone together, like a stroke that painted on several layers
+    Group(Vec<Edit>)
+}
+
+impl Edit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Edit::Tiles { .. } => "Paint Tiles",
+            Edit::AddMarker { .. } => "Add Marker",
+            Edit::RemoveMarker { .. } => "Delete Marker",
+            Edit::ChangeMarker { .. } => "Edit Marker",
+            Edit::AddLayer { .. } => "Add Layer",
+            Edit::RemoveLayer { .. } => "Delete Layer",
+            Edit::MoveLayer { .. } => "Move Layer",
+            Edit::SetLayerVisible { .. } => "Layer Visibility",
+            Edit::ChangeLayer { .. } => "Edit Layer",
+            Edit::AddObjectLayer { .. } => "Add Object Layer",
+            Edit::RemoveObjectLayer { .. } => "Delete Object Layer",
+            Edit::RenameObjectLayer { .. } => "Rename Object Layer",
+            Edit::SetObjectLayerVisible { .. } => "Object Layer Visibility",
+            Edit::AddEntity { .. } => "Add Entity",
+            Edit::RemoveEntity { .. } => "Delete Entity",
+            Edit::ChangeEntity { .. } => "Edit Entity",
+            Edit::AddTileSheet { .. } => "Add Tile Sheet",
+            Edit::RemoveTileSheet { .. } => "Remove Tile Sheet",
+            Edit::Assets { .. } => "Assets",
+            Edit::Group(edits) => edits.first().map_or("Edit", |e| e.name())
+        }
+    }
+
+    fn apply(&self, mut scene: Option<&mut Scene>, config: &mut Config, forward: bool) {
+        if let Edit::Group(edits) = self {
+            let mut apply = |edit: &Edit| edit.apply(scene.as_deref_mut(), config, forward);
+            match forward {
+                true => edits.iter().for_each(&mut apply),
+                false => edits.iter().rev().for_each(&mut apply)
+            }
+            return;
+        }
+
+        if let Edit::Assets { before, after } = self {
+            config.assets = if forward { after.clone() } else { before.clone() };
+            return;
+        }
+
+        let Some(scene) = scene else {
+            return;
+        };
+
+        match self {
+            Edit::Tiles { layer, changes } => {
+                if let Some(layer) = scene.layers.get_mut(*layer) {
+                    for change in changes {
+                        let tile = if forward { &change.after } else { &change.before };
+                        match tile {
+                            Some(tile) => { layer.tiles.insert(change.position, tile.clone()); }
+                            None => { layer.tiles.remove(&change.position); }
+                        }
+                    }
+                }
+            }
+            Edit::AddMarker { index, marker } | Edit::RemoveMarker { index, marker } => {
+                let adding = matches!(self, Edit::AddMarker { .. }) == forward;
+                if adding {
+                    scene.markers.insert((*index).min(scene.markers.len()), marker.clone());
+                } else if *index < scene.markers.len() {
+                    scene.markers.remove(*index);
+                }
+            }
+            Edit::ChangeMarker { index, before, after } => {
+                if let Some(marker) = scene.markers.get_mut(*index) {
+                    *marker = if forward { after.clone() } else { before.clone() };
+                }
+            }
+            Edit::AddLayer { index, layer } | Edit::RemoveLayer { index, layer } => {
+                let adding = matches!(self, Edit::AddLayer { .. }) == forward;
+                if adding {
+                    scene.layers.insert((*index).min(scene.layers.len()), layer.clone());
+                } else if *index < scene.layers.len() {
+                    scene.layers.remove(*index);
+                }
+            }
+            Edit::MoveLayer { from, to } => {
+                let (from, to) = if forward { (*from, *to) } else { (*to, *from) };
+                if from < scene.layers.len() && to < scene.layers.len() {
+                    let layer = scene.layers.remove(from);
+                    scene.layers.insert(to, layer);
+                }
+            }
+            Edit::SetLayerVisible { index, visible } => {
+                if let Some(layer) = scene.layers.get_mut(*index) {
+                    layer.visible = if forward { *visible } else { !*visible };
+                }
+            }
+            Edit::ChangeLayer { index, before, after } => {
+                if let Some(layer) = scene.layers.get_mut(*index) {
+                    layer.set_settings(if forward { after } else { before });
+                }
+            }
+            Edit::AddObjectLayer { index, layer } | Edit::RemoveObjectLayer { index, layer } => {
+                let adding = matches!(self, Edit::AddObjectLayer { .. }) == forward;
+                if adding {
+                    scene.object_layers.insert((*index).min(scene.object_layers.len()), layer.clone());
+                } else if *index < scene.object_layers.len() {
+                    scene.object_layers.remove(*index);
+                }
+            }
+            Edit::RenameObjectLayer { index, before, after } => {
+                if let Some(layer) = scene.object_layers.get_mut(*index) {
+                    layer.name = if forward { after.clone() } else { before.clone() };
+                }
+            }
+            Edit::SetObjectLayerVisible { index, visible } => {
+                if let Some(layer) = scene.object_layers.get_mut(*index) {
+                    layer.visible = if forward { *visible } else { !*visible };
+                }
+            }
+            Edit::AddEntity { layer, index, entity } | Edit::RemoveEntity { layer, index, entity } => {
+                let adding = matches!(self, Edit::AddEntity { .. }) == forward;
+                if let Some(layer) = scene.object_layers.get_mut(*layer) {
+                    if adding {
+                        layer.entities.insert((*index).min(layer.entities.len()), entity.clone());
+                    } else if *index < layer.entities.len() {
+                        layer.entities.remove(*index);
+                    }
+                }
+            }
+            Edit::ChangeEntity { layer, index, before, after } => {
+                if let Some(entity) = scene.object_layers.get_mut(*layer).and_then(|l| l.entities.get_mut(*index)) {
+                    *entity = if forward { after.clone() } else { before.clone() };
+                }
+            }
+            Edit::AddTileSheet { index, sheet } | Edit::RemoveTileSheet { index, sheet } => {
+                let adding = matches!(self, Edit::AddTileSheet { .. }) == forward;
+                if adding {
+                    scene.tile_sheets.insert((*index).min(scene.tile_sheets.len()), sheet.clone());
+                } else if *index < scene.tile_sheets.len() {
+                    scene.tile_sheets.remove(*index);
+                }
+            }
+            Edit::Assets { .. } | Edit::Group(_) => {}
+        }
+    }
+}
+
+/// Where the layer at `index` ends up when the layer at `from` is moved to `to`.
+pub fn moved_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// Tile changes collected while a mouse button is held, so a whole drag undoes in one step.
+struct Stroke {
+    /// Tile before and after, by layer and position
+    changes: IndexMap<(usize, (i32, i32)), TilePair>
+}
+
+type TilePair = (Option<Tile>, Option<Tile>);
+
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    stroke: Option<Stroke>
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            stroke: None
+        }
+    }
+
+    /// Records an edit that has already been applied.
+    pub fn push(&mut self, edit: Edit) {
+        self.end_stroke();
+        self.push_internal(edit);
+    }
+
+    fn push_internal(&mut self, edit: Edit) {
+        self.redo_stack.clear();
+        self.undo_stack.push(edit);
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    /// Records a tile change that has already been applied, merging it into the current stroke.
+    pub fn record_tile(&mut self, layer: usize, position: (i32, i32), before: Option<Tile>, after: Option<Tile>) {
+        let stroke = self.stroke.get_or_insert_with(|| Stroke { changes: IndexMap::new() });
+        stroke.changes.entry((layer, position))
+            .and_modify(|change| change.1 = after.clone())
+            .or_insert((before, after));
+    }
+
+    /// Closes the current stroke, turning it into a single undo step.
+    pub fn end_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            let mut layers: IndexMap<usize, Vec<TileChange>> = IndexMap::new();
+            for ((layer, position), (before, after)) in stroke.changes {
+                if before != after {
+                    layers.entry(layer).or_default().push(TileChange { position, before, after });
+                }
+            }
+
+            let mut edits = layers.into_iter()
+                .map(|(layer, changes)| Edit::Tiles { layer, changes })
+                .collect::<Vec<Edit>>();
+
+            match edits.len() {
+                0 => {}
+                1 => self.push_internal(edits.remove(0)),
+                _ => self.push_internal(Edit::Group(edits))
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty() || self.stroke.is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// The edit `undo` would revert next.
+    pub fn last_undo(&self) -> Option<&Edit> {
+        self.undo_stack.last()
+    }
+
+    /// The edit `redo` would re-apply next.
+    pub fn last_redo(&self) -> Option<&Edit> {
+        self.redo_stack.last()
+    }
+
+    /// Reverts the most recent edit and returns it so callers can refresh derived state.
+    pub fn undo(&mut self, scene: Option<&mut Scene>, config: &mut Config) -> Option<&Edit> {
+        self.end_stroke();
+        let edit = self.undo_stack.pop()?;
+        edit.apply(scene, config, false);
+        self.redo_stack.push(edit);
+        self.redo_stack.last()
+    }
+
+    /// Re-applies the most recently undone edit and returns it so callers can refresh derived state.
+    pub fn redo(&mut self, scene: Option<&mut Scene>, config: &mut Config) -> Option<&Edit> {
+        self.end_stroke();
+        let edit = self.redo_stack.pop()?;
+        edit.apply(scene, config, true);
+        self.undo_stack.push(edit);
+        self.undo_stack.last()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.stroke = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::{AssetType, LoadType};
+    use crate::schema::Extra;
+
+    fn tile(sheet_id: (u32, u32)) -> Tile {
+        Tile { sheet: String::from("tiles.png"), sheet_id, flip_x: false, flip_y: false, rotate: false, extra: Extra::new() }
+    }
+
+    fn scene() -> Scene {
+        let mut scene = Scene::new("test", "test.json");
+        scene.layers = vec!(Layer::new(), Layer::new());
+        scene
+    }
+
+    /// Paints `tile` at `position` on `layer` the way the editor does, recording it.
+    fn paint(history: &mut History, scene: &mut Scene, layer: usize, position: (i32, i32), tile: Option<Tile>) {
+        let before = match &tile {
+            Some(tile) => scene.layers[layer].tiles.insert(position, tile.clone()),
+            None => scene.layers[layer].tiles.remove(&position)
+        };
+        history.record_tile(layer, position, before, tile);
+    }
+
+    fn sheet_id(scene: &Scene, layer: usize, position: (i32, i32)) -> Option<(u32, u32)> {
+        scene.layers[layer].tiles.get(&position).map(|t| t.sheet_id)
+    }
+
+    #[test]
+    fn stroke_undoes_in_one_step() {
+        let mut history = History::new();
+        let mut config = Config::new();
+        let mut scene = scene();
+
+        paint(&mut history, &mut scene, 0, (0, 0), Some(tile((1, 0))));
+        paint(&mut history, &mut scene, 0, (16, 0), Some(tile((1, 0))));
+        // Painting the same cell again keeps its first `before`
+        paint(&mut history, &mut scene, 0, (0, 0), Some(tile((2, 0))));
+        history.end_stroke();
+
+        let Some(Edit::Tiles { layer: 0, changes }) = history.last_undo() else {
+            panic!("expected one tile edit");
+        };
+        assert_eq!(changes.len(), 2);
+
+        history.undo(Some(&mut scene), &mut config);
+        assert!(scene.layers[0].tiles.is_empty());
+        assert!(!history.can_undo());
+
+        history.redo(Some(&mut scene), &mut config);
+        assert_eq!(sheet_id(&scene, 0, (0, 0)), Some((2, 0)));
+        assert_eq!(sheet_id(&scene, 0, (16, 0)), Some((1, 0)));
+    }
+
+    #[test]
+    fn stroke_that_changes_nothing_is_dropped() {
+        let mut history = History::new();
+        let mut scene = scene();
+
+        paint(&mut history, &mut scene, 0, (0, 0), Some(tile((1, 0))));
+        paint(&mut history, &mut scene, 0, (0, 0), None);
+        history.end_stroke();
+
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn a_stroke_over_several_layers_undoes_together() {
+        let mut history = History::new();
+        let mut config = Config::new();
+        let mut scene = scene();
+
+        paint(&mut history, &mut scene, 0, (0, 0), Some(tile((1, 0))));
+        paint(&mut history, &mut scene, 1, (0, 0), Some(tile((2, 0))));
+        history.end_stroke();
+        assert!(matches!(history.last_undo(), Some(Edit::Group(edits)) if edits.len() == 2));
+
+        history.undo(Some(&mut scene), &mut config);
+        assert_eq!(sheet_id(&scene, 0, (0, 0)), None);
+        assert_eq!(sheet_id(&scene, 1, (0, 0)), None);
+        assert!(!history.can_undo());
+
+        history.redo(Some(&mut scene), &mut config);
+        assert_eq!(sheet_id(&scene, 0, (0, 0)), Some((1, 0)));
+        assert_eq!(sheet_id(&scene, 1, (0, 0)), Some((2, 0)));
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut history = History::new();
+        let mut config = Config::new();
+        let mut scene = scene();
+
+        let marker = Marker { position: [1.0, 2.0], name: String::from("spawn"), extra: Extra::new() };
+        scene.markers.push(marker.clone());
+        history.push(Edit::AddMarker { index: 0, marker: marker.clone() });
+
+        history.undo(Some(&mut scene), &mut config);
+        assert!(scene.markers.is_empty());
+        assert!(history.can_redo());
+
+        let moved = Marker { position: [3.0, 4.0], ..marker.clone() };
+        history.push(Edit::ChangeMarker { index: 0, before: marker, after: moved });
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn asset_edits_apply_without_a_scene() {
+        let mut history = History::new();
+        let mut config = Config::new();
+
+        let before = config.assets.clone();
+        config.assets.insert(String::from("player.png"), Asset {
+            name: String::from("player.png"),
+            path: String::from("assets/player.png"),
+            absolute_path: String::from("/project/assets/player.png"),
+            type_of: AssetType::Texture,
+            load_type: LoadType::External,
+            load_order: None,
+            extra: Extra::new()
+        });
+        history.push(Edit::Assets { before, after: config.assets.clone() });
+
+        history.undo(None, &mut config);
+        assert!(config.assets.is_empty());
+        history.redo(None, &mut config);
+        assert!(config.assets.contains_key("player.png"));
+    }
+}
@@ -0,0 +1,359 @@
+//! Import of LDtk projects (.ldtk), one `Scene` per level.
+//!
+//! LDtk lists layers top-most first and measures from the top left of the level, so both are
+//! flipped on the way in to match the editor's draw order and y-up viewport.
+
+use std::{collections::HashMap, fs, path::Path};
+use serde::Deserialize;
+
+use crate::application::{Layer, Marker, Scene, Tile, TileSheet};
+use crate::collision::CollisionShape;
+use crate::error::{EditorError, Result, WithPath};
+use crate::schema::Extra;
+
+pub struct LdtkImport {
+    pub scenes: Vec<Scene>,
+    /// Everything in the project that has no equivalent in a `Scene` and was skipped or simplified
+    pub warnings: Vec<String>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct LdtkProject {
+    defs: Defs,
+    levels: Vec<Level>,
+    /// Only filled in for multi-world projects, which leave `levels` empty
+    worlds: Vec<World>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Defs {
+    tilesets: Vec<TilesetDef>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct TilesetDef {
+    uid: i64,
+    identifier: String,
+    /// `None` for embedded atlases, which have no image on disk
+    rel_path: Option<String>,
+    px_wid: u32,
+    px_hei: u32,
+    tile_grid_size: u32,
+    spacing: u32,
+    padding: u32
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct World {
+    levels: Vec<Level>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Level {
+    identifier: String,
+    /// Set when the project saves levels in separate .ldtkl files
+    external_rel_path: Option<String>,
+    layer_instances: Option<Vec<LayerInstance>>
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct LayerInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__cWid")]
+    c_wid: u32,
+    #[serde(rename = "__gridSize")]
+    grid_size: u32,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset_def_uid: Option<i64>,
+    #[serde(rename = "__pxTotalOffsetX")]
+    px_total_offset_x: i32,
+    #[serde(rename = "__pxTotalOffsetY")]
+    px_total_offset_y: i32,
+    visible: bool,
+    opacity: f32,
+    grid_tiles: Vec<TileInstance>,
+    auto_layer_tiles: Vec<TileInstance>,
+    entity_instances: Vec<EntityInstance>,
+    int_grid_csv: Vec<i32>
+}
+
+impl Default for LayerInstance {
+    fn default() -> Self {
+        Self {
+            identifier: String::new(),
+            kind: String::new(),
+            c_wid: 0,
+            grid_size: 0,
+            tileset_def_uid: None,
+            px_total_offset_x: 0,
+            px_total_offset_y: 0,
+            visible: true,
+            opacity: 1.0,
+            grid_tiles: Vec::new(),
+            auto_layer_tiles: Vec::new(),
+            entity_instances: Vec::new(),
+            int_grid_csv: Vec::new()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TileInstance {
+    /// Top left of the cell in the layer
+    px: [i32; 2],
+    /// Top left of the tile in the tileset image
+    src: [u32; 2],
+    /// Flip bits, 1 for x and 2 for y
+    f: u8
+}
+
+#[derive(Deserialize)]
+struct EntityInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    /// Pivot point in the layer
+    px: [i32; 2]
+}
+
+/// Reads the .ldtk project at `file`, along with any external level files it references.
+pub fn import(file: &Path) -> Result<LdtkImport> {
+    let project: LdtkProject = serde_json::from_slice(&fs::read(file).with_path(file)?).with_path(file)?;
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut warnings = vec!();
+
+    let mut tile_sheets = vec!();
+    let mut sheet_uids = HashMap::new();
+    for def in &project.defs.tilesets {
+        let Some(rel_path) = &def.rel_path else {
+            warnings.push(format!("tileset '{}' has no image and was skipped", def.identifier));
+            continue;
+        };
+
+        let image = dir.join(rel_path);
+        let image = fs::canonicalize(&image).unwrap_or(image);
+        let path = image.to_string_lossy().to_string();
+
+        sheet_uids.insert(def.uid, tile_sheets.len());
+        tile_sheets.push(TileSheet {
+            filename: image.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path: path.clone(),
+            absolute_path: path,
+            tile_size: (def.tile_grid_size, def.tile_grid_size),
+            sheet_size: (def.px_wid, def.px_hei),
+            margin: def.padding,
+            spacing: def.spacing,
+            terrains: Vec::new(),
+            properties: HashMap::new(),
+            collision: HashMap::new(),
+            animations: HashMap::new(),
+            extra: Extra::new()
+        });
+    }
+
+    let levels = match project.levels.is_empty() {
+        true => project.worlds.into_iter().flat_map(|w| w.levels).collect(),
+        false => project.levels
+    };
+
+    let mut scenes = vec!();
+    for mut level in levels {
+        if level.layer_instances.is_none() {
+            if let Some(rel_path) = &level.external_rel_path {
+                let level_file = dir.join(rel_path);
+                level = serde_json::from_slice(&fs::read(&level_file).with_path(&level_file)?).with_path(&level_file)?;
+            }
+        }
+
+        let Some(layer_instances) = level.layer_instances.take() else {
+            return Err(EditorError::import(file, format!("level '{}' has no layers", level.identifier)));
+        };
+
+        let mut scene = Scene::new(&level.identifier, &format!("{}.json", level.identifier));
+        scene.tile_sheets = tile_sheets.clone();
+
+        let mut add_warning = |layer: &LayerInstance, warning: String| {
+            warnings.push(format!("level '{}': layer '{}': {}", level.identifier, layer.identifier, warning));
+        };
+
+        for layer in layer_instances.iter().rev() {
+            let sheet = layer.tileset_def_uid.and_then(|uid| sheet_uids.get(&uid).copied());
+            let offset = (layer.px_total_offset_x, layer.px_total_offset_y);
+
+            match layer.kind.as_str() {
+                "Tiles" | "AutoLayer" | "IntGrid" => {
+                    let tiles = if layer.kind == "Tiles" { &layer.grid_tiles } else { &layer.auto_layer_tiles };
+                    if !tiles.is_empty() {
+                        match sheet {
+                            Some(sheet) => {
+                                let (new_layer, stacked) = tile_layer(&scene.tile_sheets, sheet, layer, tiles);
+                                if stacked > 0 {
+                                    add_warning(layer, format!("{} stacked tiles were covered by the tile above them and dropped", stacked));
+                                }
+                                scene.layers.push(new_layer);
+                            }
+                            None => {
+                                add_warning(layer, String::from("its tileset has no image, the tiles were skipped"));
+                            }
+                        }
+                    }
+
+                    if layer.kind == "IntGrid" {
+                        // Collision tiles need some sheet to point at, they stay hidden so it is never drawn
+                        let boxed = box_sheet(&scene.tile_sheets, sheet, layer.grid_size);
+                        match boxed.or(sheet).or(if scene.tile_sheets.is_empty() { None } else { Some(0) }) {
+                            Some(sheet) => {
+                                let (new_layer, values) = collision_layer(&scene.tile_sheets, sheet, layer, offset);
+                                if values > 1 {
+                                    add_warning(layer, format!("{} int grid values were merged into one collision layer", values));
+                                }
+                                if boxed.is_none() {
+                                    add_warning(layer, format!(
+                                        "no tile sheet has {0}x{0} tiles to stand in for collision boxes, merged collision lists each tile",
+                                        layer.grid_size
+                                    ));
+                                }
+                                scene.layers.push(new_layer);
+                            }
+                            None => {
+                                add_warning(layer, String::from("the project has no tile sheets to build a collision layer from"));
+                            }
+                        }
+                    }
+                }
+                "Entities" => {
+                    let half = layer.grid_size as f32 / 2.0;
+                    for entity in &layer.entity_instances {
+                        // Cells are placed by their middle, LDtk measures from the top left corner of the level
+                        scene.markers.push(Marker {
+                            position: [
+                                (entity.px[0] + offset.0) as f32 - half,
+                                half - (entity.px[1] + offset.1) as f32
+                            ],
+                            name: entity.identifier.clone(),
+                            extra: Extra::new()
+                        });
+                    }
+                }
+                kind => {
+                    add_warning(layer, format!("{} layers are not supported and were skipped", kind));
+                }
+            }
+        }
+
+        // The editor always expects a layer to paint on
+        if scene.layers.is_empty() {
+            scene.layers.push(Layer::new());
+        }
+
+        scenes.push(scene);
+    }
+
+    Ok(LdtkImport { scenes, warnings })
+}
+
+/// Builds a layer from `tiles` cut from `tile_sheets[sheet]`, returning it with how many tiles
+/// were covered by a later tile in the same cell.
+fn tile_layer(tile_sheets: &[TileSheet], sheet: usize, layer: &LayerInstance, tiles: &[TileInstance]) -> (Layer, usize) {
+    let tile_sheet = &tile_sheets[sheet];
+    let step = (tile_sheet.tile_size.0 + tile_sheet.spacing).max(1);
+
+    let grid = layer.grid_size.max(1) as i32;
+    let (shift, rest) = split_offset((layer.px_total_offset_x, layer.px_total_offset_y), grid);
+
+    let mut new_layer = Layer::new();
+    new_layer.name = layer.identifier.clone();
+    new_layer.grid_size = (grid as u32, grid as u32);
+    new_layer.offset = rest;
+    new_layer.visible = layer.visible;
+    new_layer.opacity = layer.opacity;
+    new_layer.tile_sheet = tile_sheet.path.clone();
+    new_layer.current_tile_item = sheet as i32;
+
+    let mut stacked = 0;
+    for tile in tiles {
+        let position = (tile.px[0].div_euclid(grid) + shift.0, -(tile.px[1].div_euclid(grid) + shift.1));
+        let sheet_id = (
+            tile.src[0].saturating_sub(tile_sheet.margin) / step,
+            tile.src[1].saturating_sub(tile_sheet.margin) / step
+        );
+
+        let replaced = new_layer.tiles.insert(position, Tile {
+            sheet: tile_sheet.path.clone(),
+            sheet_id,
+            flip_x: tile.f & 1 != 0,
+            flip_y: tile.f & 2 != 0,
+            rotate: false,
+            extra: Extra::new()
+        });
+        if replaced.is_some() {
+            stacked += 1;
+        }
+    }
+
+    (new_layer, stacked)
+}
+
+/// A sheet whose first cell is a plain box `grid` pixels square, so collision tiles cut from it
+/// merge into boxes the shape of the int grid. `preferred`, the layer's own tileset, if it will do.
+fn box_sheet(tile_sheets: &[TileSheet], preferred: Option<usize>, grid: u32) -> Option<usize> {
+    let boxed = |sheet: &TileSheet| {
+        sheet.tile_size == (grid, grid) &&
+        sheet.collision.get(&(0, 0)).is_none_or(|shapes| shapes.iter().all(|c| *c == CollisionShape::Box))
+    };
+    preferred.filter(|i| boxed(&tile_sheets[*i])).or_else(|| tile_sheets.iter().position(boxed))
+}
+
+/// Builds a hidden collision layer with a tile on every non-empty int grid cell, returning it
+/// with how many different values the grid used.
+fn collision_layer(tile_sheets: &[TileSheet], sheet: usize, layer: &LayerInstance, offset: (i32, i32)) -> (Layer, usize) {
+    let grid = layer.grid_size.max(1) as i32;
+    let (shift, rest) = split_offset(offset, grid);
+
+    let mut new_layer = Layer::new();
+    new_layer.name = format!("{} collision", layer.identifier);
+    new_layer.grid_size = (grid as u32, grid as u32);
+    new_layer.offset = rest;
+    new_layer.visible = false;
+    new_layer.collision = true;
+    new_layer.tile_sheet = tile_sheets[sheet].path.clone();
+    new_layer.current_tile_item = sheet as i32;
+
+    let width = layer.c_wid.max(1) as usize;
+    let mut values = vec!();
+    for (i, value) in layer.int_grid_csv.iter().enumerate() {
+        if *value == 0 {
+            continue;
+        }
+        if !values.contains(value) {
+            values.push(*value);
+        }
+
+        let position = ((i % width) as i32 + shift.0, -((i / width) as i32 + shift.1));
+        new_layer.tiles.insert(position, Tile {
+            sheet: tile_sheets[sheet].path.clone(),
+            sheet_id: (0, 0),
+            flip_x: false,
+            flip_y: false,
+            rotate: false,
+            extra: Extra::new()
+        });
+    }
+
+    (new_layer, values.len())
+}
+
+/// Splits a layer offset of `offset` pixels, y down, into whole cells of `grid` and the pixels
+/// left over, y up, for `Layer::offset`.
+fn split_offset(offset: (i32, i32), grid: i32) -> ((i32, i32), [f32; 2]) {
+    let cells = (offset.0.div_euclid(grid), offset.1.div_euclid(grid));
+    (cells, [offset.0.rem_euclid(grid) as f32, -(offset.1.rem_euclid(grid) as f32)])
+}
@@ -0,0 +1,27 @@
+//! Scene, tile sheet and project types for lilah games, and the editor's `App` core.
+//!
+//! Nothing in here needs a GL context or a file dialog, so game runtimes and tools can depend
+//! on it for the same serde types the editor writes.
+
+extern crate pathdiff;
+
+pub mod animation;
+pub mod application;
+pub mod brush;
+pub mod chunk;
+pub mod collision;
+pub mod error;
+pub mod history;
+pub mod ldtk;
+pub mod orientation;
+pub mod prefab;
+pub mod properties;
+pub mod schema;
+pub mod terrain;
+pub mod tiled;
+pub mod tools;
+pub mod validate;
+
+pub use application::{App, Asset, AssetType, Config, Coordinates, Entity, EntitySprite, Layer, LayerSettings, LoadType, Marker, ObjectLayer, Scene, Tile, TileSheet, TileWrite};
+pub use error::EditorError;
+pub use orientation::Orientation;
@@ -1,20 +1,61 @@
-extern crate pathdiff;
-use application::{App, AssetType, Layer, Marker, PropertySelect, Tile, TileSheet};
+use clap::Parser;
+#[cfg(feature = "gui")]
+use editor::{aabb, Editor, PropertySelect};
+#[cfg(feature = "gui")]
+use lilah_editor::{AssetType, Coordinates, Entity, EntitySprite, Layer, LayerSettings, Marker, ObjectLayer, Orientation, Tile, TileSheet};
+#[cfg(feature = "gui")]
+use lilah_editor::application::orient_uv;
+#[cfg(feature = "gui")]
+use lilah_editor::animation::{self, Frame};
+#[cfg(feature = "gui")]
+use lilah_editor::brush::Stamp;
+#[cfg(feature = "gui")]
+use lilah_editor::collision::{self, CollisionShape};
+#[cfg(feature = "gui")]
+use lilah_editor::history::{Edit, History};
+#[cfg(feature = "gui")]
+use lilah_editor::prefab::Prefab;
+#[cfg(feature = "gui")]
+use lilah_editor::properties::{Properties, Property};
+#[cfg(feature = "gui")]
+use lilah_editor::schema::Extra;
+#[cfg(feature = "gui")]
+use lilah_editor::terrain::{self, Terrain, TerrainKind};
+#[cfg(feature = "gui")]
+use lilah_editor::validate::Location;
+#[cfg(feature = "gui")]
+use lilah_editor::tiled::TiledFormat;
+#[cfg(feature = "gui")]
+use lilah_editor::tools::{self, Tool};
+#[cfg(feature = "gui")]
 use glam::{Mat4, Vec3, Vec2, Quat};
+#[cfg(feature = "gui")]
 use imgui::{DragDropFlags, FontConfig, Selectable, TextureId};
-use renderer::{Line, ShaderProgram, Sprite};
+#[cfg(feature = "gui")]
+use renderer::{Color, Line, ShaderProgram, Sprite};
+use std::process::ExitCode;
+#[cfg(feature = "gui")]
 use std::{time::Instant, collections::HashMap};
+#[cfg(feature = "gui")]
 use glow::HasContext;
+#[cfg(feature = "gui")]
 use glutin::{event_loop::EventLoop, WindowedContext, dpi, event::{ElementState, KeyboardInput, VirtualKeyCode}};
+#[cfg(feature = "gui")]
 use imgui_winit_support::WinitPlatform;
 
+#[cfg(feature = "gui")]
 mod renderer;
-mod application;
+mod cli;
+#[cfg(feature = "gui")]
+mod editor;
 
+#[cfg(feature = "gui")]
 const TITLE: &str = "Lilah Editor";
 
+#[cfg(feature = "gui")]
 type Window = WindowedContext<glutin::PossiblyCurrent>;
 
+#[cfg(feature = "gui")]
 fn window_size(window: Window, winit_platform: WinitPlatform) -> Vec2 {
     Vec2::new(
         window.window().inner_size().to_logical::<f32>(winit_platform.hidpi_factor()).width,
@@ -22,8 +63,23 @@ fn window_size(window: Window, winit_platform: WinitPlatform) -> Vec2 {
     )
 }
 
-fn main() {
-    let mut app = App::new();
+fn main() -> ExitCode {
+    if let Some(command) = cli::Cli::parse().command {
+        return cli::run(command);
+    }
+
+    run_editor()
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_editor() -> ExitCode {
+    eprintln!("error: built without the gui feature, so there is no editor window. Give a command, see --help");
+    ExitCode::FAILURE
+}
+
+#[cfg(feature = "gui")]
+fn run_editor() -> ExitCode {
+    let mut editor = Editor::new();
 
     let (event_loop, window) = create_window();
     let (mut winit_platform, mut imgui_context) = imgui_init(&window);
@@ -97,9 +153,49 @@ fn main() {
     let mut win_size = [800f32, 600f32];
     let mut current_tile = (0u32, 0u32);
     let mut property_select = PropertySelect::None;
+    let mut marker_edit: Option<Marker> = None;
+    let mut entity_edit: Option<Entity> = None;
+    let mut object_layer_name: Option<String> = None;
+    let mut layer_edit: Option<LayerSettings> = None;
+    // Offset from the mouse to the entity being dragged
+    let mut entity_drag: Option<[f32; 2]> = None;
+    let mut entity_property_key = String::new();
+    let mut entity_property_type = 0;
+    let mut prefab_name = String::new();
+    // Prefab being dragged from the Hierarchy into the viewport
+    let mut prefab_drag: Option<usize> = None;
+    let mut show_problems = false;
+    let mut show_collision = true;
+    let mut show_merged_collision = false;
+    let mut animation_time = 0.0;
+    let mut animations_paused = false;
+    let mut animation_cell = [0, 0];
+    let mut terrain_brush: Option<(usize, usize)> = None;
+    let mut terrain_select = 0;
+    let mut rule_mask = 0u8;
+    let mut rule_cell = [0, 0];
+    let mut property_cell = [0, 0];
+    let mut property_key = String::new();
+    let mut property_type = 0;
+    let mut collision_cell = [0, 0];
+    let mut collision_type = 0;
+    let mut trace_threshold = 127;
+    let mut trace_tolerance = 1.0;
+    let mut tool = Tool::Brush;
+    let mut tool_anchor: Option<((i32, i32), bool)> = None;
+    let mut fill_contiguous = true;
+    let mut fill_max_area = 4096;
+    let mut stamp: Option<Stamp> = None;
+    let mut stamp_cell: Option<(i32, i32)> = None;
+    let mut palette_anchor: Option<(u32, u32)> = None;
+    let mut palette_end = (0u32, 0u32);
+    let mut capture_layers = 1;
+    let mut brush_flip_x = false;
+    let mut brush_flip_y = false;
+    let mut brush_rotate = false;
     let mut marker_spr = Sprite::new("lilah__editor__internal__ignore__marker_icon.png");
-    app.load_texture_internal(ig_renderer.gl_context(), "marker_icon.png");
-    marker_spr.load(ig_renderer.gl_context(), &program, &app.textures);
+    editor.load_texture_internal(ig_renderer.gl_context(), "marker_icon.png");
+    marker_spr.load(ig_renderer.gl_context(), &program, &editor.textures);
 
     let yellow = [1.0, 0.886, 0.482,1.0];
     let light_yellow = [1.0, 0.933, 0.698,1.0];
@@ -169,6 +265,26 @@ fn main() {
                     }
                 }
 
+                if !ui.io().want_text_input && !ui.io().key_ctrl {
+                    if ui.is_key_pressed(imgui::Key::X) {
+                        brush_flip_x = !brush_flip_x;
+                    }
+                    if ui.is_key_pressed(imgui::Key::Y) {
+                        brush_flip_y = !brush_flip_y;
+                    }
+                    if ui.is_key_pressed(imgui::Key::R) {
+                        brush_rotate = !brush_rotate;
+                    }
+                }
+
+                if !ui.io().want_text_input && ui.io().key_ctrl && ui.is_key_pressed(imgui::Key::Z) {
+                    if ui.io().key_shift {
+                        editor.redo(ig_renderer.gl_context(), &program);
+                    } else {
+                        editor.undo(ig_renderer.gl_context(), &program);
+                    }
+                }
+
                 // let prev_zoom = camera_zoom;
                 // camera_zoom += ui.io().mouse_wheel*0.05;
                 // if prev_zoom != camera_zoom {
@@ -182,10 +298,11 @@ fn main() {
                 //     }
                 // }
 
-                if let PropertySelect::Marker(marker) = property_select {
-                    if let Some(scene) = app.current_scene.as_mut() {  
-                        if let Some(marker) = scene.markers.get_mut(marker) {
+                if let PropertySelect::Marker(marker_index) = property_select {
+                    if let Some(scene) = editor.app.current_scene.as_mut() {  
+                        if let Some(marker) = scene.markers.get_mut(marker_index) {
                             if ui.is_mouse_clicked(imgui::MouseButton::Left) { 
+                                marker_edit = Some(marker.clone());
                                 let mouse_pos = Vec2::from_slice(&ui.io().mouse_pos);
 
                                 let model = 
@@ -204,7 +321,7 @@ fn main() {
                                 let position = Vec2::new(position_v3.x, position_v3.y);
 
                                 
-                                if application::aabb(
+                                if aabb(
                                     (position)-Vec2::new(3.0,-3.0), 
                                     Vec2::new(6.0,6.0), 
                                     Vec2::new(marker.position[0]-3.0, marker.position[1]+50.0), 
@@ -213,7 +330,7 @@ fn main() {
                                     last_click = position;
                                 }
 
-                                if application::aabb(
+                                if aabb(
                                     (position)-Vec2::new(3.0,-3.0), 
                                     Vec2::new(6.0,6.0), 
                                     Vec2::new(marker.position[0]+14.0, marker.position[1]+3.0), 
@@ -241,7 +358,7 @@ fn main() {
                                 let position_v3 = mvp.to_scale_rotation_translation().2;
                                 let position = Vec2::new(position_v3.x, position_v3.y);
 
-                                if application::aabb(
+                                if aabb(
                                     (position)-Vec2::new(10.0,-10.0), 
                                     Vec2::new(20.0,20.0), 
                                     Vec2::new(marker.position[0]-10.0, marker.position[1]+10.0), 
@@ -251,22 +368,92 @@ fn main() {
                                     marker.position[1] = position.y;
                                 }
                             }
+
+                            if ui.is_mouse_released(imgui::MouseButton::Left) {
+                                if let Some(before) = marker_edit.take() {
+                                    if before != *marker {
+                                        editor.app.history.push(Edit::ChangeMarker { index: marker_index, before, after: marker.clone() });
+                                    }
+                                }
+                            }
                         }
                     }
                 }
 
                 unsafe { ig_renderer.gl_context().clear(glow::COLOR_BUFFER_BIT) };
 
-                for sprs in &app.sprite_buffer {
-                    for spr in sprs {
-                        spr.1.draw(ig_renderer.gl_context(), &program, &app.textures);
+                if !animations_paused {
+                    animation_time += ui.io().delta_time as f64;
+                }
+                editor.animate(ig_renderer.gl_context(), animation_time);
+
+                // Layers are drawn through the view moved by their offset and parallax
+                let view = unsafe { *crate::renderer::VIEW_MATRIX };
+                let layer_view = |layer: Option<&Layer>| {
+                    let shift = layer.map_or([0.0, 0.0], |l| l.shift([camera.x, camera.y]));
+                    view * Mat4::from_translation(Vec3::new(shift[0], shift[1], 0.0))
+                };
+                editor.cull(ig_renderer.gl_context(), &program, camera, Vec2::new(window_size.0, window_size.1));
+                let orientation = editor.app.current_scene.as_ref().map_or(Orientation::Orthogonal, |s| s.orientation);
+                // Off orthogonal grids tiles overlap, and the ones lower on screen go on top
+                let in_order = |sprites: &mut Vec<&mut Sprite>| {
+                    if !orientation.is_orthogonal() {
+                        sprites.sort_by(|a, b| b.position.y.total_cmp(&a.position.y).then(a.position.x.total_cmp(&b.position.x)));
+                    }
+                };
+                for (i, chunks) in editor.sprite_buffer.iter_mut().enumerate() {
+                    let layer = editor.app.current_scene.as_ref().and_then(|s| s.layers.get(i));
+                    let color = layer.map_or([1.0; 4], |l| l.color());
+                    unsafe { *crate::renderer::VIEW_MATRIX = layer_view(layer) };
+                    let mut sprites = chunks.values_mut().flat_map(|c| c.values_mut()).collect::<Vec<&mut Sprite>>();
+                    in_order(&mut sprites);
+                    for spr in sprites {
+                        spr.tint = Color::new(color[0], color[1], color[2], color[3]);
+                        spr.draw(ig_renderer.gl_context(), &program, &editor.textures);
                     }
                 }
+                let current_layer = editor.app.current_scene.as_ref().and_then(|s| s.layers.get(editor.app.current_layer));
+                unsafe { *crate::renderer::VIEW_MATRIX = layer_view(current_layer) };
+                let mut sprites = editor.preview_sprites.values_mut().collect::<Vec<&mut Sprite>>();
+                in_order(&mut sprites);
+                for spr in sprites {
+                    spr.draw(ig_renderer.gl_context(), &program, &editor.textures);
+                }
+                unsafe { *crate::renderer::VIEW_MATRIX = view };
+
+                editor.sync_entity_sprites(ig_renderer.gl_context(), &program);
+                if let Some(scene) = editor.app.current_scene.as_ref() {
+                    for (l, layer) in scene.object_layers.iter().enumerate().filter(|(_, l)| l.visible) {
+                        for e in layer.draw_order() {
+                            match editor.entity_sprites.get(&(l, e)) {
+                                Some(spr) => spr.draw(ig_renderer.gl_context(), &program, &editor.textures),
+                                None => {
+                                    // Entities without a sprite still need something to click on
+                                    let corners = layer.entities[e].corners(&scene.tile_sheets, &editor.app.config.prefabs);
+                                    for i in 0..4 {
+                                        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                                        Line::draw(ig_renderer.gl_context(), &line_program, Vec2::new(a[0], a[1]), Vec2::new(b[0], b[1]), &[0.0,1.0,1.0,1.0]);
+                                    }
+                                }
+                            }
+                        }
+                    }
 
-                if let Some(scene) = app.current_scene.as_ref() {
+                    if let PropertySelect::Entity(l, e) = property_select {
+                        if let Some(entity) = scene.object_layers.get(l).and_then(|l| l.entities.get(e)) {
+                            let corners = entity.corners(&scene.tile_sheets, &editor.app.config.prefabs);
+                            for i in 0..4 {
+                                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                                Line::draw(ig_renderer.gl_context(), &line_program, Vec2::new(a[0], a[1]), Vec2::new(b[0], b[1]), &[1.0,1.0,0.0,1.0]);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(scene) = editor.app.current_scene.as_ref() {
                     for marker in scene.markers.iter().enumerate() {
                         marker_spr.position = Vec2::new(marker.1.position[0], marker.1.position[1]+30.0);
-                        marker_spr.draw(ig_renderer.gl_context(), &program, &app.textures);
+                        marker_spr.draw(ig_renderer.gl_context(), &program, &editor.textures);
                         if let PropertySelect::Marker(m) = property_select {
                             if m == marker.0 {
                                 Line::draw(
@@ -288,38 +475,119 @@ fn main() {
                         }
                     }
 
-                    let sheet = scene.tile_sheets.iter()
-                        .find(|&a| a.path == app.get_tile_sheet());
+                    if show_collision {
+                        let sheets = scene.tile_sheets.iter().map(|s| (&s.path, s)).collect::<HashMap<&String, &TileSheet>>();
+                        let view = Vec2::new(window_size.0, window_size.1) / 2.0;
+
+                        for layer in scene.layers.iter().filter(|l| l.visible) {
+                            let centre = camera - Vec2::from(layer.shift([camera.x, camera.y]));
+                            let (min, max) = layer.cell_range(scene.orientation, (centre - view).into(), (centre + view).into());
+                            let chunks = layer.tiles.chunks_in((min.0 - 1, min.1 - 1), (max.0 + 1, max.1 + 1));
+                            for (cell, tile) in chunks.iter().filter_map(|c| layer.tiles.chunk(c)).flatten() {
+                                let Some(sheet) = sheets.get(&tile.sheet) else { continue };
+                                let Some(shapes) = sheet.collision.get(&tile.sheet_id) else { continue };
+
+                                let size = match tile.rotate {
+                                    true => Vec2::new(sheet.tile_size.1 as f32, sheet.tile_size.0 as f32),
+                                    false => Vec2::new(sheet.tile_size.0 as f32, sheet.tile_size.1 as f32)
+                                };
+                                let position = Vec2::from(layer.cell_position(scene.orientation, *cell)) + Vec2::from(layer.shift([camera.x, camera.y]));
+                                if ((position - camera).abs() - size).cmpgt(view).any() {
+                                    continue;
+                                }
+
+                                // Shapes are y down from the top left, the viewport is y up from the centre
+                                let top_left = position + Vec2::new(-size.x, size.y) / 2.0;
+                                let world = |p: [f32; 2]| {
+                                    let p = collision::orient(p, sheet.tile_size, tile);
+                                    top_left + Vec2::new(p[0], -p[1])
+                                };
+                                for shape in shapes {
+                                    let color = match shape {
+                                        CollisionShape::OneWay { .. } => [0.3, 0.8, 1.0, 1.0],
+                                        _ => [0.3, 1.0, 0.3, 1.0]
+                                    };
+                                    for (start, end) in shape.edges(sheet.tile_size) {
+                                        Line::draw(ig_renderer.gl_context(), &line_program, world(start), world(end), &color);
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    if show_merged_collision {
+                        let orange = [1.0, 0.6, 0.1, 1.0];
+                        let merged_collision = editor.merged_collision.get_or_insert_with(|| collision::merge(scene, scene.collision_outlines));
+                        for merged in merged_collision.iter() {
+                            // The merge has the layer's offset in it already, parallax only moves the drawing
+                            let parallax = scene.layers.get(merged.layer)
+                                .map_or(Vec2::ZERO, |l| Vec2::from(l.shift([camera.x, camera.y])) - Vec2::from(l.offset));
+                            for &[x, y, w, h] in &merged.rects {
+                                let (min, max) = (Vec2::new(x, y) + parallax, Vec2::new(x + w, y + h) + parallax);
+                                for (start, end) in [
+                                    (min, Vec2::new(max.x, min.y)),
+                                    (Vec2::new(max.x, min.y), max),
+                                    (max, Vec2::new(min.x, max.y)),
+                                    (Vec2::new(min.x, max.y), min)
+                                ] {
+                                    Line::draw(ig_renderer.gl_context(), &line_program, start, end, &orange);
+                                }
+                            }
+                            for outline in &merged.outlines {
+                                for i in 0..outline.len() {
+                                    let (start, end) = (outline[i], outline[(i + 1) % outline.len()]);
+                                    Line::draw(ig_renderer.gl_context(), &line_program, Vec2::from(start) + parallax, Vec2::from(end) + parallax, &[1.0, 0.3, 1.0, 1.0]);
+                                }
+                            }
+                        }
+                    }
+
+                    let layer = scene.layers.get(editor.app.current_layer)
+                        .filter(|l| l.grid_size.0 != 0 && l.grid_size.1 != 0);
 
-                    if let Some(sheet) = sheet {
-                        let size = sheet.tile_size;
+                    if let Some(layer) = layer {
+                        let size = layer.grid_size;
 
-                        let mut offset = Vec2::new(camera.x.rem_euclid(size.0 as f32)-(size.0 as f32/2.0), camera.y.rem_euclid(size.1 as f32)-(size.1 as f32/2.0));
+                        if !editor.preview.is_empty() {
+                            let half = Vec2::new(size.0 as f32/2.0, size.1 as f32/2.0);
+                            let keys = editor.preview.iter().map(|(p, _)| Vec2::from(layer.cell_position(scene.orientation, *p)));
 
-                        let w =  window_size.0 / size.0 as f32;
-                        let h =  window_size.1 / size.1 as f32;
+                            match scene.orientation.is_orthogonal() {
+                                true => {
+                                    let min = keys.clone().fold(Vec2::splat(f32::MAX), Vec2::min) - half;
+                                    let max = keys.fold(Vec2::splat(f32::MIN), Vec2::max) + half;
 
-                        offset -= camera;
+                                    for (start, end) in [
+                                        (min, Vec2::new(max.x, min.y)),
+                                        (Vec2::new(max.x, min.y), max),
+                                        (max, Vec2::new(min.x, max.y)),
+                                        (Vec2::new(min.x, max.y), min)
+                                    ] {
+                                        Line::draw(ig_renderer.gl_context(), &line_program, start, end, &yellow);
+                                    }
+                                }
+                                false => {
+                                    // Staggered rows don't make a rectangle, so outline the cells themselves
+                                    for (p, _) in editor.preview.iter().take(1024) {
+                                        let outline = scene.orientation.cell_outline(*p, size);
+                                        for i in 0..outline.len() {
+                                            let (start, end) = (outline[i], outline[(i + 1) % outline.len()]);
+                                            Line::draw(ig_renderer.gl_context(), &line_program, Vec2::from(start), Vec2::from(end), &yellow);
+                                        }
+                                    }
+                                }
+                            }
 
-                        for i in 0..(w as i32 * 2) {
-                            Line::draw(
-                                ig_renderer.gl_context(), 
-                                &line_program, 
-                                Vec2::new( size.0 as f32 * (i-w as i32) as f32, -h * size.1 as f32) - offset,
-                                Vec2::new( size.0 as f32 * (i-w as i32) as f32, window_size.1) - offset,
-                                &[1.0,1.0,1.0,0.25]
-                            );
+                            // Strike out what would be erased, a line per tile gets slow on big fills
+                            for (p, _) in editor.preview.iter().filter(|(_, tile)| tile.is_none()).take(1024) {
+                                let p = Vec2::from(layer.cell_position(scene.orientation, *p));
+                                Line::draw(ig_renderer.gl_context(), &line_program, p - half, p + half, &[1.0, 0.3, 0.3, 1.0]);
+                            }
                         }
 
-                        for i in 0..(h as i32 * 2) {
-                            
-                            Line::draw(
-                                ig_renderer.gl_context(), 
-                                &line_program, 
-                                Vec2::new(-w * size.0 as f32, size.1 as f32 * (i-h as i32) as f32) - offset,
-                                Vec2::new(window_size.0, size.1 as f32 * (i-h as i32) as f32) - offset,
-                                &[1.0,1.0,1.0,0.25]
-                            );
+                        let view = Vec2::new(window_size.0, window_size.1) / 2.0;
+                        for (start, end) in scene.orientation.grid_lines((camera - view).into(), (camera + view).into(), size) {
+                            Line::draw(ig_renderer.gl_context(), &line_program, Vec2::from(start), Vec2::from(end), &[1.0,1.0,1.0,0.25]);
                         }
 
                         Line::draw(
@@ -343,23 +611,49 @@ fn main() {
                 if let Some(main_menu) = ui.begin_main_menu_bar() {
                     if let Some(_) = ui.begin_menu("File") {
                         if ui.menu_item("New") {
-                            window.window().set_title(app.new_project());
+                            window.window().set_title(editor.new_project());
                         }
                         if ui.menu_item("Open") {
-                            window.window().set_title(app.open_project());
-                            win_size = [app.config.window_size.0, app.config.window_size.1]; 
+                            window.window().set_title(editor.open_project());
+                            win_size = [editor.app.config.window_size.0, editor.app.config.window_size.1]; 
                         }
-                        if app.current_project != "" {
+                        if editor.app.current_project != "" {
                             if ui.menu_item("Save") {
-                                app.wrangle_main();
-                                app.write_config();
+                                if let Err(e) = editor.app.wrangle_main() {
+                                    editor.report(e);
+                                }
+                                if let Err(e) = editor.app.write_config() {
+                                    editor.report(e);
+                                }
                             }
                         }
                     }
-                    if app.current_project != "" {
+                    if editor.app.current_project != "" {
+                        if let Some(_) = ui.begin_menu("Edit") {
+                            let undo_label = match editor.app.history.last_undo() {
+                                Some(edit) => format!("Undo {}", edit.name()),
+                                None => String::from("Undo")
+                            };
+                            if ui.menu_item_config(undo_label).shortcut("Ctrl+Z").enabled(editor.app.history.can_undo()).build() {
+                                editor.undo(ig_renderer.gl_context(), &program);
+                            }
+                            let redo_label = match editor.app.history.last_redo() {
+                                Some(edit) => format!("Redo {}", edit.name()),
+                                None => String::from("Redo")
+                            };
+                            if ui.menu_item_config(redo_label).shortcut("Ctrl+Shift+Z").enabled(editor.app.history.can_redo()).build() {
+                                editor.redo(ig_renderer.gl_context(), &program);
+                            }
+                        }
                         if let Some(_) = ui.begin_menu("Project") {
                             if ui.menu_item("Run") {
-                                app.run_project();
+                                if let Err(e) = editor.app.run_project() {
+                                    editor.report(e);
+                                }
+                            }
+                            if ui.menu_item(format!("Problems ({})", editor.problems.len())) {
+                                editor.check();
+                                show_problems = true;
                             }
                             if let Some(_) = ui.begin_menu("Settings") {
                                 if ui.menu_item("Window Size") {
@@ -369,24 +663,75 @@ fn main() {
                             if let Some(_) = ui.begin_menu("Assets") {
                                 if let Some(_) = ui.begin_menu("Add") {
                                     if ui.menu_item("External") {
-                                        app.add_external_asset();
+                                        editor.add_external_asset();
                                     }
                                     if ui.menu_item("Embedded") {
-                                        app.add_embedded_asset();
+                                        editor.add_embedded_asset();
                                     }
                                 }
                             }
                         }
                         if let Some(_) = ui.begin_menu("World") {
                             if ui.menu_item("New") {
-                                app.new_scene();
+                                editor.new_scene(ig_renderer.gl_context());
                             }
                             if ui.menu_item("Open") {
-                                app.open_scene(ig_renderer.gl_context(), &program);
+                                editor.open_scene(ig_renderer.gl_context());
                             }
-                            if let Some(_) = app.current_scene.as_ref() {
+                            if let Some(_) = ui.begin_menu("Import") {
+                                if ui.menu_item("Tiled") {
+                                    editor.import_tiled(ig_renderer.gl_context());
+                                }
+                                if ui.menu_item("LDtk") {
+                                    editor.import_ldtk(ig_renderer.gl_context());
+                                }
+                            }
+                            if let Some(_) = editor.app.current_scene.as_ref() {
+                                if let Some(_) = ui.begin_menu("Export") {
+                                    if ui.menu_item("Tiled (.tmj)") {
+                                        editor.export_tiled(TiledFormat::Tmj);
+                                    }
+                                    if ui.menu_item("Tiled (.tmx)") {
+                                        editor.export_tiled(TiledFormat::Tmx);
+                                    }
+                                    if ui.menu_item("Scene json, grid cells") {
+                                        editor.export_json(Coordinates::Grid);
+                                    }
+                                    if ui.menu_item("Scene json, pixels") {
+                                        editor.export_json(Coordinates::Pixel);
+                                    }
+                                }
+                            }
+                            if ui.menu_item_config("Show Collision").selected(show_collision).build() {
+                                show_collision = !show_collision;
+                            }
+                            if ui.menu_item_config("Show Merged Collision").selected(show_merged_collision).build() {
+                                show_merged_collision = !show_merged_collision;
+                            }
+                            if ui.menu_item_config("Pause Animations").selected(animations_paused).build() {
+                                animations_paused = !animations_paused;
+                            }
+                            if let Some(scene) = editor.app.current_scene.as_mut() {
+                                if ui.menu_item_config("Save Collision Outlines").selected(scene.collision_outlines).build() {
+                                    scene.collision_outlines = !scene.collision_outlines;
+                                    editor.merged_collision = None;
+                                }
+                            }
+                            if let Some(scene) = editor.app.current_scene.as_ref() {
+                                let current = scene.orientation;
+                                if let Some(_) = ui.begin_menu("Orientation") {
+                                    for orientation in Orientation::ALL {
+                                        if ui.menu_item_config(orientation.name()).selected(orientation == current).build() {
+                                            editor.set_orientation(ig_renderer.gl_context(), orientation);
+                                        }
+                                    }
+                                }
+                            }
+                            if let Some(_) = editor.app.current_scene.as_ref() {
                                 if ui.menu_item("Save") {
-                                    app.write_current_scene();
+                                    if let Err(e) = editor.app.write_current_scene() {
+                                        editor.report(e);
+                                    }
                                 }
                             }
                         }
@@ -405,13 +750,13 @@ fn main() {
 
                         ui.columns(2, "win_size_exit", false);
                         if ui.button("Save") {
-                            app.config.window_size.0 = win_size[0];
-                            app.config.window_size.1 = win_size[1];
+                            editor.app.config.window_size.0 = win_size[0];
+                            editor.app.config.window_size.1 = win_size[1];
                             ui.close_current_popup();
                         }
                         ui.next_column();
                         if ui.button("Close") {
-                            win_size = [app.config.window_size.0, app.config.window_size.1]; 
+                            win_size = [editor.app.config.window_size.0, editor.app.config.window_size.1]; 
                             ui.close_current_popup();
                         }
                         ui.next_column();
@@ -423,7 +768,7 @@ fn main() {
                     main_menu.end();
                 }
                 
-                if app.current_project != "" {
+                if editor.app.current_project != "" {
                     ui.window("Properties")
                     .size(
                         [200.0, 
@@ -445,42 +790,274 @@ fn main() {
                             PropertySelect::None => {}
                             PropertySelect::Marker(marker) => {
                                 if let Some(_) = ui.tab_bar("prop_main") {
-                                    if let Some(scene) = app.current_scene.as_mut() {
+                                    if let Some(scene) = editor.app.current_scene.as_mut() {
                                         if let Some(_) = ui.tab_item("Marker") {
                                             ui.columns(1, "marker_columns", false);
                                             if let Some(m) = scene.markers.get_mut(*marker) {
+                                                let before = m.clone();
                                                 ui.input_text("Name", &mut m.name).build();
+                                                if ui.is_item_activated() {
+                                                    marker_edit = Some(before.clone());
+                                                }
+                                                if ui.is_item_deactivated_after_edit() {
+                                                    if let Some(before) = marker_edit.take() {
+                                                        editor.app.history.push(Edit::ChangeMarker { index: *marker, before, after: m.clone() });
+                                                    }
+                                                }
                                                 ui.input_float2("Pos", &mut m.position).build();
+                                                if ui.is_item_activated() {
+                                                    marker_edit = Some(before);
+                                                }
+                                                if ui.is_item_deactivated_after_edit() {
+                                                    if let Some(before) = marker_edit.take() {
+                                                        editor.app.history.push(Edit::ChangeMarker { index: *marker, before, after: m.clone() });
+                                                    }
+                                                }
                                             }
                                         }
                                     }
                                 }
                             }
                             PropertySelect::Tilesheet(sheet) => {
+                                let mut trace = None;
                                 if let Some(_) = ui.tab_bar("prop_main") {
                                     if let Some(_) = ui.tab_item("Tile Sheet") {
-                                        if let Some(scene) = app.current_scene.as_mut() {
+                                        if let Some(scene) = editor.app.current_scene.as_mut() {
                                             if let Some(tilesheet) = scene.tile_sheets.get_mut(*sheet) {
                                                 ui.text(format!("{}", tilesheet.filename));
                                                 if ui.input_int2("Tiles", &mut tile_count).build() {
                                                     if tile_count[0] != 0 && tile_count[1] != 0 {
+                                                        let (margin, spacing) = (tilesheet.margin * 2, tilesheet.spacing);
                                                         tilesheet.tile_size = (
-                                                            tilesheet.sheet_size.0/tile_count[0] as u32, 
-                                                            tilesheet.sheet_size.1/tile_count[1] as u32
+                                                            ((tilesheet.sheet_size.0.saturating_sub(margin) + spacing)/tile_count[0] as u32).saturating_sub(spacing), 
+                                                            ((tilesheet.sheet_size.1.saturating_sub(margin) + spacing)/tile_count[1] as u32).saturating_sub(spacing)
                                                         );
                                                     }
                                                 }
+
+                                                ui.separator();
+                                                ui.text("Terrains");
+                                                for (i, terrain) in tilesheet.terrains.iter().enumerate() {
+                                                    if ui.selectable_config(format!("{} ({})##terrain{}", terrain.name, terrain.kind.name(), i))
+                                                    .selected(terrain_select == i)
+                                                    .build() {
+                                                        terrain_select = i;
+                                                    }
+                                                }
+                                                if ui.button("Add Terrain") {
+                                                    let name = format!("Terrain {}", tilesheet.terrains.len());
+                                                    tilesheet.terrains.push(Terrain::new(&name, TerrainKind::Blob));
+                                                    terrain_select = tilesheet.terrains.len() - 1;
+                                                }
+
+                                                let mut removed = false;
+                                                if let Some(terrain) = tilesheet.terrains.get_mut(terrain_select) {
+                                                    ui.same_line();
+                                                    removed = ui.button("Remove Terrain");
+
+                                                    ui.input_text("Name", &mut terrain.name).build();
+                                                    let mut kind = TerrainKind::ALL.iter().position(|k| *k == terrain.kind).unwrap_or(0);
+                                                    if ui.combo("Kind", &mut kind, &TerrainKind::ALL, |k| k.name().into()) {
+                                                        // Masks that meant something to the old kind may collapse into one
+                                                        terrain.kind = TerrainKind::ALL[kind];
+                                                        for rule in std::mem::take(&mut terrain.rules) {
+                                                            terrain.set_rule(rule.mask, rule.sheet_id);
+                                                        }
+                                                    }
+
+                                                    ui.text("Neighbours");
+                                                    let grid = [
+                                                        [terrain::NORTH_WEST, terrain::NORTH, terrain::NORTH_EAST],
+                                                        [terrain::WEST, 0, terrain::EAST],
+                                                        [terrain::SOUTH_WEST, terrain::SOUTH, terrain::SOUTH_EAST]
+                                                    ];
+                                                    for row in grid {
+                                                        for (k, bit) in row.iter().enumerate() {
+                                                            if k > 0 {
+                                                                ui.same_line();
+                                                            }
+                                                            if *bit == 0 {
+                                                                ui.dummy([ui.frame_height(), ui.frame_height()]);
+                                                            } else {
+                                                                let mut set = rule_mask & bit != 0;
+                                                                if ui.checkbox(format!("##mask{}", bit), &mut set) {
+                                                                    rule_mask ^= bit;
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+
+                                                    ui.input_int2("Cell", &mut rule_cell).build();
+                                                    ui.same_line();
+                                                    if ui.button("Selected Tile") {
+                                                        rule_cell = [current_tile.0 as i32, current_tile.1 as i32];
+                                                    }
+                                                    let cell = (rule_cell[0].max(0) as u32, rule_cell[1].max(0) as u32);
+                                                    if ui.button("Set Rule") {
+                                                        terrain.set_rule(rule_mask, cell);
+                                                    }
+                                                    ui.same_line();
+                                                    if ui.button("Set 3x3 Block") {
+                                                        terrain.set_block_rules(cell);
+                                                    }
+
+                                                    let mut removed_rule = None;
+                                                    for (r, rule) in terrain.rules.iter().enumerate() {
+                                                        if ui.small_button(format!("x##rule{}", r)) {
+                                                            removed_rule = Some(r);
+                                                        }
+                                                        ui.same_line();
+                                                        if ui.selectable(format!("{} -> {}x{}##rule{}", terrain::describe(rule.mask), rule.sheet_id.0, rule.sheet_id.1, r)) {
+                                                            rule_mask = rule.mask;
+                                                            rule_cell = [rule.sheet_id.0 as i32, rule.sheet_id.1 as i32];
+                                                        }
+                                                    }
+                                                    if let Some(r) = removed_rule {
+                                                        terrain.rules.remove(r);
+                                                    }
+                                                }
+                                                if removed {
+                                                    tilesheet.terrains.remove(terrain_select);
+                                                    terrain_select = terrain_select.saturating_sub(1);
+                                                    terrain_brush = None;
+                                                }
+
+                                                ui.separator();
+                                                ui.text("Tile Properties");
+                                                ui.input_int2("Cell##properties", &mut property_cell).build();
+                                                ui.same_line();
+                                                if ui.button("Selected Tile##properties") {
+                                                    property_cell = [current_tile.0 as i32, current_tile.1 as i32];
+                                                }
+                                                let cell = (property_cell[0].max(0) as u32, property_cell[1].max(0) as u32);
+
+                                                let mut properties = tilesheet.properties.remove(&cell).unwrap_or_default();
+                                                edit_properties(ui, "tile", &mut properties, &mut property_key, &mut property_type);
+                                                if !properties.is_empty() {
+                                                    tilesheet.properties.insert(cell, properties);
+                                                }
+
+                                                ui.separator();
+                                                ui.text("Collision");
+                                                ui.input_int2("Cell##collision", &mut collision_cell).build();
+                                                ui.same_line();
+                                                if ui.button("Selected Tile##collision") {
+                                                    collision_cell = [current_tile.0 as i32, current_tile.1 as i32];
+                                                }
+                                                let cell = (collision_cell[0].max(0) as u32, collision_cell[1].max(0) as u32);
+
+                                                let mut removed_shape = None;
+                                                let mut shapes_changed = false;
+                                                if let Some(shapes) = tilesheet.collision.get_mut(&cell) {
+                                                    for (s, shape) in shapes.iter_mut().enumerate() {
+                                                        if ui.small_button(format!("x##shape{}", s)) {
+                                                            removed_shape = Some(s);
+                                                        }
+                                                        ui.same_line();
+                                                        ui.text(shape.name());
+                                                        match shape {
+                                                            CollisionShape::Box => {}
+                                                            CollisionShape::Rect(rect) => {
+                                                                shapes_changed |= ui.input_float4(format!("X Y W H##shape{}", s), rect).build();
+                                                            }
+                                                            CollisionShape::Slope { left, right } => {
+                                                                shapes_changed |= ui.input_float(format!("Left##shape{}", s), left).build();
+                                                                shapes_changed |= ui.input_float(format!("Right##shape{}", s), right).build();
+                                                            }
+                                                            CollisionShape::OneWay { y } => {
+                                                                shapes_changed |= ui.input_float(format!("Y##shape{}", s), y).build();
+                                                            }
+                                                            CollisionShape::Polygon(points) => {
+                                                                ui.same_line();
+                                                                ui.text(format!("({} points)", points.len()));
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                                if let Some(s) = removed_shape {
+                                                    if let Some(shapes) = tilesheet.collision.get_mut(&cell) {
+                                                        shapes_changed = true;
+                                                        shapes.remove(s);
+                                                        if shapes.is_empty() {
+                                                            tilesheet.collision.remove(&cell);
+                                                        }
+                                                    }
+                                                }
+
+                                                let defaults = CollisionShape::defaults(tilesheet.tile_size);
+                                                ui.combo("Shape##collision", &mut collision_type, &defaults, |s| s.name().into());
+                                                if ui.button("Add Shape") {
+                                                    tilesheet.collision.entry(cell).or_default().push(defaults[collision_type].clone());
+                                                    shapes_changed = true;
+                                                }
+                                                if shapes_changed {
+                                                    editor.merged_collision = None;
+                                                }
+
+                                                ui.input_int("Alpha Threshold", &mut trace_threshold).build();
+                                                ui.input_float("Tolerance", &mut trace_tolerance).build();
+                                                if ui.button("Trace Alpha") {
+                                                    trace = Some(cell);
+                                                }
+
+                                                ui.separator();
+                                                ui.text("Animation");
+                                                ui.input_int2("Cell##animation", &mut animation_cell).build();
+                                                ui.same_line();
+                                                if ui.button("Selected Tile##animation") {
+                                                    animation_cell = [current_tile.0 as i32, current_tile.1 as i32];
+                                                }
+                                                let cell = (animation_cell[0].max(0) as u32, animation_cell[1].max(0) as u32);
+
+                                                let mut removed_frame = None;
+                                                if let Some(frames) = tilesheet.animations.get_mut(&cell) {
+                                                    for (f, frame) in frames.iter_mut().enumerate() {
+                                                        if ui.small_button(format!("x##frame{}", f)) {
+                                                            removed_frame = Some(f);
+                                                        }
+                                                        ui.same_line();
+                                                        let mut frame_cell = [frame.sheet_id.0 as i32, frame.sheet_id.1 as i32];
+                                                        if ui.input_int2(format!("##frame_cell{}", f), &mut frame_cell).build() {
+                                                            frame.sheet_id = (frame_cell[0].max(0) as u32, frame_cell[1].max(0) as u32);
+                                                        }
+                                                        let mut duration = frame.duration as i32;
+                                                        if ui.input_int(format!("ms##frame{}", f), &mut duration).build() {
+                                                            frame.duration = duration.max(0) as u32;
+                                                        }
+                                                    }
+                                                    ui.text(format!("{}ms a loop", animation::length(frames)));
+                                                }
+                                                if let Some(f) = removed_frame {
+                                                    if let Some(frames) = tilesheet.animations.get_mut(&cell) {
+                                                        frames.remove(f);
+                                                        if frames.is_empty() {
+                                                            tilesheet.animations.remove(&cell);
+                                                        }
+                                                    }
+                                                }
+                                                if ui.button("Add Selected Tile as Frame") {
+                                                    tilesheet.animations.entry(cell).or_default().push(Frame { sheet_id: current_tile, duration: 100 });
+                                                }
                                             }
                                         }
                                     }
                                 }
+                                if let Some(cell) = trace {
+                                    editor.trace_collision(*sheet, cell, trace_threshold.clamp(0, 255) as u8, trace_tolerance);
+                                }
                             }
                             PropertySelect::Layer => {
                                 if let Some(_) = ui.tab_bar("prop_main") {
                                     if let Some(_) = ui.tab_item("Layer") {
-                                        if let Some(scene) = app.current_scene.as_mut() {
-                                            if let Some(layer) = scene.layers.get_mut(app.current_layer) {
+                                        if let Some(scene) = editor.app.current_scene.as_mut() {
+                                            if let Some(layer) = scene.layers.get_mut(editor.app.current_layer) {
+                                                let before = layer.settings();
                                                 ui.columns(2, "Properties", true);
+                                                ui.text("Name");
+                                                ui.next_column();
+                                                ui.input_text("##layer_name", &mut layer.name).hint(format!("Layer {}", editor.app.current_layer)).build();
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
                                                 ui.text("Tile Sheet");
                                                 ui.next_column();
                                                 if let Some(tilesheet) = scene.tile_sheets.get(layer.current_tile_item as usize) {
@@ -489,36 +1066,79 @@ fn main() {
                                                     }
                                                 }
                                                 ui.next_column();
+                                                ui.text("Grid Size");
+                                                ui.next_column();
+                                                let mut grid_size = [layer.grid_size.0 as i32, layer.grid_size.1 as i32];
+                                                if ui.input_int2("##layer_grid_size", &mut grid_size).build() {
+                                                    layer.grid_size = (grid_size[0].max(1) as u32, grid_size[1].max(1) as u32);
+                                                    editor.merged_collision = None;
+                                                    // Tiles keep their cells and move with the grid, `cull` builds them where they are now
+                                                    if let Some(buffer) = editor.sprite_buffer.get_mut(editor.app.current_layer) {
+                                                        for sprite in buffer.values_mut().flat_map(|c| c.values_mut()) {
+                                                            sprite.unload(ig_renderer.gl_context());
+                                                        }
+                                                        buffer.clear();
+                                                    }
+                                                }
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
                                                 ui.text("Tile Count");
                                                 ui.next_column();
-                                                ui.text(format!("{}", layer.tiles.keys().len()));
+                                                ui.text(format!("{}", layer.tiles.len()));
+                                                ui.next_column();
+                                                ui.text("Sheets Used");
+                                                ui.next_column();
+                                                for sheet in &scene.tile_sheets {
+                                                    let count = layer.tiles.values().filter(|t| t.sheet == sheet.path).count();
+                                                    if count > 0 {
+                                                        ui.text(format!("{} ({})", sheet.filename, count));
+                                                    }
+                                                }
                                                 ui.next_column();
                                                 ui.text("Collision");
                                                 ui.next_column();
-                                                if layer.collision {
-                                                    ui.checkbox("enabled", &mut layer.collision);
-                                                } else {
-                                                    ui.checkbox("disabled", &mut layer.collision);
+                                                let label = if layer.collision { "enabled" } else { "disabled" };
+                                                if ui.checkbox(label, &mut layer.collision) {
+                                                    editor.merged_collision = None;
+                                                }
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
+                                                ui.text("Locked");
+                                                ui.next_column();
+                                                ui.checkbox("##layer_locked", &mut layer.locked);
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
+                                                ui.text("Opacity");
+                                                ui.next_column();
+                                                ui.slider("##layer_opacity", 0.0, 1.0, &mut layer.opacity);
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
+                                                ui.text("Tint");
+                                                ui.next_column();
+                                                ui.color_edit4("##layer_tint", &mut layer.tint);
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
+                                                ui.text("Offset");
+                                                ui.next_column();
+                                                if ui.input_float2("##layer_offset", &mut layer.offset).build() {
+                                                    editor.merged_collision = None;
                                                 }
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
+                                                ui.next_column();
+                                                ui.text("Parallax");
+                                                ui.next_column();
+                                                ui.input_float2("##layer_parallax", &mut layer.parallax).build();
+                                                record_layer_edit(ui, &mut editor.app.history, &mut layer_edit, editor.app.current_layer, &before, layer);
                                                 ui.next_column();
 
                                                 let win_color = ui.push_style_color(imgui::StyleColor::PopupBg, [0.129, 0.129, 0.125, 0.9]);
                                                 if let Some(_) = ui.begin_popup("TileSheetPopup") { 
                                                     let list = scene.tile_sheets.iter().map(|TileSheet { ref filename, .. }| filename.as_str()).collect::<Vec<&str>>();
                                                     let list2 = scene.tile_sheets.iter().map(|TileSheet { ref path, .. }| path.as_str()).collect::<Vec<&str>>();
+                                                    // Only what gets painted next changes, tiles keep their own sheets
                                                     if ui.list_box("Tile Sheet", &mut layer.current_tile_item, list.as_slice(), list.len() as i32) {
                                                         layer.tile_sheet = list2[layer.current_tile_item as usize].to_string();
-                                                        app.current_tile_sheet = list2[layer.current_tile_item as usize].to_string();
-                                                        for j in &mut layer.tiles {
-                                                            if let Some(buffer) = app.sprite_buffer.get_mut(app.current_layer) {
-                                                                if let Some(spr) = buffer.get_mut(
-                                                                    j.0
-                                                                ) {
-                                                                    spr.texture_id = list2[layer.current_tile_item as usize].to_string();
-                                                                }
-                                                            }
-                                                            j.1.sheet = layer.tile_sheet.clone(); 
-                                                        } 
+                                                        editor.app.current_tile_sheet = list2[layer.current_tile_item as usize].to_string();
                                                         ui.close_current_popup();
                                                     }
                                                 }
@@ -527,58 +1147,127 @@ fn main() {
                                         }
                                     }
                                     if let Some(_) = ui.tab_item("Tiles") {
-                                        if let Some(scene) = app.current_scene.as_ref() {
+                                        let mut save_stamp = false;
+                                        if let Some(s) = stamp.as_mut() {
+                                            ui.input_text("Stamp", &mut s.name).build();
+                                            save_stamp = ui.button("Save to Library");
+                                            ui.same_line();
+                                            if ui.button("Clear Stamp") {
+                                                stamp = None;
+                                            }
+                                        }
+                                        if let (true, Some(s)) = (save_stamp, stamp.clone()) {
+                                            if let Err(e) = editor.app.save_brush(s) {
+                                                editor.report(e);
+                                            }
+                                        }
+
+                                        let mut picked_brush = None;
+                                        let mut removed_brush = None;
+                                        if !editor.app.config.brushes.is_empty() {
+                                            ui.text("Library");
+                                            for (b, brush) in editor.app.config.brushes.iter().enumerate() {
+                                                if ui.small_button(format!("x##brush_library{}", b)) {
+                                                    removed_brush = Some(b);
+                                                }
+                                                ui.same_line();
+                                                if ui.selectable(format!("{}##brush_library{}", brush.name, b)) {
+                                                    picked_brush = Some(brush.clone());
+                                                }
+                                            }
+                                        }
+                                        if let Some(brush) = picked_brush {
+                                            if let Some(scene) = editor.app.current_scene.as_ref() {
+                                                for sheet in brush.missing_sheets(&scene.tile_sheets) {
+                                                    editor.report(format!("{}: {} isn't in this scene, its tiles won't be painted", brush.name, sheet));
+                                                }
+                                            }
+                                            stamp = Some(brush);
+                                            tool = Tool::Brush;
+                                            terrain_brush = None;
+                                        }
+                                        if let Some(b) = removed_brush {
+                                            if let Err(e) = editor.app.remove_brush(b) {
+                                                editor.report(e);
+                                            }
+                                        }
+                                        if stamp.is_some() || !editor.app.config.brushes.is_empty() {
+                                            ui.separator();
+                                        }
+
+                                        if let Some(scene) = editor.app.current_scene.as_ref() {
                                             let sheet = scene.tile_sheets.iter()
-                                                .find(|&a| a.path == app.get_tile_sheet());
+                                                .find(|&a| a.path == editor.app.get_tile_sheet());
                                             
                                             if let Some(sheet) = sheet {
+                                                let mut tool_index = Tool::ALL.iter().position(|t| *t == tool).unwrap_or(0);
+                                                if ui.combo("Tool", &mut tool_index, &Tool::ALL, |t| t.name().into()) {
+                                                    tool = Tool::ALL[tool_index];
+                                                    if tool != Tool::Brush {
+                                                        terrain_brush = None;
+                                                    }
+                                                }
+                                                if tool == Tool::Fill {
+                                                    ui.checkbox("Contiguous", &mut fill_contiguous);
+                                                    ui.input_int("Max Area", &mut fill_max_area).build();
+                                                }
+                                                if tool == Tool::Capture {
+                                                    // Counted up from the current layer
+                                                    ui.input_int("Layers", &mut capture_layers).build();
+                                                }
+                                                ui.checkbox("Flip X (X)", &mut brush_flip_x);
+                                                ui.same_line();
+                                                ui.checkbox("Flip Y (Y)", &mut brush_flip_y);
+                                                ui.same_line();
+                                                ui.checkbox("Rotate 90 (R)", &mut brush_rotate);
+                                                ui.separator();
+
+                                                let sheet_index = scene.tile_sheets.iter().position(|a| a.path == sheet.path).unwrap_or(0);
+                                                for (t, terrain) in sheet.terrains.iter().enumerate() {
+                                                    if ui.selectable_config(format!("{}##brush{}", terrain.name, t))
+                                                    .selected(terrain_brush == Some((sheet_index, t)))
+                                                    .build() {
+                                                        terrain_brush = Some((sheet_index, t));
+                                                        tool = Tool::Brush;
+                                                        stamp = None;
+                                                    }
+                                                }
+                                                if !sheet.terrains.is_empty() {
+                                                    ui.separator();
+                                                }
+
                                                 let tile_wh = sheet.get_num_of_tiles();
                                                 
                                                 ui.columns(tile_wh.0 as i32, "tile_cols", false);
                                                 for i in 0..tile_wh.1 {
                                                     for j in 0..tile_wh.0 {
-                                                        if ui.selectable(format!("{}x{}", j, i).to_string()) {
-                                                            current_tile = (j as u32, i as u32);
-                                                        }
+                                                        // Dragging across the palette picks a block of cells as a stamp
+                                                        let selected = match palette_anchor {
+                                                            Some(anchor) => {
+                                                                (anchor.0.min(palette_end.0)..=anchor.0.max(palette_end.0)).contains(&j) &&
+                                                                (anchor.1.min(palette_end.1)..=anchor.1.max(palette_end.1)).contains(&i)
+                                                            }
+                                                            None => stamp.is_none() && terrain_brush.is_none() && current_tile == (j, i)
+                                                        };
+                                                        ui.selectable_config(format!("{}x{}", j, i)).selected(selected).build();
+                                                        let hovered = ui.is_item_hovered();
 
                                                         ui.invisible_button("mock_btn_for_tile_img", [25.0, 25.0]);
-                                                        let min = ui.item_rect_min();
-                                                        let max = ui.item_rect_max();
-
-                                                        let ratio = (
-                                                            ((sheet.sheet_size.0 as f32/tile_wh.0 as f32)/sheet.sheet_size.0 as f32),
-                                                            ((sheet.sheet_size.1 as f32/tile_wh.1 as f32)/sheet.sheet_size.1 as f32),
-                                                        );
-
-                                                        fn precision_f32(x: f32, decimals: u32) -> f32 {
-                                                            if x == 0. || decimals == 0 {
-                                                                0.
-                                                            } else {
-                                                                let shift = decimals as i32 - x.abs().log10().ceil() as i32;
-                                                                let shift_factor = 10_f64.powi(shift) as f32;
-
-                                                                (x * shift_factor).round() / shift_factor
+                                                        if hovered || ui.is_item_hovered() {
+                                                            if ui.is_mouse_clicked(imgui::MouseButton::Left) {
+                                                                palette_anchor = Some((j, i));
+                                                            }
+                                                            if ui.is_mouse_down(imgui::MouseButton::Left) {
+                                                                palette_end = (j, i);
                                                             }
                                                         }
+                                                        let min = ui.item_rect_min();
+                                                        let max = ui.item_rect_max();
 
-                                                        let zero = (
-                                                            precision_f32((j as f32) / tile_wh.0 as f32 + (1.0/sheet.sheet_size.0 as f32), 2), 
-                                                            precision_f32((i as f32) / tile_wh.1 as f32 + (1.0/sheet.sheet_size.1 as f32), 2)
-                                                        );
-
-                                                        let one = (
-                                                            precision_f32(zero.0+ratio.0 - (1.0/sheet.sheet_size.0 as f32) * 2.0, 2), 
-                                                            precision_f32(zero.1+ratio.1 - (1.0/sheet.sheet_size.1 as f32) * 2.0, 2)
-                                                        );
-
-                                                        let new_verts =
-                                                        [[zero.0, zero.1],
-                                                        [one.0, zero.1],
-                                                        [one.0, one.1],
-                                                        [zero.0, one.1]];
+                                                        let new_verts = orient_uv(sheet.cell_uv((j, i)), brush_flip_x, brush_flip_y, brush_rotate);
                                                         let draw_list = ui.get_window_draw_list();
                                                         draw_list 
-                                                        .add_image_quad(TextureId::new(u32::from(app.textures[&app.current_tile_sheet].id.0) as usize),
+                                                        .add_image_quad(TextureId::new(u32::from(editor.textures[&editor.app.current_tile_sheet].id.0) as usize),
                                                         [min[0], min[1]], [max[0], min[1]], [max[0], max[1]], [min[0], max[1]])
                                                         .uv(new_verts[0], new_verts[1], new_verts[2], new_verts[3])
                                                         .build();
@@ -589,16 +1278,171 @@ fn main() {
                                                     }
                                                     ui.next_column();
                                                 }
+
+                                                if let Some(anchor) = palette_anchor {
+                                                    if !ui.is_mouse_down(imgui::MouseButton::Left) {
+                                                        if anchor == palette_end {
+                                                            current_tile = anchor;
+                                                            stamp = None;
+                                                        } else {
+                                                            stamp = Some(Stamp::from_sheet(sheet, anchor, palette_end));
+                                                            tool = Tool::Brush;
+                                                        }
+                                                        terrain_brush = None;
+                                                        palette_anchor = None;
+                                                    }
+                                                }
                                             }
                                         }
                                     }
                                 }
                             }
+                            PropertySelect::ObjectLayer(index) => {
+                                if let Some(_) = ui.tab_bar("prop_main") {
+                                    if let Some(_) = ui.tab_item("Object Layer") {
+                                        if let Some(scene) = editor.app.current_scene.as_mut() {
+                                            if let Some(layer) = scene.object_layers.get_mut(*index) {
+                                                let before = layer.name.clone();
+                                                ui.input_text("Name", &mut layer.name).build();
+                                                if ui.is_item_activated() {
+                                                    object_layer_name = Some(before);
+                                                }
+                                                if ui.is_item_deactivated_after_edit() {
+                                                    if let Some(before) = object_layer_name.take().filter(|b| *b != layer.name) {
+                                                        editor.app.history.push(Edit::RenameObjectLayer { index: *index, before, after: layer.name.clone() });
+                                                    }
+                                                }
+                                                if ui.checkbox("Visible", &mut layer.visible) {
+                                                    editor.app.history.push(Edit::SetObjectLayerVisible { index: *index, visible: layer.visible });
+                                                }
+                                                ui.text(format!("{} entities", layer.entities.len()));
+                                                if ui.button("Add Entity") {
+                                                    let entity = Entity::new(&format!("Entity {}", layer.entities.len()), [camera.x, camera.y]);
+                                                    editor.app.history.push(Edit::AddEntity { layer: *index, index: layer.entities.len(), entity: entity.clone() });
+                                                    layer.entities.push(entity);
+                                                }
+                                                ui.text_wrapped("Click an entity in the viewport to select it and drag to move it. Ctrl+Click adds one.");
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            PropertySelect::Entity(layer_index, index) => {
+                                let mut deleted = false;
+                                if let Some(_) = ui.tab_bar("prop_main") {
+                                    if let Some(_) = ui.tab_item("Entity") {
+                                        if let Some(scene) = editor.app.current_scene.as_mut() {
+                                            let sheets = scene.tile_sheets.iter().map(|s| (s.filename.clone(), s.path.clone())).collect::<Vec<(String, String)>>();
+                                            if let Some(entity) = scene.object_layers.get_mut(*layer_index).and_then(|l| l.entities.get_mut(*index)) {
+                                                let before = entity.clone();
+                                                ui.input_text("Name", &mut entity.name).build();
+                                                ui.input_float2("Pos", &mut entity.position).build();
+                                                ui.input_float("Rotation", &mut entity.rotation).build();
+                                                ui.input_float2("Scale", &mut entity.scale).build();
+                                                ui.color_edit4("Tint", &mut entity.tint);
+                                                ui.input_int("Z", &mut entity.z).build();
+
+                                                ui.separator();
+                                                let prefab_names = editor.app.config.prefabs.iter().map(|p| p.name.as_str()).collect::<Vec<&str>>();
+                                                let names = std::iter::once("None").chain(prefab_names.iter().copied()).collect::<Vec<&str>>();
+                                                let mut prefab = entity.prefab.as_ref()
+                                                    .and_then(|name| prefab_names.iter().position(|p| p == name))
+                                                    .map_or(0, |i| i + 1);
+                                                if ui.combo_simple_string("Prefab##entity", &mut prefab, &names) {
+                                                    entity.prefab = prefab.checked_sub(1).map(|i| prefab_names[i].to_string());
+                                                }
+                                                let inherited = entity.prefab(&editor.app.config.prefabs).cloned();
+                                                if let (Some(name), None) = (&entity.prefab, &inherited) {
+                                                    ui.text_colored([1.0, 0.4, 0.4, 1.0], format!("Unknown prefab {}", name));
+                                                }
+
+                                                ui.separator();
+                                                ui.text(if inherited.is_some() { "Sprite Override" } else { "Sprite" });
+                                                edit_sprite(ui, "entity", &mut entity.sprite, &sheets, current_tile);
+
+                                                ui.separator();
+                                                match &inherited {
+                                                    Some(prefab) => {
+                                                        ui.text("Inherited Properties");
+                                                        for (key, property) in &prefab.properties {
+                                                            if entity.properties.contains_key(key) {
+                                                                continue;
+                                                            }
+                                                            if ui.small_button(format!("Override##entity_{}", key)) {
+                                                                entity.properties.insert(key.clone(), property.clone());
+                                                            }
+                                                            ui.same_line();
+                                                            ui.text(format!("{} ({}): {:?}", key, property.type_name(), property));
+                                                        }
+                                                        ui.text("Property Overrides");
+                                                    }
+                                                    None => {
+                                                        ui.text("Properties");
+                                                    }
+                                                }
+                                                edit_properties(ui, "entity", &mut entity.properties, &mut entity_property_key, &mut entity_property_type);
+                                                if inherited.is_some() && ui.button("Revert to Prefab") {
+                                                    entity.sprite = None;
+                                                    entity.properties.clear();
+                                                }
+
+                                                ui.separator();
+                                                deleted = ui.button("Delete Entity");
+
+                                                // Pushed to the history once the widget lets go, see the viewport
+                                                if *entity != before && entity_edit.is_none() {
+                                                    entity_edit = Some(before);
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                                if let (true, Some(scene)) = (deleted, editor.app.current_scene.as_mut()) {
+                                    if let Some(layer) = scene.object_layers.get_mut(*layer_index).filter(|l| *index < l.entities.len()) {
+                                        let entity = layer.entities.remove(*index);
+                                        editor.app.history.push(Edit::RemoveEntity { layer: *layer_index, index: *index, entity });
+                                        entity_edit = None;
+                                        property_select = PropertySelect::ObjectLayer(*layer_index);
+                                    }
+                                }
+                            }
+                            PropertySelect::Prefab(index) => {
+                                if let Some(_) = ui.tab_bar("prop_main") {
+                                    if let Some(_) = ui.tab_item("Prefab") {
+                                        let sheets = editor.app.current_scene.as_ref()
+                                            .map(|scene| scene.tile_sheets.iter().map(|s| (s.filename.clone(), s.path.clone())).collect::<Vec<(String, String)>>())
+                                            .unwrap_or_default();
+                                        let instances = editor.app.current_scene.as_ref().map_or(0, |scene| {
+                                            scene.object_layers.iter()
+                                                .flat_map(|l| &l.entities)
+                                                .filter(|e| e.prefab.as_ref() == editor.app.config.prefabs.get(*index).map(|p| &p.name))
+                                                .count()
+                                        });
+                                        if let Some(prefab) = editor.app.config.prefabs.get_mut(*index) {
+                                            ui.text(format!("Name: {}", prefab.name));
+                                            ui.input_text("Name Pattern", &mut prefab.name_pattern).build();
+                                            ui.text_disabled("{n} is replaced by a number");
+                                            ui.text(format!("{} instances in this scene", instances));
+
+                                            ui.separator();
+                                            ui.text("Sprite");
+                                            edit_sprite(ui, "prefab", &mut prefab.sprite, &sheets, current_tile);
+
+                                            ui.separator();
+                                            ui.text("Properties");
+                                            edit_properties(ui, "prefab", &mut prefab.properties, &mut entity_property_key, &mut entity_property_type);
+
+                                            ui.separator();
+                                            ui.text_wrapped("Changes apply to every instance that doesn't override them and are saved with the project. Drag the prefab from the Hierarchy into the viewport to place one.");
+                                        }
+                                    }
+                                }
+                            }
                             PropertySelect::Script => {
                                 let mut sorted_scripts = vec!();
-                                for ass in &app.config.assets {
-                                    if let AssetType::Script = ass.1.type_of {
-                                        sorted_scripts.push((ass.1.name.clone(), ass.1.load_order.unwrap()));
+                                for ass in &editor.app.config.assets {
+                                    if let (AssetType::Script, Some(load_order)) = (&ass.1.type_of, ass.1.load_order) {
+                                        sorted_scripts.push((ass.1.name.clone(), load_order));
                                     }
                                 }
                                 sorted_scripts.sort_by(|a, b| a.1.cmp(&b.1));
@@ -623,8 +1467,9 @@ fn main() {
                                     }
                                 }
                                 if a != -1 && b != -1 {
+                                    let before = editor.app.config.assets.clone();
                                     let mut aa_temp = None;
-                                    for i in &app.config.assets {
+                                    for i in &editor.app.config.assets {
                                         if let AssetType::Script = i.1.type_of {
                                             if let Some(lo) = i.1.load_order {
                                                 if lo == a as usize {
@@ -635,7 +1480,7 @@ fn main() {
                                     }
 
                                     let mut bb_temp = None;
-                                    for i in &app.config.assets {
+                                    for i in &editor.app.config.assets {
                                         if let AssetType::Script = i.1.type_of {
                                             if let Some(lo) = i.1.load_order {
                                                 if lo == b as usize {
@@ -646,7 +1491,7 @@ fn main() {
                                     }
 
                                     let mut bb= None;
-                                    for i in &mut app.config.assets {
+                                    for i in &mut editor.app.config.assets {
                                         if let AssetType::Script = i.1.type_of {
                                             if let Some(lo) = i.1.load_order {
                                                 if lo == b as usize {
@@ -662,7 +1507,7 @@ fn main() {
                                     }
 
                                     let mut aa = None;
-                                    for i in &mut app.config.assets {
+                                    for i in &mut editor.app.config.assets {
                                         if let AssetType::Script = i.1.type_of {
                                             if let Some(lo) = i.1.load_order {
                                                 if lo == a as usize {
@@ -676,6 +1521,10 @@ fn main() {
                                         Some(aaa) => aaa.load_order = bb_temp,
                                         None => { }
                                     }
+
+                                    if a != b {
+                                        editor.app.record_assets(before);
+                                    }
                                 }
                             }
                        }
@@ -701,37 +1550,48 @@ fn main() {
                         let mut hover_color = ui.push_style_color(imgui::StyleColor::HeaderHovered, [1.0,1.0,1.0,0.35]);
                         let mut active_hover_color = ui.push_style_color(imgui::StyleColor::HeaderActive, [1.0,1.0,1.0,0.5]);
 
+                        // Layer changes that rebuild sprites wait until the scene is let go
+                        let mut layer_action = None;
                         if let Some(_) = ui.tab_bar("main") {
-                            if let Some(scene) = app.current_scene.as_mut() {
+                            if let Some(scene) = editor.app.current_scene.as_mut() {
                             if let Some(_) = ui.tab_item("Layers") {
                                 
                                     if ui.button("Add") {
                                         let mut new_layer = Layer::new();
-                                        new_layer.tile_sheet = app.current_tile_sheet.clone();
+                                        new_layer.tile_sheet = editor.app.current_tile_sheet.clone();
+                                        if let Some(sheet) = scene.tile_sheets.iter().find(|s| s.path == new_layer.tile_sheet) {
+                                            new_layer.grid_size = sheet.tile_size;
+                                        }
+                                        editor.app.history.push(Edit::AddLayer { index: scene.layers.len(), layer: new_layer.clone() });
                                         scene.layers.push(new_layer);
-                                        app.sprite_buffer.push(HashMap::new());
+                                        editor.sprite_buffer.push(HashMap::new());
                                     }
 
                                     ui.columns(3, "layers_column", false);
+                                    let layer_count = scene.layers.len();
                                     for i in scene.layers.iter_mut().enumerate() {
-                                        if i.0 == app.current_layer {
+                                        if i.0 == editor.app.current_layer {
                                             ui.bullet();
                                         }
                                         ui.next_column();
-                                        if ui.selectable(format!("Layer {}", i.0)) {
+                                        let label = match i.1.locked {
+                                            true => format!("{} (locked)##layer{}", i.1.label(i.0), i.0),
+                                            false => format!("{}##layer{}", i.1.label(i.0), i.0)
+                                        };
+                                        let selected = ui.selectable(label);
+                                        // Dragging a layer past its neighbour swaps them
+                                        if ui.is_item_active() && !ui.is_item_hovered() {
+                                            let delta = ui.mouse_drag_delta()[1];
+                                            let next = if delta < 0.0 { i.0.checked_sub(1) } else { Some(i.0 + 1) };
+                                            if let Some(next) = next.filter(|n| *n < layer_count) {
+                                                layer_action = Some(LayerAction::Move(i.0, next));
+                                                ui.reset_mouse_drag_delta(imgui::MouseButton::Left);
+                                            }
+                                        }
+                                        if selected {
                                             property_select = PropertySelect::Layer;
-                                            app.current_layer = i.0;
-                                            app.current_tile_sheet = i.1.tile_sheet.clone();
-                                            for j in &mut i.1.tiles {
-                                                if let Some(buffer) = app.sprite_buffer.get_mut(i.0) {
-                                                    if let Some(spr) = buffer.get_mut(
-                                                        j.0
-                                                    ) {
-                                                        spr.texture_id = i.1.tile_sheet.clone();
-                                                    }
-                                                }
-                                                j.1.sheet = app.current_tile_sheet.clone();
-                                            } 
+                                            editor.app.current_layer = i.0;
+                                            editor.app.current_tile_sheet = i.1.tile_sheet.clone();
                                         }
                                         ui.next_column();
                                         let button_label = if i.1.visible {
@@ -743,25 +1603,54 @@ fn main() {
 
                                         if ui.button(button_label) {
                                             i.1.visible = !i.1.visible;
+                                            editor.app.history.push(Edit::SetLayerVisible { index: i.0, visible: i.1.visible });
 
-                                            for j in &mut i.1.tiles {
-                                                if let Some(buffer) = app.sprite_buffer.get_mut(i.0) {
-                                                    if let Some(spr) = buffer.get_mut(
-                                                        j.0
-                                                    ) {
-                                                        spr.visible = i.1.visible;
-                                                    }
-                                                }
-                                            }   
                                         }
                                         ui.next_column();
                                     }
                                     ui.columns(1, "layers_column_2", false);
+
+                                    ui.separator();
+                                    let current = editor.app.current_layer;
+                                    if let Some(layer) = scene.layers.get(current) {
+                                        ui.text(layer.label(current));
+                                        if ui.button("Duplicate") {
+                                            layer_action = Some(LayerAction::Duplicate(current));
+                                        }
+                                        ui.same_line();
+                                        let below_locked = current.checked_sub(1).and_then(|i| scene.layers.get(i)).map(|l| l.locked);
+                                        if below_locked == Some(false) && ui.button("Merge Down") {
+                                            layer_action = Some(LayerAction::MergeDown(current));
+                                        }
+                                        if ui.button("Copy to Scene...") {
+                                            layer_action = Some(LayerAction::CopyToScene(current));
+                                        }
+                                        if scene.layers.len() > 1 {
+                                            ui.same_line();
+                                            if ui.button("Delete") {
+                                                ui.open_popup("Delete Layer?");
+                                            }
+                                        }
+
+                                        if let Some(_) = ui.modal_popup_config("Delete Layer?").always_auto_resize(true).begin_popup() {
+                                            ui.text(format!("Delete {} and its {} tiles?", layer.label(current), layer.tiles.len()));
+                                            if ui.button("Delete##confirm") {
+                                                layer_action = Some(LayerAction::Delete(current));
+                                                ui.close_current_popup();
+                                            }
+                                            ui.same_line();
+                                            if ui.button("Cancel") {
+                                                ui.close_current_popup();
+                                            }
+                                        }
+                                    }
                                 }
                                 if let Some(_) = ui.tab_item("Markers") {
-                                    if let Some(scene) = app.current_scene.as_mut() {
+                                    if let Some(scene) = editor.app.current_scene.as_mut() {
                                         if ui.button("Add") {
-                                            scene.markers.push(Marker { position: [0.0, 0.0], name: format!("Marker {}", scene.markers.len()).to_string() });
+                                            let marker = Marker { position: [0.0, 0.0], name: format!("Marker {}", scene.markers.len()).to_string(), extra: Extra::new() };
+                                            editor.app.history.push(Edit::AddMarker { index: scene.markers.len(), marker: marker.clone() });
+                                            scene.markers.push(marker);
                                         }
 
                                         ui.columns(2, "markers_columns", false);
@@ -778,12 +1667,108 @@ fn main() {
                                         }
 
                                         for i in for_deletion {
-                                            scene.markers.remove(i);
+                                            let marker = scene.markers.remove(i);
+                                            editor.app.history.push(Edit::RemoveMarker { index: i, marker });
                                         }
                                     }
                                 }
+                                if let Some(_) = ui.tab_item("Objects") {
+                                    if let Some(scene) = editor.app.current_scene.as_mut() {
+                                        if ui.button("Add Layer") {
+                                            let layer = ObjectLayer::new(&format!("Objects {}", scene.object_layers.len()));
+                                            editor.app.history.push(Edit::AddObjectLayer { index: scene.object_layers.len(), layer: layer.clone() });
+                                            scene.object_layers.push(layer);
+                                        }
+
+                                        ui.columns(2, "objects_columns", false);
+                                        let mut layer_deletion = None;
+                                        let mut entity_deletion = None;
+                                        for (l, layer) in scene.object_layers.iter().enumerate() {
+                                            if ui.selectable(format!("{}##object_layer{}", layer.name, l)) {
+                                                property_select = PropertySelect::ObjectLayer(l);
+                                            }
+                                            ui.next_column();
+                                            if ui.button(format!("Delete##object_layer{}", l)) {
+                                                layer_deletion = Some(l);
+                                            }
+                                            ui.next_column();
+
+                                            for (e, entity) in layer.entities.iter().enumerate() {
+                                                if ui.selectable(format!("    {}##entity{}_{}", entity.name, l, e)) {
+                                                    property_select = PropertySelect::Entity(l, e);
+                                                }
+                                                ui.next_column();
+                                                if ui.button(format!("Delete##entity{}_{}", l, e)) {
+                                                    entity_deletion = Some((l, e));
+                                                }
+                                                ui.next_column();
+                                            }
+                                        }
+
+                                        if let Some((l, e)) = entity_deletion {
+                                            let entity = scene.object_layers[l].entities.remove(e);
+                                            editor.app.history.push(Edit::RemoveEntity { layer: l, index: e, entity });
+                                            property_select = PropertySelect::ObjectLayer(l);
+                                        }
+                                        if let Some(l) = layer_deletion {
+                                            let layer = scene.object_layers.remove(l);
+                                            editor.app.history.push(Edit::RemoveObjectLayer { index: l, layer });
+                                            property_select = PropertySelect::None;
+                                        }
+                                    }
+                                }
+                                if let Some(_) = ui.tab_item("Prefabs") {
+                                    ui.input_text("##prefab_name", &mut prefab_name).hint("Name").build();
+                                    ui.same_line();
+                                    if ui.button("Add##prefab") && !prefab_name.is_empty() {
+                                        if let Err(e) = editor.app.add_prefab(Prefab::new(&prefab_name)) {
+                                            editor.report(e);
+                                        }
+                                        prefab_name.clear();
+                                    }
+
+                                    ui.columns(2, "prefabs_columns", false);
+                                    let mut for_deletion = None;
+                                    for (i, prefab) in editor.app.config.prefabs.iter().enumerate() {
+                                        if ui.selectable(format!("{}##prefab{}", prefab.name, i)) {
+                                            property_select = PropertySelect::Prefab(i);
+                                        }
+                                        if ui.is_item_active() && ui.is_mouse_dragging(imgui::MouseButton::Left) {
+                                            prefab_drag = Some(i);
+                                        }
+                                        ui.next_column();
+                                        if ui.button(format!("Delete##prefab{}", i)) {
+                                            for_deletion = Some(i);
+                                        }
+                                        ui.next_column();
+                                    }
+
+                                    if let Some(i) = for_deletion {
+                                        if let Err(e) = editor.app.remove_prefab(i) {
+                                            editor.report(e);
+                                        }
+                                        property_select = PropertySelect::None;
+                                    }
+                                }
                             }
                         }
+
+                        match layer_action {
+                            Some(LayerAction::Delete(index)) => editor.remove_layer(ig_renderer.gl_context(), index),
+                            Some(LayerAction::Duplicate(index)) => editor.duplicate_layer(index),
+                            Some(LayerAction::Move(from, to)) => editor.move_layer(from, to),
+                            Some(LayerAction::MergeDown(index)) => editor.merge_layer_down(ig_renderer.gl_context(), &program, index),
+                            Some(LayerAction::CopyToScene(index)) => editor.copy_layer_to_scene(index),
+                            None => {}
+                        }
+                        if layer_action.is_some() {
+                            if let Some(scene) = editor.app.current_scene.as_ref() {
+                                if let Some(layer) = scene.layers.get(editor.app.current_layer) {
+                                    editor.app.current_tile_sheet = layer.tile_sheet.clone();
+                                }
+                            }
+                            editor.check();
+                        }
                         text_color.pop();
                         hover_color.pop();
                         active_hover_color.pop();
@@ -811,10 +1796,10 @@ fn main() {
                                 let mut to_remove = vec!();
 
                                 ui.columns(2, "layers_column", false);
-                                for asset in &app.config.assets {
+                                for asset in &editor.app.config.assets {
                                     if ui.selectable(format!("[{:#?}]{}", asset.1.type_of, asset.1.name)) {
                                         if let AssetType::Texture = asset.1.type_of {
-                                            if let Some(_) = app.current_scene {
+                                            if let Some(_) = editor.app.current_scene {
                                                 ui.open_popup(asset.0);
                                             }
                                         } else if let AssetType::Script = asset.1.type_of {
@@ -848,7 +1833,7 @@ fn main() {
                                 }
 
                                 for load in to_load {
-                                    app.add_texture(
+                                    editor.add_texture(
                                         ig_renderer.gl_context(), 
                                         load.0.to_string(),
                                         load.1.to_string(), 
@@ -856,11 +1841,15 @@ fn main() {
                                     );
                                 }
 
-                                for rem in to_remove {
-                                    app.config.assets.remove(&rem);
+                                if !to_remove.is_empty() {
+                                    let before = editor.app.config.assets.clone();
+                                    for rem in to_remove {
+                                        editor.app.config.assets.remove(&rem);
+                                    }
+                                    editor.app.record_assets(before);
                                 }
                             }
-                            if let Some(scene) = app.current_scene.as_mut() {
+                            if let Some(scene) = editor.app.current_scene.as_mut() {
                                 if let Some(_) = ui.tab_item(format!("World:{}", scene.name)) {
                                     if let Some(_) = ui.tab_bar("world") {
                                         if let Some(_) = ui.tab_item("Tile Sheets") {
@@ -870,10 +1859,8 @@ fn main() {
                                                 if ui.selectable(format!("{}##{}", tex.1.filename, tex.0)) {
                                                     property_select = PropertySelect::Tilesheet(tex.0);
                                                     if let Some(tilesheet) = scene.tile_sheets.get(tex.0) {
-                                                        tile_count = [
-                                                            (tilesheet.sheet_size.0/tilesheet.tile_size.0) as i32, 
-                                                            (tilesheet.sheet_size.1/tilesheet.tile_size.1) as i32
-                                                        ];
+                                                        let count = tilesheet.get_num_of_tiles();
+                                                        tile_count = [count.0 as i32, count.1 as i32];
                                                     }
                                                 }
                                                 ui.next_column();
@@ -885,7 +1872,9 @@ fn main() {
                                             }
 
                                             for i in to_remove {
-                                                scene.tile_sheets.remove(i);
+                                                let sheet = scene.tile_sheets.remove(i);
+                                                editor.app.history.push(Edit::RemoveTileSheet { index: i, sheet });
+                                                editor.merged_collision = None;
                                             }
                                         }
                                     }
@@ -898,114 +1887,362 @@ fn main() {
                     });
                 }
 
-                let new_tile = if let Some(_) = app.current_scene.as_ref() {
-                    if let PropertySelect::Marker(_) = property_select {
-                        None
-                    } else {
-                        let mouse_pos = Vec2::from_slice(&ui.io().mouse_pos);
-
-                        let model = 
-                        Mat4::IDENTITY * 
-                        Mat4::from_scale_rotation_translation( 
-                            Vec3::new(1.0, 1.0, 1.0),
-                            Quat::from_rotation_z(0.0), 
-                            Vec3::new(mouse_pos.x-(window_size.0/2.0), (window_size.1/2.0)-mouse_pos.y, 0.0)
-                        );
+                if show_problems {
+                    let mut goto = None;
+                    ui.window("Problems")
+                    .opened(&mut show_problems)
+                    .position([window_size.0/2.0 - 250.0, window_size.1/2.0 - 150.0], imgui::Condition::FirstUseEver)
+                    .size([500.0, 300.0], imgui::Condition::FirstUseEver)
+                    .build(|| {
+                        let text_color = ui.push_style_color(imgui::StyleColor::Text, [1.0,1.0,1.0,1.0]);
 
-                        let view = unsafe { *crate::renderer::VIEW_MATRIX };
-                        let projection = unsafe { *crate::renderer::PROJECTION_MATRIX };
-
-                        let mvp =  model * view.inverse() * projection;
-                        let position = mvp.to_scale_rotation_translation().2;
-                        
-                        if !ui.io().want_capture_mouse && !ui.is_key_down(imgui::Key::Space) {
-                            if ui.is_mouse_down(imgui::MouseButton::Left) {
-                                Some((position, true))
-                            } else if ui.is_mouse_down(imgui::MouseButton::Right) {
-                                Some((position, false))
-                            } else {
-                                None
+                        if ui.button("Refresh") {
+                            editor.check();
+                        }
+                        ui.same_line();
+                        ui.text(format!("{} problems", editor.problems.len()));
+                        ui.separator();
+
+                        let mut clipper = imgui::ListClipper::new(editor.problems.len() as i32).begin(ui);
+                        while clipper.step() {
+                            for i in clipper.display_start()..clipper.display_end() {
+                                let problem = &editor.problems[i as usize];
+                                if ui.selectable(format!("{}##problem{}", problem, i)) {
+                                    goto = Some(problem.location.clone());
+                                }
+                            }
+                        }
+
+                        text_color.pop();
+                    });
+
+                    // Take the user to whatever the problem points at
+                    if let (Some(location), Some(scene)) = (goto, editor.app.current_scene.as_ref()) {
+                        let mut focus = None;
+                        match location {
+                            Location::Tile { layer, position } => {
+                                property_select = PropertySelect::Layer;
+                                editor.app.current_layer = layer;
+                                focus = scene.layers.get(layer).map(|l| Vec2::from(l.cell_position(scene.orientation, position)));
+                            }
+                            Location::Layer(layer) => {
+                                property_select = PropertySelect::Layer;
+                                editor.app.current_layer = layer;
+                            }
+                            Location::TileSheet(sheet) => {
+                                property_select = PropertySelect::Tilesheet(sheet);
+                                if let Some(tilesheet) = scene.tile_sheets.get(sheet) {
+                                    let count = tilesheet.get_num_of_tiles();
+                                    tile_count = [count.0 as i32, count.1 as i32];
+                                }
+                            }
+                            Location::Marker(marker) => {
+                                property_select = PropertySelect::Marker(marker);
+                                focus = scene.markers.get(marker).map(|m| Vec2::new(m.position[0], m.position[1]));
+                            }
+                            Location::Entity { layer, index } => {
+                                property_select = PropertySelect::Entity(layer, index);
+                                focus = scene.object_layers.get(layer)
+                                    .and_then(|l| l.entities.get(index))
+                                    .map(|e| Vec2::new(e.position[0], e.position[1]));
+                            }
+                            Location::Asset(key) => {
+                                if let Some(AssetType::Script) = editor.app.config.assets.get(&key).map(|a| &a.type_of) {
+                                    property_select = PropertySelect::Script;
+                                }
+                            }
+                        }
+
+                        if let Some(layer) = scene.layers.get(editor.app.current_layer) {
+                            editor.app.current_tile_sheet = layer.tile_sheet.clone();
+                        }
+                        if let Some(position) = focus {
+                            camera = position;
+                            unsafe {
+                                *crate::renderer::VIEW_MATRIX = 
+                                Mat4::from_translation(Vec3::new(-camera.x, -camera.y, 0.0));
                             }
-                        } else {
-                            None
                         }
                     }
-                } else {
-                    None
+                }
+
+                if !editor.notifications.is_empty() {
+                    ui.window("Notifications")
+                    .position(
+                        [window_size.0/2.0 - 200.0, 
+                        30.0], 
+                        imgui::Condition::Always
+                    )
+                    .size_constraints([400.0, 0.0], [400.0, window_size.1/2.0])
+                    .always_auto_resize(true)
+                    .collapsible(false)
+                    .build(|| {
+                        let text_color = ui.push_style_color(imgui::StyleColor::Text, [1.0,1.0,1.0,1.0]);
+
+                        let mut dismissed = None;
+                        for note in editor.notifications.iter().enumerate() {
+                            ui.text_wrapped(note.1);
+                            if ui.button(format!("Dismiss##{}", note.0)) {
+                                dismissed = Some(note.0);
+                            }
+                            ui.separator();
+                        }
+
+                        if let Some(i) = dismissed {
+                            editor.notifications.remove(i);
+                        }
+                        if editor.notifications.len() > 1 && ui.button("Dismiss All") {
+                            editor.notifications.clear();
+                        }
+
+                        text_color.pop();
+                    });
+                }
+
+                let mouse_position = {
+                    let mouse_pos = Vec2::from_slice(&ui.io().mouse_pos);
+
+                    let model = 
+                    Mat4::IDENTITY * 
+                    Mat4::from_scale_rotation_translation( 
+                        Vec3::new(1.0, 1.0, 1.0),
+                        Quat::from_rotation_z(0.0), 
+                        Vec3::new(mouse_pos.x-(window_size.0/2.0), (window_size.1/2.0)-mouse_pos.y, 0.0)
+                    );
+
+                    let view = unsafe { *crate::renderer::VIEW_MATRIX };
+                    let projection = unsafe { *crate::renderer::PROJECTION_MATRIX };
+
+                    let mvp =  model * view.inverse() * projection;
+                    mvp.to_scale_rotation_translation().2
                 };
+                let mouse_scene = (!ui.io().want_capture_mouse && !ui.is_key_down(imgui::Key::Space)).then_some(mouse_position);
 
-                if let Some(nt) = new_tile {
-                    let tile = if let Some(scene) = app.current_scene.as_ref() {
-                        let sheet = scene.tile_sheets.iter().find(
-                            |&a| a.path == app.get_tile_sheet()
-                        );
-                        
-                        if let Some(sheet) = sheet {
-                            if nt.1 {
-                                let mut new_spr = Sprite::new(&app.current_tile_sheet);
-                                new_spr.load(ig_renderer.gl_context(), &program, &app.textures);
-                                new_spr.cut_sprite_sheet(0, 0,sheet.get_num_of_tiles().0, sheet.get_num_of_tiles().1);
-                                new_spr.anim_sprite_sheet(
-                                    ig_renderer.gl_context(), 
-                                    &program, 
-                                    current_tile.0 as i32, current_tile.1 as i32
-                                );
-                                new_spr.position = Vec2::new(
-                                    sheet.tile_size.0 as f32 * f32::round(nt.0.x/sheet.tile_size.0 as f32), 
-                                    sheet.tile_size.1 as f32 * f32::round(nt.0.y/sheet.tile_size.1 as f32)
-                                );
-                                app.sprite_buffer[app.current_layer].insert(
-                                    (
-                                        (sheet.tile_size.0 as f32 * f32::round(nt.0.x/sheet.tile_size.0 as f32)) as i32, 
-                                        (sheet.tile_size.1 as f32 * f32::round(nt.0.y/sheet.tile_size.1 as f32)) as i32
-                                    ),
-                                    new_spr
-                                );
+                // Prefabs dropped on the viewport go on the selected object layer, or the first
+                if let Some(i) = prefab_drag {
+                    if let Some(prefab) = editor.app.config.prefabs.get(i) {
+                        ui.tooltip_text(&prefab.name);
+                    }
+                    if !ui.is_mouse_down(imgui::MouseButton::Left) {
+                        prefab_drag = None;
+                        let over_window = ui.is_window_hovered_with_flags(imgui::WindowHoveredFlags::ANY_WINDOW);
+                        if let (false, Some(prefab), Some(scene)) = (over_window, editor.app.config.prefabs.get(i), editor.app.current_scene.as_mut()) {
+                            let l = match property_select {
+                                PropertySelect::ObjectLayer(l) | PropertySelect::Entity(l, _) if l < scene.object_layers.len() => l,
+                                _ => 0
+                            };
+                            if scene.object_layers.is_empty() {
+                                let layer = ObjectLayer::new("Objects 0");
+                                editor.app.history.push(Edit::AddObjectLayer { index: 0, layer: layer.clone() });
+                                scene.object_layers.push(layer);
                             }
-                            else {
-                                if let Some(layer) = scene.layers.get(app.current_layer) {
-                                    if let Some(_) = layer.tiles.get(
-                                        &((sheet.tile_size.0 as f32 * f32::round(nt.0.x/sheet.tile_size.0 as f32)) as i32, 
-                                        (sheet.tile_size.1 as f32 * f32::round(nt.0.y/sheet.tile_size.1 as f32)) as i32)
-                                    ) {
-                                        app.sprite_buffer[app.current_layer].remove(
-                                            &((sheet.tile_size.0 as f32 * f32::round(nt.0.x/sheet.tile_size.0 as f32)) as i32, 
-                                            (sheet.tile_size.1 as f32 * f32::round(nt.0.y/sheet.tile_size.1 as f32)) as i32)
-                                        );
-                                    }
+
+                            let entity = prefab.instantiate(scene, [mouse_position.x, mouse_position.y]);
+                            let index = scene.object_layers[l].entities.len();
+                            editor.app.history.push(Edit::AddEntity { layer: l, index, entity: entity.clone() });
+                            scene.object_layers[l].entities.push(entity);
+                            property_select = PropertySelect::Entity(l, index);
+                        }
+                    }
+                }
+
+                // Markers and entities take the mouse instead of the tile tools
+                let editing_objects = matches!(property_select, PropertySelect::Marker(_) | PropertySelect::ObjectLayer(_) | PropertySelect::Entity(..));
+                // Tiles are picked where the current layer is drawn, and not at all on a locked one
+                let current_layer = editor.app.current_scene.as_ref()
+                    .and_then(|scene| scene.layers.get(editor.app.current_layer))
+                    .filter(|layer| !layer.locked);
+                let orientation = editor.app.current_scene.as_ref().map_or(Orientation::Orthogonal, |s| s.orientation);
+                let mouse_world = mouse_scene.filter(|_| !editing_objects).and_then(|position| {
+                    let shift = current_layer?.shift([camera.x, camera.y]);
+                    Some(position - Vec3::new(shift[0], shift[1], 0.0))
+                });
+
+                // Tools paint cells of the current layer's grid
+                let cell = mouse_world.zip(current_layer)
+                    .filter(|(_, layer)| layer.grid_size.0 != 0 && layer.grid_size.1 != 0)
+                    .map(|(position, layer)| layer.cell_at(orientation, [position.x, position.y]));
+
+                let object_layer = match property_select {
+                    PropertySelect::ObjectLayer(l) | PropertySelect::Entity(l, _) => Some(l),
+                    _ => None
+                };
+                if let (Some(l), Some(scene)) = (object_layer, editor.app.current_scene.as_mut()) {
+                    let point = mouse_scene.map(|p| [p.x, p.y]);
+                    if let (Some(point), Some(layer)) = (point.filter(|_| ui.is_mouse_clicked(imgui::MouseButton::Left)), scene.object_layers.get_mut(l)) {
+                        let hit = layer.entity_at(&scene.tile_sheets, &editor.app.config.prefabs, point);
+                        let index = match hit {
+                            Some(index) => Some(index),
+                            None if ui.io().key_ctrl => {
+                                let entity = Entity::new(&format!("Entity {}", layer.entities.len()), point);
+                                editor.app.history.push(Edit::AddEntity { layer: l, index: layer.entities.len(), entity: entity.clone() });
+                                layer.entities.push(entity);
+                                Some(layer.entities.len() - 1)
+                            }
+                            None => None
+                        };
+
+                        property_select = match index {
+                            Some(index) => PropertySelect::Entity(l, index),
+                            None => PropertySelect::ObjectLayer(l)
+                        };
+                        if let Some(entity) = index.and_then(|i| layer.entities.get(i)) {
+                            entity_edit = Some(entity.clone());
+                            entity_drag = Some([entity.position[0] - point[0], entity.position[1] - point[1]]);
+                        }
+                    }
+
+                    if let PropertySelect::Entity(l, e) = property_select {
+                        if let Some(entity) = scene.object_layers.get_mut(l).and_then(|layer| layer.entities.get_mut(e)) {
+                            if let (Some(offset), Some(point)) = (entity_drag, point) {
+                                if ui.is_mouse_dragging(imgui::MouseButton::Left) {
+                                    entity.position = [point[0] + offset[0], point[1] + offset[1]];
                                 }
                             }
+                            if !ui.is_mouse_down(imgui::MouseButton::Left) {
+                                entity_drag = None;
+                            }
 
-                            Some((Tile {
-                                sheet: app.current_tile_sheet.clone(),
-                                sheet_id: current_tile,
-                                position: (
-                                    sheet.tile_size.0 as f32 * f32::round(nt.0.x/sheet.tile_size.0 as f32), 
-                                    sheet.tile_size.1 as f32 * f32::round(nt.0.y/sheet.tile_size.1 as f32)
-                                )
-                            }, nt.1))
-                        }
-                        else {
-                            None
+                            // Edits from the Properties window end when its widgets let go
+                            if entity_drag.is_none() && !ui.is_any_item_active() {
+                                if let Some(before) = entity_edit.take() {
+                                    if before != *entity {
+                                        editor.app.history.push(Edit::ChangeEntity { layer: l, index: e, before, after: entity.clone() });
+                                    }
+                                }
+                            }
                         }
+                    }
+                }
+
+                if !ui.is_mouse_down(imgui::MouseButton::Left) && !ui.is_mouse_down(imgui::MouseButton::Right) {
+                    editor.app.history.end_stroke();
+                }
+
+                let mut preview = vec!();
+                if let Some(cell) = cell {
+                    let sheet_path = editor.app.current_tile_sheet.clone();
+                    let tile = |fill: bool| fill.then(|| Tile {
+                        sheet: sheet_path.clone(),
+                        sheet_id: current_tile,
+                        flip_x: brush_flip_x,
+                        flip_y: brush_flip_y,
+                        rotate: brush_rotate,
+                        extra: Extra::new()
+                    });
+
+                    let down = if ui.is_mouse_down(imgui::MouseButton::Left) {
+                        Some(true)
+                    } else if ui.is_mouse_down(imgui::MouseButton::Right) {
+                        Some(false)
+                    } else {
+                        None
+                    };
+                    let clicked = if ui.is_mouse_clicked(imgui::MouseButton::Left) {
+                        Some(true)
+                    } else if ui.is_mouse_clicked(imgui::MouseButton::Right) {
+                        Some(false)
                     } else {
                         None
                     };
 
-                    if let (Some(scene), Some(tile)) = (app.current_scene.as_mut(), tile) {
-                        if tile.1 {
-                            if let Some(layer) = scene.layers.get_mut(app.current_layer) {
-                                layer.tiles.insert((tile.0.position.0 as i32, tile.0.position.1 as i32), tile.0);
+                    match tool {
+                        Tool::Brush if stamp.is_some() => {
+                            if let Some(stamp) = stamp.as_ref() {
+                                match down {
+                                    Some(fill) => {
+                                        // Only paint again once the mouse moves on to another cell
+                                        if stamp_cell != Some(cell) {
+                                            editor.paint_stamp(ig_renderer.gl_context(), &program, stamp, cell, fill);
+                                            stamp_cell = Some(cell);
+                                        }
+                                    }
+                                    None => {
+                                        preview = stamp.tiles(cell, true).into_iter().flatten().collect();
+                                    }
+                                }
+                            }
+                        }
+                        Tool::Brush => {
+                            match (down, terrain_brush) {
+                                (Some(fill), Some((sheet, terrain))) => {
+                                    editor.paint_terrain(ig_renderer.gl_context(), &program, sheet, terrain, cell, fill);
+                                }
+                                (Some(fill), None) => {
+                                    editor.set_tiles(ig_renderer.gl_context(), &program, vec!((cell, tile(fill))));
+                                }
+                                (None, None) => {
+                                    preview = vec!((cell, tile(true)));
+                                }
+                                (None, Some(_)) => {}
+                            }
+                        }
+                        Tool::Fill => {
+                            let area = editor.app.current_scene.as_ref()
+                                .and_then(|scene| scene.layers.get(editor.app.current_layer))
+                                .map(|layer| tools::fill(layer, orientation, cell, fill_contiguous, fill_max_area.max(1) as usize));
+
+                            // The outer `None` is no layer to fill, the inner one an area too big to fill
+                            match (area, clicked) {
+                                (Some(Some(area)), Some(fill)) => {
+                                    let tiles = area.into_iter().map(|c| (c, tile(fill))).collect();
+                                    editor.set_tiles(ig_renderer.gl_context(), &program, tiles);
+                                }
+                                (Some(None), Some(_)) => {
+                                    editor.report(format!("Fill would cover more than {} tiles", fill_max_area.max(1)));
+                                }
+                                (Some(Some(area)), None) => {
+                                    preview = area.into_iter().map(|c| (c, tile(true))).collect();
+                                }
+                                _ => {}
                             }
                         }
-                        else {
-                            if let Some(layer) = scene.layers.get_mut(app.current_layer) {
-                                layer.tiles.remove(&(tile.0.position.0 as i32, tile.0.position.1 as i32));
+                        _ => {
+                            if let Some(fill) = clicked {
+                                tool_anchor = Some((cell, fill));
+                            }
+                            if let (Some((anchor, fill)), Tool::Capture) = (tool_anchor, tool) {
+                                if let Some(scene) = editor.app.current_scene.as_ref() {
+                                    let end = (editor.app.current_layer + capture_layers.max(1) as usize).min(scene.layers.len());
+                                    let layers = scene.layers.get(editor.app.current_layer..end).unwrap_or_default();
+                                    if down == Some(fill) {
+                                        // Show what will be picked up from the current layer
+                                        if let Some(layer) = layers.first() {
+                                            preview = tools::rect(anchor, cell, true).into_iter()
+                                                .filter_map(|c| layer.tiles.get(&c).map(|t| (c, Some(t.clone()))))
+                                                .collect();
+                                        }
+                                    } else {
+                                        let captured = Stamp::capture(layers, anchor, cell);
+                                        if !captured.is_empty() {
+                                            stamp = Some(captured);
+                                            tool = Tool::Brush;
+                                            terrain_brush = None;
+                                        }
+                                        tool_anchor = None;
+                                    }
+                                }
+                            }
+                            else if let Some((anchor, fill)) = tool_anchor {
+                                let tiles = tool.shape(anchor, cell).into_iter().map(|c| (c, tile(fill))).collect();
+                                if down == Some(fill) {
+                                    preview = tiles;
+                                } else {
+                                    editor.set_tiles(ig_renderer.gl_context(), &program, tiles);
+                                    tool_anchor = None;
+                                }
                             }
                         }
                     }
                 }
 
+                // A shape let go of over a window is dropped
+                if !ui.is_mouse_down(imgui::MouseButton::Left) && !ui.is_mouse_down(imgui::MouseButton::Right) {
+                    tool_anchor = None;
+                    stamp_cell = None;
+                }
+                editor.set_preview(ig_renderer.gl_context(), &program, preview);
+
                 winit_platform.prepare_render(ui, window.window());
                 let draw_data = imgui_context.render();
 
@@ -1048,6 +2285,93 @@ fn main() {
     });
 }
 
+/// What the Layers tab asked for this frame, done once it no longer borrows the scene.
+#[cfg(feature = "gui")]
+#[derive(Clone, Copy)]
+enum LayerAction {
+    Delete(usize),
+    Duplicate(usize),
+    /// From, to
+    Move(usize, usize),
+    MergeDown(usize),
+    CopyToScene(usize)
+}
+
+/// A row per property of `properties` to edit or remove it, then a row to add one named
+/// `new_key` of the type at `new_type` in `Property::DEFAULTS`. `id` keeps the widgets apart
+/// from other tables in the same window.
+/// Records a Layer tab edit of layer `index` made with the last item: `before` is kept from when
+/// the item is activated, and the change is pushed once it is let go.
+#[cfg(feature = "gui")]
+fn record_layer_edit(ui: &imgui::Ui, history: &mut History, edit: &mut Option<LayerSettings>, index: usize, before: &LayerSettings, layer: &Layer) {
+    if ui.is_item_activated() {
+        *edit = Some(before.clone());
+    }
+    if ui.is_item_deactivated_after_edit() {
+        if let Some(before) = edit.take().filter(|b| *b != layer.settings()) {
+            history.push(Edit::ChangeLayer { index, before, after: layer.settings() });
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn edit_properties(ui: &imgui::Ui, id: &str, properties: &mut Properties, new_key: &mut String, new_type: &mut usize) {
+    let mut removed = None;
+    for (key, property) in properties.iter_mut() {
+        if ui.small_button(format!("x##{}_{}", id, key)) {
+            removed = Some(key.clone());
+        }
+        ui.same_line();
+        let label = format!("{} ({})##{}_{}", key, property.type_name(), id, key);
+        match property {
+            Property::Bool(v) => {
+                ui.checkbox(&label, v);
+            }
+            Property::Int(v) => {
+                ui.input_scalar(&label, v).build();
+            }
+            Property::Float(v) => {
+                ui.input_scalar(&label, v).build();
+            }
+            Property::String(v) => {
+                ui.input_text(&label, v).build();
+            }
+        }
+    }
+    if let Some(key) = removed {
+        properties.remove(&key);
+    }
+
+    ui.input_text(format!("Key##{}_new", id), new_key).build();
+    ui.combo(format!("Type##{}_new", id), new_type, &Property::DEFAULTS, |p| p.type_name().into());
+    if ui.button(format!("Add Property##{}", id)) && !new_key.is_empty() && !properties.contains_key(new_key.as_str()) {
+        properties.insert(std::mem::take(new_key), Property::DEFAULTS[*new_type].clone());
+    }
+}
+
+/// A tile sheet combo and cell picker for an entity or prefab sprite. "None" clears it.
+#[cfg(feature = "gui")]
+fn edit_sprite(ui: &imgui::Ui, id: &str, sprite: &mut Option<EntitySprite>, sheets: &[(String, String)], current_tile: (u32, u32)) {
+    let names = std::iter::once("None").chain(sheets.iter().map(|(f, _)| f.as_str())).collect::<Vec<&str>>();
+    let mut sheet = sprite.as_ref()
+        .and_then(|s| sheets.iter().position(|(_, p)| *p == s.sheet))
+        .map_or(0, |i| i + 1);
+    if ui.combo_simple_string(format!("Tile Sheet##{}", id), &mut sheet, &names) {
+        *sprite = sheet.checked_sub(1).map(|i| EntitySprite { sheet: sheets[i].1.clone(), sheet_id: current_tile });
+    }
+    if let Some(sprite) = sprite.as_mut() {
+        let mut cell = [sprite.sheet_id.0 as i32, sprite.sheet_id.1 as i32];
+        if ui.input_int2(format!("Cell##{}", id), &mut cell).build() {
+            sprite.sheet_id = (cell[0].max(0) as u32, cell[1].max(0) as u32);
+        }
+        ui.same_line();
+        if ui.button(format!("Selected Tile##{}", id)) {
+            sprite.sheet_id = current_tile;
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
 fn create_window() -> (EventLoop<()>, Window) {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window = glutin::window::WindowBuilder::new()
@@ -1066,10 +2390,12 @@ fn create_window() -> (EventLoop<()>, Window) {
     (event_loop, window)
 }
 
+#[cfg(feature = "gui")]
 fn glow_context(window: &Window) -> glow::Context {
     unsafe { glow::Context::from_loader_function(|s| window.get_proc_address(s).cast()) }
 }
 
+#[cfg(feature = "gui")]
 fn imgui_init(window: &Window) -> (WinitPlatform, imgui::Context) {
     let mut imgui_context = imgui::Context::create();
     imgui_context.set_ini_filename(None);
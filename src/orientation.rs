@@ -0,0 +1,265 @@
+//! How a scene's cells are laid out.
+//!
+//! Cells are `(column, row)` with y up whatever the orientation, and each orientation places
+//! them the way Tiled does with its rows flipped, so maps go between the two cell for cell.
+//! Staggered and hexagonal maps shift their odd rows (or odd columns for flat hexagons).
+
+use serde::{Deserialize, Serialize};
+
+/// Most hexagons `grid_lines` traces, a line each being a draw call in the editor.
+pub const GRID_CELLS: usize = 2048;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Orthogonal,
+    /// Diamond tiles in a diamond shaped map
+    Isometric,
+    /// Diamond tiles in a rectangular map, odd rows shifted right by half a tile
+    Staggered,
+    /// Hexagons with a point at the top, odd rows shifted right by half a tile
+    HexPointy,
+    /// Hexagons with a flat top, odd columns shifted down by half a tile
+    HexFlat
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 5] = [
+        Orientation::Orthogonal,
+        Orientation::Isometric,
+        Orientation::Staggered,
+        Orientation::HexPointy,
+        Orientation::HexFlat
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Orientation::Orthogonal => "Orthogonal",
+            Orientation::Isometric => "Isometric",
+            Orientation::Staggered => "Staggered Isometric",
+            Orientation::HexPointy => "Hexagonal, Pointy Top",
+            Orientation::HexFlat => "Hexagonal, Flat Top"
+        }
+    }
+
+    /// Whether cells are side by side rectangles. Tiles on other grids overlap, so the ones
+    /// further down the screen have to be drawn last.
+    pub fn is_orthogonal(self) -> bool {
+        self == Orientation::Orthogonal
+    }
+
+    /// Length of a hexagon's flat sides in pixels for a `grid` sized cell, 0 for other
+    /// orientations. Always half the cell, as Tiled suggests for new maps.
+    pub fn hex_side_length(self, grid: (u32, u32)) -> u32 {
+        match self {
+            Orientation::HexPointy => grid.1 / 2,
+            Orientation::HexFlat => grid.0 / 2,
+            _ => 0
+        }
+    }
+
+    /// The middle of `cell` in pixels on a grid of `grid` sized cells.
+    pub fn cell_position(self, cell: (i32, i32), grid: (u32, u32)) -> [f32; 2] {
+        let (c, r) = (cell.0 as f32, cell.1 as f32);
+        let (w, h) = (grid.0 as f32, grid.1 as f32);
+        let odd_row = if cell.1.rem_euclid(2) == 1 { w / 2.0 } else { 0.0 };
+        match self {
+            Orientation::Orthogonal => [c * w, r * h],
+            Orientation::Isometric => [(c + r) * w / 2.0, (r - c) * h / 2.0],
+            Orientation::Staggered => [c * w + odd_row, r * h / 2.0],
+            Orientation::HexPointy => {
+                let side = self.hex_side_length(grid) as f32;
+                [c * w + odd_row, r * (h + side) / 2.0]
+            }
+            Orientation::HexFlat => {
+                let side = self.hex_side_length(grid) as f32;
+                let odd_column = if cell.0.rem_euclid(2) == 1 { h / 2.0 } else { 0.0 };
+                [c * (w + side) / 2.0, r * h - odd_column]
+            }
+        }
+    }
+
+    /// The cell under `point`, in pixels on a grid of `grid` sized cells.
+    pub fn cell_at(self, point: [f32; 2], grid: (u32, u32)) -> (i32, i32) {
+        let (w, h) = (grid.0.max(1) as f32, grid.1.max(1) as f32);
+        match self {
+            Orientation::Orthogonal => ((point[0] / w).round() as i32, (point[1] / h).round() as i32),
+            Orientation::Isometric | Orientation::Staggered => {
+                // Both are the same diamonds, only counted differently
+                let u = (point[0] / w - point[1] / h).round() as i32;
+                let v = (point[0] / w + point[1] / h).round() as i32;
+                match self {
+                    Orientation::Isometric => (u, v),
+                    _ => {
+                        let row = v - u;
+                        ((u + v - row.rem_euclid(2)).div_euclid(2), row)
+                    }
+                }
+            }
+            Orientation::HexPointy | Orientation::HexFlat => {
+                // Hexagons are the cells whose middle is nearest, so try the ones around a guess
+                let step = self.cell_position((1, 2), grid);
+                let guess = match self {
+                    Orientation::HexPointy => {
+                        let row = (point[1] * 2.0 / step[1].max(1.0)).round() as i32;
+                        let shift = self.cell_position((0, row), grid)[0];
+                        (((point[0] - shift) / w).round() as i32, row)
+                    }
+                    _ => {
+                        let column = (point[0] / step[0].max(1.0)).round() as i32;
+                        let shift = self.cell_position((column, 0), grid)[1];
+                        (column, ((point[1] - shift) / h).round() as i32)
+                    }
+                };
+                let distance = |cell: &(i32, i32)| {
+                    let p = self.cell_position(*cell, grid);
+                    (p[0] - point[0]).powi(2) + (p[1] - point[1]).powi(2)
+                };
+                // Hexagons that aren't regular aren't the same shape as the area nearest their
+                // middle, so the one drawn round the point wins
+                let around = (-1..=1)
+                    .flat_map(|x| (-1..=1).map(move |y| (guess.0 + x, guess.1 + y)))
+                    .collect::<Vec<(i32, i32)>>();
+                around.iter()
+                    .find(|cell| self.outline_contains(**cell, grid, point))
+                    .or_else(|| around.iter().min_by(|a, b| distance(a).total_cmp(&distance(b))))
+                    .copied()
+                    .unwrap_or(guess)
+            }
+        }
+    }
+
+    /// The lowest and highest column and row of the cells under the pixels from `min` to `max`.
+    /// Off orthogonal grids the cells in the corners aren't always the furthest out, so this
+    /// takes the extent of all four.
+    pub fn cell_range(self, min: [f32; 2], max: [f32; 2], grid: (u32, u32)) -> ((i32, i32), (i32, i32)) {
+        let corners = [min, [max[0], min[1]], max, [min[0], max[1]]].map(|p| self.cell_at(p, grid));
+        corners.iter().fold((corners[0], corners[0]), |(low, high), c| {
+            ((low.0.min(c.0), low.1.min(c.1)), (high.0.max(c.0), high.1.max(c.1)))
+        })
+    }
+
+    /// Lines along the edges of the cells from `min` to `max`, in pixels. Hexagons don't line up
+    /// into long lines, so they come as three edges each for up to `GRID_CELLS` cells nearest
+    /// the middle.
+    pub fn grid_lines(self, min: [f32; 2], max: [f32; 2], grid: (u32, u32)) -> Vec<([f32; 2], [f32; 2])> {
+        let (w, h) = (grid.0.max(1) as f32, grid.1.max(1) as f32);
+        match self {
+            Orientation::Orthogonal => {
+                let columns = (min[0] / w - 0.5).floor() as i32..=(max[0] / w - 0.5).ceil() as i32;
+                let rows = (min[1] / h - 0.5).floor() as i32..=(max[1] / h - 0.5).ceil() as i32;
+                columns
+                    .map(|x| (x as f32 + 0.5) * w)
+                    .map(|x| ([x, min[1]], [x, max[1]]))
+                    .chain(rows.map(|y| (y as f32 + 0.5) * h).map(|y| ([min[0], y], [max[0], y])))
+                    .collect()
+            }
+            Orientation::Isometric | Orientation::Staggered => {
+                // Edges are where x / w - y / h or x / w + y / h is half way between whole numbers
+                let down = (min[0] / w - max[1] / h).floor() as i32..=(max[0] / w - min[1] / h).ceil() as i32;
+                let up = (min[0] / w + min[1] / h).floor() as i32..=(max[0] / w + max[1] / h).ceil() as i32;
+                down
+                    .map(|u| u as f32 + 0.5)
+                    .map(|u| ([min[0], (min[0] / w - u) * h], [max[0], (max[0] / w - u) * h]))
+                    .chain(up.map(|v| v as f32 + 0.5).map(|v| ([min[0], (v - min[0] / w) * h], [max[0], (v - max[0] / w) * h])))
+                    .collect()
+            }
+            Orientation::HexPointy | Orientation::HexFlat => {
+                let middle = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+                let centre = self.cell_at(middle, grid);
+                let (low, high) = self.cell_range(min, max, grid);
+                let (low, high) = ((low.0 - 1, low.1 - 1), (high.0 + 1, high.1 + 1));
+
+                // Each cell draws the edges from its bottom round to its right, its neighbours
+                // draw the others. A tiny grid would otherwise be millions of cells
+                let reach = (GRID_CELLS as f32).sqrt() as i32;
+                let distance = |cell: &(i32, i32)| {
+                    let p = self.cell_position(*cell, grid);
+                    (p[0] - middle[0]).powi(2) + (p[1] - middle[1]).powi(2)
+                };
+                let mut cells = (low.0.max(centre.0 - reach)..=high.0.min(centre.0 + reach))
+                    .flat_map(|x| (low.1.max(centre.1 - reach)..=high.1.min(centre.1 + reach)).map(move |y| (x, y)))
+                    .collect::<Vec<(i32, i32)>>();
+                cells.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+                cells.truncate(GRID_CELLS);
+
+                cells.into_iter()
+                    .flat_map(|cell| {
+                        let outline = self.cell_outline(cell, grid);
+                        (0..3).map(move |i| (outline[i], outline[i + 1]))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The cells sharing an edge with `cell`.
+    pub fn neighbours(self, cell: (i32, i32)) -> Vec<(i32, i32)> {
+        let (x, y) = cell;
+        // The column the shifted rows (or rows the shifted columns) line up with on the left
+        let left = if y.rem_euclid(2) == 1 { x } else { x - 1 };
+        match self {
+            Orientation::Orthogonal | Orientation::Isometric => vec!((x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)),
+            Orientation::Staggered => vec!((left, y + 1), (left + 1, y + 1), (left, y - 1), (left + 1, y - 1)),
+            Orientation::HexPointy => vec!(
+                (x + 1, y), (x - 1, y),
+                (left, y + 1), (left + 1, y + 1), (left, y - 1), (left + 1, y - 1)
+            ),
+            Orientation::HexFlat => {
+                let below = if x.rem_euclid(2) == 1 { y - 1 } else { y };
+                vec!(
+                    (x, y + 1), (x, y - 1),
+                    (x + 1, below), (x + 1, below + 1), (x - 1, below), (x - 1, below + 1)
+                )
+            }
+        }
+    }
+
+    /// The corners of `cell`'s outline in pixels, going round.
+    pub fn cell_outline(self, cell: (i32, i32), grid: (u32, u32)) -> Vec<[f32; 2]> {
+        let [x, y] = self.cell_position(cell, grid);
+        let (w, h) = (grid.0 as f32 / 2.0, grid.1 as f32 / 2.0);
+        let side = self.hex_side_length(grid) as f32 / 2.0;
+        let corners = match self {
+            Orientation::Orthogonal => vec!([-w, -h], [w, -h], [w, h], [-w, h]),
+            Orientation::Isometric | Orientation::Staggered => vec!([0.0, -h], [w, 0.0], [0.0, h], [-w, 0.0]),
+            Orientation::HexPointy => vec!([0.0, -h], [w, -side], [w, side], [0.0, h], [-w, side], [-w, -side]),
+            Orientation::HexFlat => vec!([-side, -h], [side, -h], [w, 0.0], [side, h], [-side, h], [-w, 0.0])
+        };
+        corners.into_iter().map(|[cx, cy]| [x + cx, y + cy]).collect()
+    }
+
+    /// Whether `point` is inside or on `cell`'s outline.
+    fn outline_contains(self, cell: (i32, i32), grid: (u32, u32), point: [f32; 2]) -> bool {
+        // Outlines go round anticlockwise, so the point is left of every edge
+        let outline = self.cell_outline(cell, grid);
+        outline.iter().zip(outline.iter().cycle().skip(1)).all(|(a, b)| {
+            (b[0] - a[0]) * (point[1] - a[1]) - (b[1] - a[1]) * (point[0] - a[0]) >= 0.0
+        })
+    }
+
+    /// Tiled's names for the orientation: its `orientation`, and for staggered and hexagonal
+    /// maps its `staggeraxis`.
+    pub fn tiled_name(self) -> (&'static str, Option<&'static str>) {
+        match self {
+            Orientation::Orthogonal => ("orthogonal", None),
+            Orientation::Isometric => ("isometric", None),
+            Orientation::Staggered => ("staggered", Some("y")),
+            Orientation::HexPointy => ("hexagonal", Some("y")),
+            Orientation::HexFlat => ("hexagonal", Some("x"))
+        }
+    }
+
+    /// The orientation for Tiled's `orientation` and `staggeraxis`, if the editor has it.
+    pub fn from_tiled(orientation: &str, stagger_axis: &str) -> Option<Self> {
+        match (orientation, stagger_axis) {
+            ("orthogonal", _) => Some(Orientation::Orthogonal),
+            ("isometric", _) => Some(Orientation::Isometric),
+            ("staggered", "y") => Some(Orientation::Staggered),
+            ("hexagonal", "y") => Some(Orientation::HexPointy),
+            ("hexagonal", "x") => Some(Orientation::HexFlat),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Prefabs: entity templates saved with the project.
+//!
+//! An instance names its prefab in `Entity::prefab` and only stores what it overrides, a sprite
+//! and any properties, so editing a prefab changes its instances in every scene the next time
+//! they are read.
+
+use serde::{Deserialize, Serialize};
+
+use crate::application::{Entity, EntitySprite, Scene};
+use crate::properties::Properties;
+use crate::schema::Extra;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prefab {
+    /// Unique within the project, instances refer to the prefab by it
+    pub name: String,
+    /// Names new instances, with `{n}` replaced by a number
+    pub name_pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<EntitySprite>,
+    #[serde(default, skip_serializing_if = "Properties::is_empty")]
+    pub properties: Properties,
+    #[serde(flatten)]
+    pub extra: Extra
+}
+
+impl Prefab {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            name_pattern: format!("{}_{{n}}", name),
+            sprite: None,
+            properties: Properties::new(),
+            extra: Extra::new()
+        }
+    }
+
+    /// A new instance at `position`, overriding nothing, named for the instances already in
+    /// `scene`.
+    pub fn instantiate(&self, scene: &Scene, position: [f32; 2]) -> Entity {
+        let count = scene.object_layers.iter()
+            .flat_map(|l| &l.entities)
+            .filter(|e| e.prefab.as_deref() == Some(self.name.as_str()))
+            .count();
+
+        let mut entity = Entity::new(&self.name_pattern.replace("{n}", &count.to_string()), position);
+        entity.prefab = Some(self.name.clone());
+        entity
+    }
+}
+
+/// The prefab called `name`, if `prefabs` has one.
+pub fn find<'a>(prefabs: &'a [Prefab], name: &str) -> Option<&'a Prefab> {
+    prefabs.iter().find(|p| p.name == name)
+}
@@ -0,0 +1,75 @@
+//! Custom properties on the cells of a tile sheet, like "friction" or "is_ladder", and the
+//! lookup function generated into a project's main.rs so gameplay code can read them.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+use crate::application::TileSheet;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String)
+}
+
+/// A cell's properties by name.
+pub type Properties = BTreeMap<String, Property>;
+
+impl Property {
+    /// One property of each type, holding its default value.
+    pub const DEFAULTS: [Property; 4] = [Property::Bool(false), Property::Int(0), Property::Float(0.0), Property::String(String::new())];
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Property::Bool(_) => "Bool",
+            Property::Int(_) => "Int",
+            Property::Float(_) => "Float",
+            Property::String(_) => "String"
+        }
+    }
+
+    /// The property as a `TileProperty` expression in generated code.
+    fn to_rust(&self) -> String {
+        match self {
+            Property::Bool(v) => format!("TileProperty::Bool({})", v),
+            Property::Int(v) => format!("TileProperty::Int({})", v),
+            Property::Float(v) if v.is_nan() => String::from("TileProperty::Float(f64::NAN)"),
+            Property::Float(v) if v.is_infinite() => format!("TileProperty::Float({}f64::INFINITY)", if *v < 0.0 { "-" } else { "" }),
+            Property::Float(v) => format!("TileProperty::Float({:?})", v),
+            Property::String(v) => format!("TileProperty::String({:?})", v)
+        }
+    }
+}
+
+/// Source for a `TileProperty` enum and a `tile_property(sheet, cell, key)` function answering
+/// from the properties of `sheets`. `sheet` is the tile sheet's path, as tiles store it.
+pub fn generate(sheets: &[TileSheet]) -> String {
+    let mut arms = String::new();
+    for sheet in sheets {
+        let mut cells = sheet.properties.iter().collect::<Vec<_>>();
+        cells.sort_by_key(|(cell, _)| **cell);
+
+        for (cell, properties) in cells {
+            for (key, property) in properties {
+                arms.push_str(&format!(
+                    "            ({:?}, ({}, {}), {:?}) => Some({}),\n",
+                    sheet.path, cell.0, cell.1, key, property.to_rust()
+                ));
+            }
+        }
+    }
+
+    format!(
+        "    #[allow(dead_code)]\n    \
+        #[derive(Clone, Debug, PartialEq)]\n    \
+        pub enum TileProperty {{\n        Bool(bool),\n        Int(i64),\n        Float(f64),\n        String(&'static str)\n    }}\n\n    \
+        /// The custom property `key` of cell `cell` of the tile sheet at `sheet`, as set in the editor.\n    \
+        #[allow(dead_code)]\n    \
+        pub fn tile_property(sheet: &str, cell: (u32, u32), key: &str) -> Option<TileProperty> {{\n        \
+        match (sheet, cell, key) {{\n{}            _ => None\n        }}\n    }}\n",
+        arms
+    )
+}
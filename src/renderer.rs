@@ -7,6 +7,7 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use lazy_mut::lazy_mut;
+use lilah_editor::application::orient_uv;
 
 lazy_mut! {
     pub static mut VIEW_MATRIX: Mat4 = Mat4::IDENTITY;
@@ -36,9 +37,10 @@ out vec4 FragColor;
 in vec2 texCoord;
 
 uniform sampler2D texture0;
+uniform vec4 tint;
 
 void main() {
-   FragColor = texture(texture0, texCoord);
+   FragColor = texture(texture0, texCoord) * tint;
 }
 "#;
 
@@ -327,6 +329,10 @@ pub struct Sprite {
     size: (u32, u32),
     /// Current position on sprite sheet
     index: (i32, i32),
+    /// Pixel size of the region picked with `set_region`, overriding the sheet cut
+    region_size: Option<(u32, u32)>,
+    /// Texture coordinates of that region
+    region_uv: Option<[f32; 4]>,
     /// Texture file name
     pub texture_id: String,
 
@@ -335,6 +341,16 @@ pub struct Sprite {
     pub sort: u32,
 
     pub visible: bool,
+
+    /// Mirrored left to right, after `rotate`. Applied by `set_region` and `anim_sprite_sheet`
+    pub flip_x: bool,
+    /// Mirrored top to bottom, after `rotate`
+    pub flip_y: bool,
+    /// Turned 90° clockwise
+    pub rotate: bool,
+    /// Radians anticlockwise, on top of `rotate`
+    pub rotation: f32,
+    pub scale: Vec2,
     
     vertex_buffer: Option<Buffer>,
     vertex_array: Option<VertexArray>
@@ -362,12 +378,19 @@ impl Sprite {
             base_size: (1, 1),
             index_cut: (0, 0),
             index: (0,0),
+            region_size: None,
+            region_uv: None,
             texture_id: t_id.to_string(),
             vertex_array: None,
             vertex_buffer: None,
             tint: Color::WHITE,
             sort: 0,
-            visible: true
+            visible: true,
+            flip_x: false,
+            flip_y: false,
+            rotate: false,
+            rotation: 0.0,
+            scale: Vec2::ONE
         }
     }
 
@@ -401,7 +424,26 @@ impl Sprite {
         }
     }
 
+    /// Frees what `load` made on the GPU. The sprite can be loaded again.
+    pub fn unload(&mut self, gl: &glow::Context) {
+        if let Some(mut vao) = self.vertex_array.take() {
+            vao.delete(gl);
+        }
+        if let Some(mut vbo) = self.vertex_buffer.take() {
+            vbo.delete(gl);
+        }
+    }
+
+    /// Whether `load` has given the sprite its buffers yet.
+    pub fn is_loaded(&self) -> bool {
+        self.vertex_array.is_some()
+    }
+
     pub fn get_size(&self) -> (u32, u32) {
+        if let Some(size) = self.region_size {
+            return size;
+        }
+
         (
             self.base_size.0/self.size.0,
             self.base_size.1/self.size.1,
@@ -415,6 +457,7 @@ impl Sprite {
     }
 
     pub fn anim_sprite_sheet(&mut self, gl: &glow::Context, program: &ShaderProgram, ind: i32, ind2: i32) {
+        self.region_uv = None;
         self.index = (ind*self.get_size().0 as i32, ind2*self.get_size().1 as i32);
         let ratio = (
             ((self.base_size.0 as f32/self.size.0 as f32)/self.base_size.0 as f32),
@@ -442,11 +485,30 @@ impl Sprite {
             precision_f32(zero.1+ratio.1 - (1.0/self.base_size.1 as f32) * 2.0, 2)
         );
 
+        self.set_corners(gl, [zero.0, zero.1, one.0, one.1]);
+    }
+
+    /// Shows the part of the texture at `uv` (`[u0, v0, u1, v1]`), drawn `size` pixels large.
+    /// Does nothing if that is what the sprite already shows, so animations can call it every frame.
+    pub fn set_region(&mut self, gl: &glow::Context, uv: [f32; 4], size: (u32, u32)) {
+        if self.region_size == Some(size) && self.region_uv == Some(uv) {
+            return;
+        }
+
+        self.region_size = Some(size);
+        self.region_uv = Some(uv);
+        self.set_corners(gl, uv);
+    }
+
+    /// Points the quad at `uv` (`[u0, v0, u1, v1]`), turned and mirrored by the sprite's flags.
+    fn set_corners(&mut self, gl: &glow::Context, uv: [f32; 4]) {
+        let [top_left, top_right, bottom_right, bottom_left] = orient_uv(uv, self.flip_x, self.flip_y, self.rotate);
+
         let mut new_verts = Sprite::DEF_VERTICES;
-        new_verts[0].1 = [zero.0, one.1];
-        new_verts[1].1 = [one.0, one.1];
-        new_verts[2].1 = [one.0, zero.1];
-        new_verts[3].1 = [zero.0, zero.1];
+        new_verts[0].1 = bottom_left;
+        new_verts[1].1 = bottom_right;
+        new_verts[2].1 = top_right;
+        new_verts[3].1 = top_left;
 
         unsafe { 
             self.vertex_array.as_ref().unwrap().bind(gl);
@@ -459,11 +521,17 @@ impl Sprite {
             return;
         }
 
+        // A turned sprite is as wide as the region is tall
+        let size = match self.rotate {
+            true => (self.get_size().1, self.get_size().0),
+            false => self.get_size()
+        };
+
         let model = 
         Mat4::IDENTITY * 
         Mat4::from_scale_rotation_translation( 
-            Vec3::new(self.get_size().0 as f32, self.get_size().1 as f32, 1.0),
-            Quat::from_rotation_z(0.0), 
+            Vec3::new(size.0 as f32 * self.scale.x, size.1 as f32 * self.scale.y, 1.0),
+            Quat::from_rotation_z(self.rotation), 
             Vec3::new(self.position.x, self.position.y, 0.0)
         );
 
@@ -484,6 +552,9 @@ impl Sprite {
 
             let sort_attr = gl.get_uniform_location(program.id, "sort").unwrap();
             gl.uniform_1_f32(Some(&sort_attr), self.sort as f32);
+
+            let tint_attr = gl.get_uniform_location(program.id, "tint").unwrap();
+            gl.uniform_4_f32(Some(&tint_attr), self.tint.r, self.tint.g, self.tint.b, self.tint.a);
             
             gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
         }
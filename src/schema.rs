@@ -0,0 +1,226 @@
+//! `format_version` handling for scene and config.json files.
+//!
+//! Files are upgraded as json values before serde sees them, one migration per version, so a
+//! migration only ever has to know about the version right before it. Files without a
+//! `format_version` are version 0, from before it existed.
+//!
+//! Upgrading happens in memory, reading a file never changes it. The original is copied to
+//! `backup_path` the first time the upgraded version is saved over it.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt, fs, path::{Path, PathBuf}};
+use serde::{de::{DeserializeOwned, Visitor}, Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::chunk::chunk_of;
+use crate::error::{EditorError, Result, WithPath};
+
+pub const SCENE_VERSION: u32 = 3;
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Fields a type doesn't know about, kept so they survive a load and save.
+pub type Extra = Map<String, Value>;
+
+/// A map key for types read by hand, borrowed from the input where it can be so matching the
+/// known fields doesn't allocate.
+pub(crate) struct FieldName<'de>(pub Cow<'de, str>);
+
+impl<'de> Deserialize<'de> for FieldName<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl<'de> Visitor<'de> for FieldVisitor {
+            type Value = FieldName<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a field name")
+            }
+
+            fn visit_borrowed_str<E>(self, v: &'de str) -> std::result::Result<Self::Value, E> {
+                Ok(FieldName(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                Ok(FieldName(Cow::Owned(v.to_string())))
+            }
+
+            fn visit_string<E>(self, v: String) -> std::result::Result<Self::Value, E> {
+                Ok(FieldName(Cow::Owned(v)))
+            }
+        }
+
+        deserializer.deserialize_str(FieldVisitor)
+    }
+}
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `SCENE_MIGRATIONS[n]` upgrades a version `n` scene to version `n + 1`.
+const SCENE_MIGRATIONS: [Migration; SCENE_VERSION as usize] = [scene_v1, scene_v2, scene_v3];
+/// `CONFIG_MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`.
+const CONFIG_MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [config_v1];
+
+/// Tile sheets gained `margin` and `spacing`.
+fn scene_v1(scene: &mut Map<String, Value>) {
+    if let Some(Value::Array(sheets)) = scene.get_mut("tile_sheets") {
+        for sheet in sheets.iter_mut().filter_map(Value::as_object_mut) {
+            sheet.entry("margin").or_insert(Value::from(0));
+            sheet.entry("spacing").or_insert(Value::from(0));
+        }
+    }
+}
+
+/// Layer `tiles`, a list of `[key, tile]` pairs, became `chunks`, lists of them per chunk.
+fn scene_v2(scene: &mut Map<String, Value>) {
+    let Some(Value::Array(layers)) = scene.get_mut("layers") else {
+        return;
+    };
+    for layer in layers.iter_mut().filter_map(Value::as_object_mut) {
+        let tiles = match layer.remove("tiles") {
+            Some(Value::Array(tiles)) => tiles,
+            _ => Vec::new()
+        };
+
+        let mut chunks: BTreeMap<(i32, i32), Vec<Value>> = BTreeMap::new();
+        for tile in tiles {
+            let key = tile.get(0).and_then(|k| serde_json::from_value::<(i32, i32)>(k.clone()).ok()).unwrap_or_default();
+            chunks.entry(chunk_of(key)).or_default().push(tile);
+        }
+        let chunks = chunks.into_iter()
+            .map(|(chunk, tiles)| serde_json::json!({ "chunk": [chunk.0, chunk.1], "tiles": tiles }))
+            .collect::<Vec<Value>>();
+        layer.insert(String::from("chunks"), Value::Array(chunks));
+    }
+}
+
+/// Tile keys went from pixels to cells of a new per layer `grid_size`, and tiles lost their
+/// `position`. The grid is the layer's palette sheet's tile size, made smaller if some keys
+/// are off it, so every tile keeps its place.
+fn scene_v3(scene: &mut Map<String, Value>) {
+    let sizes = match scene.get("tile_sheets") {
+        Some(Value::Array(sheets)) => sheets.iter()
+            .filter_map(|s| Some((s.get("path")?.as_str()?.to_string(), serde_json::from_value::<(u32, u32)>(s.get("tile_size")?.clone()).ok()?)))
+            .collect::<Vec<(String, (u32, u32))>>(),
+        _ => Vec::new()
+    };
+    let Some(Value::Array(layers)) = scene.get_mut("layers") else {
+        return;
+    };
+
+    fn gcd(a: i64, b: i64) -> i64 {
+        match b {
+            0 => a.abs(),
+            _ => gcd(b, a % b)
+        }
+    }
+
+    for layer in layers.iter_mut().filter_map(Value::as_object_mut) {
+        let tiles = match layer.remove("chunks") {
+            Some(Value::Array(chunks)) => chunks.into_iter()
+                .filter_map(|mut c| match c.get_mut("tiles").map(Value::take) {
+                    Some(Value::Array(tiles)) => Some(tiles),
+                    _ => None
+                })
+                .flatten()
+                .filter_map(|tile| {
+                    let key = serde_json::from_value::<(i32, i32)>(tile.get(0)?.clone()).ok()?;
+                    let mut tile = tile.get(1)?.clone();
+                    tile.as_object_mut()?.remove("position");
+                    Some((key, tile))
+                })
+                .collect::<Vec<((i32, i32), Value)>>(),
+            _ => Vec::new()
+        };
+
+        let palette = layer.get("tile_sheet").and_then(Value::as_str)
+            .and_then(|path| sizes.iter().find(|(p, _)| p == path))
+            .map_or((0, 0), |(_, size)| (size.0 as i64, size.1 as i64));
+        let grid = tiles.iter().fold(palette, |grid, (key, _)| (gcd(grid.0, key.0 as i64), gcd(grid.1, key.1 as i64)));
+        let grid = (
+            if grid.0 > 0 { grid.0 as i32 } else { 16 },
+            if grid.1 > 0 { grid.1 as i32 } else { 16 }
+        );
+
+        let mut chunks: BTreeMap<(i32, i32), Vec<Value>> = BTreeMap::new();
+        for (key, tile) in tiles {
+            let cell = (key.0 / grid.0, key.1 / grid.1);
+            chunks.entry(chunk_of(cell)).or_default().push(serde_json::json!([[cell.0, cell.1], tile]));
+        }
+        let chunks = chunks.into_iter()
+            .map(|(chunk, tiles)| serde_json::json!({ "chunk": [chunk.0, chunk.1], "tiles": tiles }))
+            .collect::<Vec<Value>>();
+        layer.insert(String::from("chunks"), Value::Array(chunks));
+        layer.insert(String::from("grid_size"), serde_json::json!([grid.0, grid.1]));
+    }
+}
+
+/// Only adds `format_version`.
+fn config_v1(_: &mut Map<String, Value>) {}
+
+/// The scene at `file` and, if it had to be upgraded, the version it was.
+pub(crate) fn load_scene<T: DeserializeOwned>(file: &Path) -> Result<(T, Option<u32>)> {
+    load(file, &SCENE_MIGRATIONS)
+}
+
+/// The config at `file` and, if it had to be upgraded, the version it was.
+pub(crate) fn load_config<T: DeserializeOwned>(file: &Path) -> Result<(T, Option<u32>)> {
+    load(file, &CONFIG_MIGRATIONS)
+}
+
+/// Just the version of a file, read without building the rest.
+#[derive(Deserialize)]
+struct Version {
+    format_version: Option<u32>
+}
+
+/// Where the original of a file upgraded from `version` is kept.
+pub fn backup_path(file: &Path, version: u32) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    file.with_file_name(name)
+}
+
+/// Copies the version `version` file at `file` to `backup_path`, before it is first saved over
+/// with an upgraded version. An existing backup is kept, it is the older original.
+pub(crate) fn back_up(file: &Path, version: u32) -> Result<()> {
+    let backup = backup_path(file, version);
+    if file.exists() && !backup.exists() {
+        fs::copy(file, &backup).with_path(&backup)?;
+    }
+    Ok(())
+}
+
+/// Reads the json file at `file`, upgrading it in memory if it is older than `migrations` goes.
+/// Returns the version it was upgraded from alongside it.
+fn load<T: DeserializeOwned>(file: &Path, migrations: &[Migration]) -> Result<(T, Option<u32>)> {
+    let bytes = fs::read(file).with_path(file)?;
+    let current = migrations.len() as u32;
+
+    // Up to date files, nearly all of them, skip the json value. Big scenes load several
+    // times faster straight into `T`
+    if let Ok(Version { format_version: Some(version) }) = serde_json::from_slice::<Version>(&bytes) {
+        if version == current {
+            return Ok((serde_json::from_slice(&bytes).with_path(file)?, None));
+        }
+    }
+
+    let mut value: Value = serde_json::from_slice(&bytes).with_path(file)?;
+
+    let Some(map) = value.as_object_mut() else {
+        return Ok((serde_json::from_value(value).with_path(file)?, None));
+    };
+
+    let version = map.get("format_version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > current {
+        return Err(EditorError::NewerFormat { path: file.to_path_buf(), version, supported: current });
+    }
+    if version == current {
+        return Ok((serde_json::from_value(value).with_path(file)?, None));
+    }
+
+    for migration in &migrations[version as usize..] {
+        migration(map);
+    }
+    map.insert(String::from("format_version"), Value::from(current));
+
+    Ok((serde_json::from_value(value).with_path(file)?, Some(version)))
+}
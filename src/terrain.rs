@@ -0,0 +1,199 @@
+//! Autotiling: a terrain picks which cell of its tile sheet a tile uses from which of the eight
+//! cells around it are painted with the same terrain.
+//!
+//! Masks have one bit per neighbour, edges in the low four bits and corners in the high four.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::application::{Layer, Tile, TileSheet, TileWrite};
+use crate::schema::Extra;
+
+pub const NORTH: u8 = 1;
+pub const EAST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const WEST: u8 = 8;
+pub const NORTH_EAST: u8 = 16;
+pub const SOUTH_EAST: u8 = 32;
+pub const SOUTH_WEST: u8 = 64;
+pub const NORTH_WEST: u8 = 128;
+
+/// Offset in cells of the neighbour for each mask bit, in bit order. y is up like the viewport.
+const NEIGHBOURS: [(i32, i32); 8] = [(0, 1), (1, 0), (0, -1), (-1, 0), (1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+/// Each corner with the two edges next to it.
+const CORNERS: [(u8, u8, u8); 4] = [
+    (NORTH_EAST, NORTH, EAST),
+    (SOUTH_EAST, SOUTH, EAST),
+    (SOUTH_WEST, SOUTH, WEST),
+    (NORTH_WEST, NORTH, WEST)
+];
+
+/// Lists the neighbours set in `mask`, like "N E NE".
+pub fn describe(mask: u8) -> String {
+    let names = ["N", "E", "S", "W", "NE", "SE", "SW", "NW"];
+    let set = names.iter().enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<&str>>();
+
+    match set.is_empty() {
+        true => String::from("alone"),
+        false => set.join(" ")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum TerrainKind {
+    /// 16 tiles, one bit per corner, set when all three cells around that corner are filled
+    Corner,
+    /// 47 tiles, the four edges plus any corner whose two edges are filled
+    Blob,
+    /// 9 tiles laid out as a 3x3 block, from the four edges only
+    Minimal
+}
+
+impl TerrainKind {
+    pub const ALL: [TerrainKind; 3] = [TerrainKind::Corner, TerrainKind::Blob, TerrainKind::Minimal];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TerrainKind::Corner => "4-bit Corner",
+            TerrainKind::Blob => "8-bit Blob",
+            TerrainKind::Minimal => "3x3 Minimal"
+        }
+    }
+
+    /// Clears the bits of `mask` this kind doesn't tell apart.
+    pub fn reduce(&self, mask: u8) -> u8 {
+        let corners = CORNERS.iter()
+            .filter(|(corner, a, b)| mask & (corner | a | b) == corner | a | b)
+            .fold(0, |out, (corner, _, _)| out | corner);
+
+        match self {
+            TerrainKind::Corner => corners,
+            TerrainKind::Blob => (mask & 0x0f) | corners,
+            TerrainKind::Minimal => mask & 0x0f
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainRule {
+    pub mask: u8,
+    pub sheet_id: (u32, u32)
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Terrain {
+    pub name: String,
+    pub kind: TerrainKind,
+    pub rules: Vec<TerrainRule>
+}
+
+impl Terrain {
+    pub fn new(name: &str, kind: TerrainKind) -> Self {
+        Self { name: name.to_string(), kind, rules: Vec::new() }
+    }
+
+    /// Whether `tile` is one of this terrain's cells of `sheet`.
+    pub fn contains(&self, sheet: &TileSheet, tile: &Tile) -> bool {
+        tile.sheet == sheet.path && self.rules.iter().any(|r| r.sheet_id == tile.sheet_id)
+    }
+
+    /// Uses `sheet_id` for tiles with `mask`, replacing the rule already there.
+    pub fn set_rule(&mut self, mask: u8, sheet_id: (u32, u32)) {
+        let mask = self.kind.reduce(mask);
+        match self.rules.iter_mut().find(|r| r.mask == mask) {
+            Some(rule) => rule.sheet_id = sheet_id,
+            None => self.rules.push(TerrainRule { mask, sheet_id })
+        }
+    }
+
+    /// The cell for a tile whose neighbours are `mask`. Masks without a rule of their own use
+    /// the rule that differs from them in the fewest bits, so an incomplete set still paints.
+    pub fn sheet_id(&self, mask: u8) -> Option<(u32, u32)> {
+        let mask = self.kind.reduce(mask);
+        self.rules.iter()
+            .min_by_key(|r| (r.mask ^ mask).count_ones())
+            .map(|r| r.sheet_id)
+    }
+
+    /// Adds the rules for the usual 3x3 block, outer corners at its corners and the fully
+    /// surrounded tile in the middle, with its top left cell at `origin`.
+    pub fn set_block_rules(&mut self, origin: (u32, u32)) {
+        let block = [
+            [EAST | SOUTH, EAST | SOUTH | WEST, SOUTH | WEST],
+            [NORTH | EAST | SOUTH, NORTH | EAST | SOUTH | WEST, NORTH | SOUTH | WEST],
+            [NORTH | EAST, NORTH | EAST | WEST, NORTH | WEST]
+        ];
+
+        for (y, row) in block.iter().enumerate() {
+            for (x, mask) in row.iter().enumerate() {
+                // The corner bits only matter to blob terrains, where a solid block has them all set
+                let corners = self.kind.reduce(mask | 0xf0) & 0xf0;
+                self.set_rule(mask | corners, (origin.0 + x as u32, origin.1 + y as u32));
+            }
+        }
+    }
+}
+
+/// Which of the cells around `position` are filled with `terrain`, reading from `cells`.
+fn mask(cells: &HashMap<(i32, i32), Option<Tile>>, sheet: &TileSheet, terrain: &Terrain, position: (i32, i32)) -> u8 {
+    NEIGHBOURS.iter().enumerate()
+        .filter(|(_, offset)| {
+            let neighbour = (position.0 + offset.0, position.1 + offset.1);
+            cells.get(&neighbour).is_some_and(|t| t.as_ref().is_some_and(|t| terrain.contains(sheet, t)))
+        })
+        .fold(0, |mask, (bit, _)| mask | (1 << bit))
+}
+
+/// What filling (or erasing) `terrain` at `position` in `layer` turns it and its neighbours
+/// into, with the cells re-picked so the edges between them stay joined up. Only tiles that
+/// would change are returned, `None` for the erased one.
+pub fn paint(layer: &Layer, sheet: &TileSheet, terrain: &Terrain, position: (i32, i32), fill: bool) -> Vec<TileWrite> {
+    let Some(first) = terrain.rules.first() else {
+        return vec!();
+    };
+
+    let filled = layer.tiles.get(&position).is_some_and(|t| terrain.contains(sheet, t));
+    if filled == fill {
+        return vec!();
+    }
+
+    // Masks of the ring around `position` look one cell further out
+    let mut cells = HashMap::new();
+    for x in -2..=2 {
+        for y in -2..=2 {
+            let p = (position.0 + x, position.1 + y);
+            cells.insert(p, layer.tiles.get(&p).cloned());
+        }
+    }
+
+    // The cell is picked below along with the neighbours
+    cells.insert(position, fill.then(|| Tile {
+        sheet: sheet.path.clone(),
+        sheet_id: first.sheet_id,
+        flip_x: false,
+        flip_y: false,
+        rotate: false,
+        extra: Extra::new()
+    }));
+
+    let mut tiles = vec!();
+    for offset in std::iter::once((0, 0)).chain(NEIGHBOURS) {
+        let p = (position.0 + offset.0, position.1 + offset.1);
+        let mut tile = cells[&p].clone();
+        if let Some(tile) = tile.as_mut().filter(|t| terrain.contains(sheet, t)) {
+            if let Some(sheet_id) = terrain.sheet_id(mask(&cells, sheet, terrain, p)) {
+                tile.sheet_id = sheet_id;
+            }
+        }
+
+        if layer.tiles.get(&p) != tile.as_ref() {
+            tiles.push((p, tile));
+        }
+    }
+
+    tiles
+}
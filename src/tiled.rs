@@ -0,0 +1,1151 @@
+//! Import and export of Tiled maps (.tmx and .tmj) and tilesets (.tsx).
+//!
+//! Both map flavours are read into the same `MapData` first, so the mapping onto layers, tile
+//! sheets and markers only lives in `build_scene`. Exported maps start at cell 0, with the shift
+//! from scene coordinates kept in the `lilah_origin_x`/`lilah_origin_y` map properties so they
+//! import back onto the same tile keys. Isometric, staggered and hexagonal maps keep their
+//! orientation, see `Orientation` for how their cells line up.
+
+use std::{collections::HashMap, fs, io::Read, path::{Path, PathBuf}};
+use base64::Engine;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use roxmltree::Node;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::application::{Layer, Marker, Scene, Tile, TileSheet};
+use crate::error::{EditorError, Result, WithPath};
+use crate::orientation::Orientation;
+use crate::schema::Extra;
+
+/// Flip and rotation flags Tiled keeps in the top four bits of every gid.
+const GID_FLAGS: u32 = 0xf000_0000;
+const FLIP_H: u32 = 0x8000_0000;
+const FLIP_V: u32 = 0x4000_0000;
+/// Swaps x and y, applied before the other two
+const FLIP_D: u32 = 0x2000_0000;
+/// Only means something on hexagonal maps
+const ROTATE_HEX: u32 = 0x1000_0000;
+
+/// Tiled's flags as `(flip_x, flip_y, rotate)`. Swapping x and y is the same as turning
+/// clockwise and then mirroring left to right.
+fn tile_flags(raw: u32) -> (bool, bool, bool) {
+    let d = raw & FLIP_D != 0;
+    ((raw & FLIP_H != 0) != d, raw & FLIP_V != 0, d)
+}
+
+/// The reverse of `tile_flags`.
+fn gid_flags(tile: &Tile) -> u32 {
+    let mut flags = 0;
+    if tile.flip_x != tile.rotate {
+        flags |= FLIP_H;
+    }
+    if tile.flip_y {
+        flags |= FLIP_V;
+    }
+    if tile.rotate {
+        flags |= FLIP_D;
+    }
+    flags
+}
+
+pub struct TiledImport {
+    pub scene: Scene,
+    /// Everything in the map that has no equivalent in a `Scene` and was skipped or simplified
+    pub warnings: Vec<String>
+}
+
+/// Reads the .tmx or .tmj map at `file`, along with any external tilesets it references.
+pub fn import(file: &Path) -> Result<TiledImport> {
+    let text = fs::read_to_string(file).with_path(file)?;
+    let map = if text.trim_start().starts_with('<') {
+        read_xml_map(file, &text)?
+    } else {
+        read_json_map(file, &text)?
+    };
+
+    Ok(build_scene(map, &file.file_stem().unwrap_or_default().to_string_lossy()))
+}
+
+struct MapData {
+    orientation: String,
+    /// "x" or "y" on staggered and hexagonal maps
+    stagger_axis: String,
+    /// "odd" or "even"
+    stagger_index: String,
+    hex_side_length: u32,
+    tile_size: (u32, u32),
+    /// Scene cell of the map's top left cell
+    origin: (i32, i32),
+    tilesets: Vec<TilesetData>,
+    layers: Vec<LayerData>
+}
+
+struct TilesetData {
+    first_gid: u32,
+    name: String,
+    /// `None` for image collection tilesets
+    image: Option<PathBuf>,
+    image_size: (u32, u32),
+    tile_size: (u32, u32),
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    tile_count: u32
+}
+
+impl TilesetData {
+    /// The image size, worked out from the grid for old files that don't record it.
+    fn sheet_size(&self) -> (u32, u32) {
+        if (self.image_size.0 > 0 && self.image_size.1 > 0) || self.columns == 0 {
+            return self.image_size;
+        }
+
+        let span = |count: u32, tile: u32| self.margin * 2 + count * tile + count.saturating_sub(1) * self.spacing;
+        let rows = self.tile_count.div_ceil(self.columns);
+        (span(self.columns, self.tile_size.0), span(rows, self.tile_size.1))
+    }
+}
+
+struct LayerData {
+    name: String,
+    kind: LayerKind,
+    visible: bool,
+    collision: bool,
+    /// Name of the tileset the palette should start on
+    tile_sheet: Option<String>,
+    opacity: f32,
+    /// Pixels, y down
+    offset: (f32, f32),
+    parallax: (f32, f32),
+    tint: Option<[f32; 4]>,
+    locked: bool
+}
+
+enum LayerKind {
+    /// Raw gids by cell, flags included
+    Tiles(Vec<((i32, i32), u32)>),
+    Objects(Vec<ObjectData>),
+    /// Image layers and anything else without an equivalent
+    Other(&'static str)
+}
+
+struct ObjectData {
+    name: String,
+    position: (f32, f32),
+    /// `None` for point objects
+    shape: Option<&'static str>
+}
+
+fn build_scene(mut map: MapData, name: &str) -> TiledImport {
+    let mut warnings = vec!();
+    let mut scene = Scene::new(name, &format!("{}.json", name));
+    let (tile_w, tile_h) = map.tile_size;
+
+    match Orientation::from_tiled(&map.orientation, &map.stagger_axis) {
+        Some(orientation) => scene.orientation = orientation,
+        None => warnings.push(format!("{} maps are not supported, tiles were laid out orthogonally", map.orientation))
+    }
+    let side = scene.orientation.hex_side_length(map.tile_size);
+    if side > 0 && map.hex_side_length != side {
+        warnings.push(format!("hexagons had sides of {} pixels, the editor's have {}", map.hex_side_length, side));
+    }
+    // The editor shifts the odd rows (or columns), so maps shifting the even ones move over one
+    let stagger = match (scene.orientation, map.stagger_index.as_str()) {
+        (Orientation::Staggered | Orientation::HexPointy, "even") => (0, 1),
+        (Orientation::HexFlat, "even") => (1, 0),
+        _ => (0, 0)
+    };
+
+    // Gids are resolved against the last tileset starting at or below them
+    map.tilesets.sort_by_key(|t| t.first_gid);
+
+    let mut sheets = vec!();
+    for tileset in &map.tilesets {
+        let Some(image) = &tileset.image else {
+            warnings.push(format!("tileset '{}' is an image collection, its tiles were skipped", tileset.name));
+            sheets.push(None);
+            continue;
+        };
+
+        if tileset.tile_size != map.tile_size {
+            warnings.push(format!(
+                "tileset '{}' has {}x{} tiles on a {}x{} grid, they were centred on their cells",
+                tileset.name, tileset.tile_size.0, tileset.tile_size.1, tile_w, tile_h
+            ));
+        }
+
+        let path = image.to_string_lossy().to_string();
+        sheets.push(Some(scene.tile_sheets.len()));
+        scene.tile_sheets.push(TileSheet {
+            filename: image.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path: path.clone(),
+            absolute_path: path,
+            tile_size: tileset.tile_size,
+            sheet_size: tileset.sheet_size(),
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            terrains: Vec::new(),
+            properties: HashMap::new(),
+            collision: HashMap::new(),
+            animations: HashMap::new(),
+            extra: Extra::new()
+        });
+    }
+
+    for layer in map.layers {
+        match layer.kind {
+            LayerKind::Tiles(cells) => {
+                let mut new_layer = Layer::new();
+                new_layer.name = layer.name.clone();
+                new_layer.grid_size = (tile_w, tile_h);
+                new_layer.visible = layer.visible;
+                new_layer.collision = layer.collision;
+                new_layer.opacity = layer.opacity;
+                new_layer.offset = [layer.offset.0, -layer.offset.1];
+                new_layer.parallax = [layer.parallax.0, layer.parallax.1];
+                new_layer.locked = layer.locked;
+                if let Some(tint) = layer.tint {
+                    new_layer.tint = tint;
+                }
+
+                let mut usage = vec![0usize; scene.tile_sheets.len()];
+                let mut hex_rotated = 0;
+                let mut skipped = 0;
+                for (cell, raw) in cells {
+                    let gid = raw & !GID_FLAGS;
+                    if gid == 0 {
+                        continue;
+                    }
+
+                    let Some(index) = map.tilesets.iter().rposition(|t| t.first_gid <= gid) else {
+                        skipped += 1;
+                        continue;
+                    };
+                    let (tileset, Some(sheet)) = (&map.tilesets[index], sheets[index]) else {
+                        skipped += 1;
+                        continue;
+                    };
+
+                    if raw & ROTATE_HEX != 0 {
+                        hex_rotated += 1;
+                    }
+
+                    let id = gid - tileset.first_gid;
+                    let columns = match tileset.columns {
+                        0 => scene.tile_sheets[sheet].get_num_of_tiles().0.max(1),
+                        columns => columns
+                    };
+                    // Tiled rows grow downwards, the viewport's y axis grows upwards
+                    let position = (cell.0 + map.origin.0 + stagger.0, -(cell.1 + map.origin.1 + stagger.1));
+
+                    usage[sheet] += 1;
+                    let (flip_x, flip_y, rotate) = tile_flags(raw);
+                    new_layer.tiles.insert(position, Tile {
+                        sheet: scene.tile_sheets[sheet].path.clone(),
+                        sheet_id: (id % columns, id / columns),
+                        flip_x,
+                        flip_y,
+                        rotate,
+                        extra: Extra::new()
+                    });
+                }
+
+                if hex_rotated > 0 {
+                    warnings.push(format!("layer '{}': {} tiles had a hexagonal 120° rotation, which was dropped", layer.name, hex_rotated));
+                }
+                if skipped > 0 {
+                    warnings.push(format!("layer '{}': {} tiles from image collections or unknown tilesets were skipped", layer.name, skipped));
+                }
+
+                let named = layer.tile_sheet.and_then(|name| {
+                    map.tilesets.iter().position(|t| t.name == name).and_then(|i| sheets[i])
+                });
+                let most_used = usage.iter().enumerate().max_by_key(|(_, count)| **count).map(|(sheet, _)| sheet);
+                if let Some(sheet) = named.or(most_used) {
+                    new_layer.tile_sheet = scene.tile_sheets[sheet].path.clone();
+                    new_layer.current_tile_item = sheet as i32;
+                }
+
+                scene.layers.push(new_layer);
+            }
+            LayerKind::Objects(objects) => {
+                // Objects become markers, which have nowhere to keep these
+                let dropped = dropped_attributes(layer.opacity, layer.offset, layer.parallax, layer.tint.is_some());
+                if !dropped.is_empty() {
+                    warnings.push(format!("layer '{}': {} not supported on object layers and dropped", layer.name, dropped.join(", ")));
+                }
+                if !scene.orientation.is_orthogonal() && !objects.is_empty() {
+                    warnings.push(format!("layer '{}': object positions were read as if the map were orthogonal", layer.name));
+                }
+
+                for object in objects {
+                    match object.shape {
+                        None => {
+                            // Tile keys are cell centres, Tiled measures from the top left corner of the map
+                            let x = object.position.0 + (map.origin.0 * tile_w as i32) as f32;
+                            let y = object.position.1 + (map.origin.1 * tile_h as i32) as f32;
+                            scene.markers.push(Marker {
+                                position: [x - tile_w as f32 / 2.0, tile_h as f32 / 2.0 - y],
+                                name: object.name,
+                                extra: Extra::new()
+                            });
+                        }
+                        Some(shape) => {
+                            warnings.push(format!(
+                                "layer '{}': {} object '{}' was skipped, only point objects become markers",
+                                layer.name, shape, object.name
+                            ));
+                        }
+                    }
+                }
+            }
+            LayerKind::Other(kind) => {
+                warnings.push(format!("{} '{}' was skipped", kind, layer.name));
+            }
+        }
+    }
+
+    // The editor always expects a layer to paint on
+    if scene.layers.is_empty() {
+        scene.layers.push(Layer::new());
+    }
+
+    TiledImport { scene, warnings }
+}
+
+/// Joins a path from a map or tileset onto the directory of the file it came from.
+fn resolve(dir: &Path, source: &str) -> PathBuf {
+    let path = dir.join(source);
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+fn dropped_attributes(opacity: f32, offset: (f32, f32), parallax: (f32, f32), tint: bool) -> Vec<&'static str> {
+    let mut dropped = vec!();
+    if opacity != 1.0 {
+        dropped.push("opacity");
+    }
+    if offset != (0.0, 0.0) {
+        dropped.push("offset");
+    }
+    if parallax != (1.0, 1.0) {
+        dropped.push("parallax");
+    }
+    if tint {
+        dropped.push("tint");
+    }
+    dropped
+}
+
+/// Reads a Tiled colour, `#RRGGBB` or `#AARRGGBB`.
+fn parse_color(text: &str) -> Option<[f32; 4]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+    match hex.len() {
+        6 => Some([channel(16), channel(8), channel(0), 1.0]),
+        8 => Some([channel(16), channel(8), channel(0), channel(24)]),
+        _ => None
+    }
+}
+
+/// Writes a colour the way Tiled reads it, `#AARRGGBB`.
+fn format_color(color: [f32; 4]) -> String {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u32;
+    format!("#{:02x}{:02x}{:02x}{:02x}", channel(color[3]), channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+/// Decodes the text of a `data` element or json `data` string into the `count` raw gids of a
+/// layer or chunk.
+fn decode_gids(file: &Path, data: &str, encoding: &str, compression: Option<&str>, count: usize) -> Result<Vec<u32>> {
+    match encoding {
+        "csv" => {
+            let gids = data.split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse::<u32>().map_err(|e| EditorError::import(file, format!("bad csv tile data: {}", e))))
+                .collect::<Result<Vec<u32>>>()?;
+            check_count(file, gids, count)
+        }
+        "base64" => {
+            let compact = data.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+            let bytes = base64::engine::general_purpose::STANDARD.decode(compact)
+                .map_err(|e| EditorError::import(file, format!("bad base64 tile data: {}", e)))?;
+
+            let raw = match compression.unwrap_or("") {
+                "" => bytes,
+                "zlib" => {
+                    let mut raw = vec!();
+                    ZlibDecoder::new(&bytes[..]).read_to_end(&mut raw).with_path(file)?;
+                    raw
+                }
+                "gzip" => {
+                    let mut raw = vec!();
+                    GzDecoder::new(&bytes[..]).read_to_end(&mut raw).with_path(file)?;
+                    raw
+                }
+                other => {
+                    return Err(EditorError::import(file, format!("{} compressed tile data is not supported", other)));
+                }
+            };
+
+            if raw.len() % 4 != 0 {
+                return Err(EditorError::import(file, format!("tile data is {} bytes, not a whole number of gids", raw.len())));
+            }
+            check_count(file, raw.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(), count)
+        }
+        other => Err(EditorError::import(file, format!("{} tile data is not supported", other)))
+    }
+}
+
+/// Fails unless there are `count` gids, one for every cell of the layer or chunk.
+fn check_count(file: &Path, gids: Vec<u32>, count: usize) -> Result<Vec<u32>> {
+    match gids.len() == count {
+        true => Ok(gids),
+        false => Err(EditorError::import(file, format!("tile data has {} gids, its size needs {}", gids.len(), count)))
+    }
+}
+
+/// Lays `gids` out row by row from `origin`, `width` cells wide.
+fn place(cells: &mut Vec<((i32, i32), u32)>, origin: (i32, i32), width: u32, gids: Vec<u32>) {
+    let width = width.max(1) as usize;
+    cells.extend(gids.into_iter().enumerate().map(|(i, gid)| {
+        ((origin.0 + (i % width) as i32, origin.1 + (i / width) as i32), gid)
+    }));
+}
+
+/// Reads an external .tsx or .tsj tileset.
+fn read_tileset_file(file: &Path, first_gid: u32) -> Result<TilesetData> {
+    let text = fs::read_to_string(file).with_path(file)?;
+    let dir = file.parent().unwrap_or(Path::new(""));
+
+    if text.trim_start().starts_with('<') {
+        let doc = roxmltree::Document::parse(&text).map_err(|e| EditorError::import(file, e))?;
+        if !doc.root_element().has_tag_name("tileset") {
+            return Err(EditorError::import(file, "not a Tiled tileset"));
+        }
+        Ok(read_xml_tileset(doc.root_element(), first_gid, dir))
+    } else {
+        let tileset: JsonTileset = serde_json::from_str(&text).with_path(file)?;
+        Ok(tileset.into_data(first_gid, dir))
+    }
+}
+
+fn attr<T: std::str::FromStr>(node: Node, name: &str, default: T) -> T {
+    node.attribute(name).and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Value of the custom property `name` on `node`.
+fn xml_property<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .filter(|n| n.has_tag_name("properties"))
+        .flat_map(|n| n.children())
+        .find(|p| p.has_tag_name("property") && p.attribute("name") == Some(name))
+        .and_then(|p| p.attribute("value"))
+}
+
+fn read_xml_map(file: &Path, text: &str) -> Result<MapData> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| EditorError::import(file, e))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("map") {
+        return Err(EditorError::import(file, "not a Tiled map"));
+    }
+
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut tilesets = vec!();
+    for node in root.children().filter(|n| n.has_tag_name("tileset")) {
+        let first_gid = attr(node, "firstgid", 1);
+        tilesets.push(match node.attribute("source") {
+            Some(source) => read_tileset_file(&resolve(dir, source), first_gid)?,
+            None => read_xml_tileset(node, first_gid, dir)
+        });
+    }
+
+    let mut layers = vec!();
+    read_xml_layers(file, root, true, &mut layers)?;
+
+    Ok(MapData {
+        orientation: root.attribute("orientation").unwrap_or("orthogonal").to_string(),
+        stagger_axis: root.attribute("staggeraxis").unwrap_or("y").to_string(),
+        stagger_index: root.attribute("staggerindex").unwrap_or("odd").to_string(),
+        hex_side_length: attr(root, "hexsidelength", 0),
+        tile_size: (attr(root, "tilewidth", 0), attr(root, "tileheight", 0)),
+        origin: (
+            xml_property(root, "lilah_origin_x").and_then(|v| v.parse().ok()).unwrap_or(0),
+            xml_property(root, "lilah_origin_y").and_then(|v| v.parse().ok()).unwrap_or(0)
+        ),
+        tilesets,
+        layers
+    })
+}
+
+fn read_xml_tileset(node: Node, first_gid: u32, dir: &Path) -> TilesetData {
+    let image = node.children().find(|n| n.has_tag_name("image"));
+
+    TilesetData {
+        first_gid,
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        image: image.and_then(|i| i.attribute("source")).map(|s| resolve(dir, s)),
+        image_size: image.map(|i| (attr(i, "width", 0), attr(i, "height", 0))).unwrap_or_default(),
+        tile_size: (attr(node, "tilewidth", 0), attr(node, "tileheight", 0)),
+        margin: attr(node, "margin", 0),
+        spacing: attr(node, "spacing", 0),
+        columns: attr(node, "columns", 0),
+        tile_count: attr(node, "tilecount", 0)
+    }
+}
+
+/// Reads the layers under `parent`, flattening groups into their children.
+fn read_xml_layers(file: &Path, parent: Node, visible: bool, layers: &mut Vec<LayerData>) -> Result<()> {
+    for node in parent.children().filter(|n| n.is_element()) {
+        let visible = visible && attr(node, "visible", 1) != 0;
+        let kind = match node.tag_name().name() {
+            "layer" => LayerKind::Tiles(read_xml_tiles(file, node)?),
+            "objectgroup" => {
+                LayerKind::Objects(node.children().filter(|n| n.has_tag_name("object")).map(read_xml_object).collect())
+            }
+            "imagelayer" => LayerKind::Other("image layer"),
+            "group" => {
+                read_xml_layers(file, node, visible, layers)?;
+                continue;
+            }
+            _ => continue
+        };
+
+        layers.push(LayerData {
+            name: node.attribute("name").unwrap_or_default().to_string(),
+            kind,
+            visible,
+            collision: xml_property(node, "collision") == Some("true"),
+            tile_sheet: xml_property(node, "tile_sheet").map(str::to_string),
+            opacity: attr(node, "opacity", 1.0),
+            offset: (attr(node, "offsetx", 0.0), attr(node, "offsety", 0.0)),
+            parallax: (attr(node, "parallaxx", 1.0), attr(node, "parallaxy", 1.0)),
+            tint: node.attribute("tintcolor").and_then(parse_color),
+            locked: attr(node, "locked", 0) != 0
+        });
+    }
+
+    Ok(())
+}
+
+fn read_xml_tiles(file: &Path, layer: Node) -> Result<Vec<((i32, i32), u32)>> {
+    let mut cells = vec!();
+    let Some(data) = layer.children().find(|n| n.has_tag_name("data")) else {
+        return Ok(cells);
+    };
+
+    let encoding = data.attribute("encoding");
+    let compression = data.attribute("compression");
+    // `node` is the layer's data or one of its chunks, either way sized by `size`
+    let gids = |node: Node, size: Node| -> Result<Vec<u32>> {
+        let count = attr::<usize>(size, "width", 0) * attr::<usize>(size, "height", 0);
+        match encoding {
+            None => check_count(file, node.children().filter(|n| n.has_tag_name("tile")).map(|t| attr(t, "gid", 0)).collect(), count),
+            Some(encoding) => decode_gids(file, node.text().unwrap_or_default(), encoding, compression, count)
+        }
+    };
+
+    // Infinite maps split their data into chunks
+    let chunks = data.children().filter(|n| n.has_tag_name("chunk")).collect::<Vec<Node>>();
+    if chunks.is_empty() {
+        place(&mut cells, (0, 0), attr(layer, "width", 0), gids(data, layer)?);
+    }
+    for chunk in chunks {
+        place(&mut cells, (attr(chunk, "x", 0), attr(chunk, "y", 0)), attr(chunk, "width", 0), gids(chunk, chunk)?);
+    }
+
+    Ok(cells)
+}
+
+fn read_xml_object(node: Node) -> ObjectData {
+    let child = node.children()
+        .filter(|n| n.is_element() && !n.has_tag_name("properties"))
+        .map(|n| n.tag_name().name())
+        .next();
+
+    let shape = match child {
+        Some("point") => None,
+        Some("ellipse") => Some("ellipse"),
+        Some("polygon") => Some("polygon"),
+        Some("polyline") => Some("polyline"),
+        Some("text") => Some("text"),
+        _ if node.attribute("gid").is_some() => Some("tile"),
+        _ => Some("rectangle")
+    };
+
+    ObjectData {
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        position: (attr(node, "x", 0.0), attr(node, "y", 0.0)),
+        shape
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsonMap {
+    #[serde(rename = "type")]
+    kind: String,
+    orientation: String,
+    staggeraxis: Option<String>,
+    staggerindex: Option<String>,
+    hexsidelength: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    tilesets: Vec<JsonTileset>,
+    layers: Vec<JsonLayer>,
+    properties: Vec<JsonProperty>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsonTileset {
+    firstgid: u32,
+    source: Option<String>,
+    name: String,
+    image: Option<String>,
+    imagewidth: u32,
+    imageheight: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    tilecount: u32
+}
+
+impl JsonTileset {
+    fn into_data(self, first_gid: u32, dir: &Path) -> TilesetData {
+        TilesetData {
+            first_gid,
+            name: self.name,
+            image: self.image.map(|i| resolve(dir, &i)),
+            image_size: (self.imagewidth, self.imageheight),
+            tile_size: (self.tilewidth, self.tileheight),
+            margin: self.margin,
+            spacing: self.spacing,
+            columns: self.columns,
+            tile_count: self.tilecount
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    visible: bool,
+    opacity: f32,
+    offsetx: f32,
+    offsety: f32,
+    parallaxx: f32,
+    parallaxy: f32,
+    tintcolor: Option<String>,
+    locked: bool,
+    width: u32,
+    height: u32,
+    data: Option<JsonData>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    chunks: Vec<JsonChunk>,
+    objects: Vec<JsonObject>,
+    layers: Vec<JsonLayer>,
+    properties: Vec<JsonProperty>
+}
+
+impl Default for JsonLayer {
+    fn default() -> Self {
+        Self {
+            kind: String::new(),
+            name: String::new(),
+            visible: true,
+            opacity: 1.0,
+            offsetx: 0.0,
+            offsety: 0.0,
+            parallaxx: 1.0,
+            parallaxy: 1.0,
+            tintcolor: None,
+            locked: false,
+            width: 0,
+            height: 0,
+            data: None,
+            encoding: None,
+            compression: None,
+            chunks: Vec::new(),
+            objects: Vec::new(),
+            layers: Vec::new(),
+            properties: Vec::new()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonData {
+    Gids(Vec<u32>),
+    Encoded(String)
+}
+
+#[derive(Deserialize)]
+struct JsonChunk {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    data: JsonData
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsonObject {
+    name: String,
+    x: f32,
+    y: f32,
+    point: bool,
+    ellipse: bool,
+    polygon: Option<serde_json::Value>,
+    polyline: Option<serde_json::Value>,
+    text: Option<serde_json::Value>,
+    gid: Option<u32>
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value
+}
+
+/// Value of the custom property `name` in `properties`.
+fn json_property<'a>(properties: &'a [JsonProperty], name: &str) -> Option<&'a serde_json::Value> {
+    properties.iter().find(|p| p.name == name).map(|p| &p.value)
+}
+
+fn read_json_map(file: &Path, text: &str) -> Result<MapData> {
+    let map: JsonMap = serde_json::from_str(text).with_path(file)?;
+    if map.kind != "map" {
+        return Err(EditorError::import(file, "not a Tiled map"));
+    }
+
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut tilesets = vec!();
+    for tileset in map.tilesets {
+        let first_gid = tileset.firstgid;
+        tilesets.push(match &tileset.source {
+            Some(source) => read_tileset_file(&resolve(dir, source), first_gid)?,
+            None => tileset.into_data(first_gid, dir)
+        });
+    }
+
+    let mut layers = vec!();
+    read_json_layers(file, map.layers, true, &mut layers)?;
+
+    let origin = |name: &str| {
+        json_property(&map.properties, name).and_then(|v| v.as_i64()).unwrap_or(0) as i32
+    };
+
+    Ok(MapData {
+        orientation: if map.orientation.is_empty() { String::from("orthogonal") } else { map.orientation },
+        stagger_axis: map.staggeraxis.unwrap_or_else(|| String::from("y")),
+        stagger_index: map.staggerindex.unwrap_or_else(|| String::from("odd")),
+        hex_side_length: map.hexsidelength,
+        tile_size: (map.tilewidth, map.tileheight),
+        origin: (origin("lilah_origin_x"), origin("lilah_origin_y")),
+        tilesets,
+        layers
+    })
+}
+
+/// Reads `json_layers`, flattening groups into their children.
+fn read_json_layers(file: &Path, json_layers: Vec<JsonLayer>, visible: bool, layers: &mut Vec<LayerData>) -> Result<()> {
+    for layer in json_layers {
+        let visible = visible && layer.visible;
+        let gids = |data: JsonData, width: u32, height: u32| -> Result<Vec<u32>> {
+            let count = width as usize * height as usize;
+            match data {
+                JsonData::Gids(gids) => check_count(file, gids, count),
+                JsonData::Encoded(text) => {
+                    decode_gids(file, &text, layer.encoding.as_deref().unwrap_or("base64"), layer.compression.as_deref(), count)
+                }
+            }
+        };
+
+        let kind = match layer.kind.as_str() {
+            "tilelayer" => {
+                let mut cells = vec!();
+                if let Some(data) = layer.data {
+                    place(&mut cells, (0, 0), layer.width, gids(data, layer.width, layer.height)?);
+                }
+                for chunk in layer.chunks {
+                    place(&mut cells, (chunk.x, chunk.y), chunk.width, gids(chunk.data, chunk.width, chunk.height)?);
+                }
+                LayerKind::Tiles(cells)
+            }
+            "objectgroup" => {
+                LayerKind::Objects(layer.objects.into_iter().map(|o| {
+                    let shape = if o.point {
+                        None
+                    } else if o.ellipse {
+                        Some("ellipse")
+                    } else if o.polygon.is_some() {
+                        Some("polygon")
+                    } else if o.polyline.is_some() {
+                        Some("polyline")
+                    } else if o.text.is_some() {
+                        Some("text")
+                    } else if o.gid.is_some() {
+                        Some("tile")
+                    } else {
+                        Some("rectangle")
+                    };
+
+                    ObjectData { name: o.name, position: (o.x, o.y), shape }
+                }).collect())
+            }
+            "imagelayer" => LayerKind::Other("image layer"),
+            "group" => {
+                read_json_layers(file, layer.layers, visible, layers)?;
+                continue;
+            }
+            _ => continue
+        };
+
+        layers.push(LayerData {
+            name: layer.name,
+            kind,
+            visible,
+            collision: json_property(&layer.properties, "collision") == Some(&serde_json::Value::Bool(true)),
+            tile_sheet: json_property(&layer.properties, "tile_sheet").and_then(|v| v.as_str()).map(str::to_string),
+            opacity: layer.opacity,
+            offset: (layer.offsetx, layer.offsety),
+            parallax: (layer.parallaxx, layer.parallaxy),
+            tint: layer.tintcolor.as_deref().and_then(parse_color),
+            locked: layer.locked
+        });
+    }
+
+    Ok(())
+}
+
+/// Which flavour of map `export` writes. Tilesets are always written as .tsx.
+#[derive(Clone, Copy)]
+pub enum TiledFormat {
+    Tmx,
+    Tmj
+}
+
+struct ExportTileset {
+    first_gid: u32,
+    name: String,
+    source: String,
+    columns: u32
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes `scene` to `file` as a Tiled map, with a .tsx next to it for each tile sheet.
+/// Returns what couldn't be carried over exactly.
+pub fn export(scene: &Scene, file: &Path, format: TiledFormat) -> Result<Vec<String>> {
+    let mut warnings = vec!();
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+    let image_dir = fs::canonicalize(dir).with_path(dir)?;
+
+    // Tiled maps have a single grid, the first layer's
+    let (tile_w, tile_h) = scene.layers.first()
+        .map(|l| l.grid_size)
+        .filter(|s| s.0 > 0 && s.1 > 0)
+        .unwrap_or((16, 16));
+    let orientation = scene.orientation;
+
+    let mut tilesets: Vec<ExportTileset> = vec!();
+    let mut next_gid = 1;
+    for sheet in &scene.tile_sheets {
+        let (columns, rows) = sheet.get_num_of_tiles();
+        let stem = Path::new(&sheet.filename).file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let mut name = stem.clone();
+        while tilesets.iter().any(|t| t.name == name) {
+            name = format!("{}_{}", stem, tilesets.len());
+        }
+
+        let image = match Path::new(&sheet.path).is_absolute() {
+            true => pathdiff::diff_paths(&sheet.path, &image_dir).unwrap_or_else(|| PathBuf::from(&sheet.path)),
+            false => PathBuf::from(&sheet.path)
+        };
+
+        let source = format!("{}.tsx", name);
+        let tsx = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <tileset version=\"1.10\" name=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" spacing=\"{}\" margin=\"{}\" tilecount=\"{}\" columns=\"{}\">\n \
+            <image source=\"{}\" width=\"{}\" height=\"{}\"/>\n\
+            </tileset>\n",
+            escape(&name), sheet.tile_size.0, sheet.tile_size.1, sheet.spacing, sheet.margin, columns * rows, columns,
+            escape(&image.to_string_lossy().replace('\\', "/")), sheet.sheet_size.0, sheet.sheet_size.1
+        );
+        let path = dir.join(&source);
+        fs::write(&path, tsx).with_path(&path)?;
+
+        let lost = [
+            ("terrains", sheet.terrains.is_empty()),
+            ("properties", sheet.properties.is_empty()),
+            ("collision shapes", sheet.collision.is_empty()),
+            ("animations", sheet.animations.is_empty())
+        ];
+        let lost = lost.iter().filter(|(_, empty)| !empty).map(|(what, _)| *what).collect::<Vec<&str>>();
+        if !lost.is_empty() {
+            warnings.push(format!("tile sheet '{}': {} were not exported", sheet.filename, lost.join(", ")));
+        }
+
+        tilesets.push(ExportTileset { first_gid: next_gid, name, source, columns: columns.max(1) });
+        next_gid += (columns * rows).max(1);
+    }
+
+    for layer in &scene.object_layers {
+        warnings.push(format!("object layer '{}' and its {} entities were not exported", layer.name, layer.entities.len()));
+    }
+
+    // Tile keys are cells with y pointing up, Tiled wants them with y pointing down
+    let mut layers = vec!();
+    for (i, layer) in scene.layers.iter().enumerate() {
+        let mut cells = HashMap::new();
+        let mut off_grid = 0;
+        let mut covered = 0;
+        let mut unknown = 0;
+        for (position, tile) in &layer.tiles {
+            let Some(sheet) = scene.tile_sheets.iter().position(|s| s.path == tile.sheet) else {
+                unknown += 1;
+                continue;
+            };
+            // Layers on another grid go through pixels
+            let cell = match layer.grid_size == (tile_w, tile_h) {
+                true => *position,
+                false => {
+                    let point = layer.cell_position(orientation, *position);
+                    let cell = orientation.cell_at(point, (tile_w, tile_h));
+                    if orientation.cell_position(cell, (tile_w, tile_h)) != point {
+                        off_grid += 1;
+                    }
+                    cell
+                }
+            };
+            let cell = (cell.0, -cell.1);
+            let tileset = &tilesets[sheet];
+            // Several tiles of a finer grid can snap into one cell, only the last is kept
+            if cells.insert(cell, (tileset.first_gid + tile.sheet_id.1 * tileset.columns + tile.sheet_id.0) | gid_flags(tile)).is_some() {
+                covered += 1;
+            }
+        }
+
+        if off_grid > 0 {
+            warnings.push(format!("layer '{}': {} tiles were off the {}x{} grid and were snapped to it", layer.label(i), off_grid, tile_w, tile_h));
+        }
+        if covered > 0 {
+            warnings.push(format!("layer '{}': {} tiles were snapped into a cell another tile took and were lost", layer.label(i), covered));
+        }
+        if unknown > 0 {
+            warnings.push(format!("layer '{}': {} tiles use tile sheets the scene doesn't have and were skipped", layer.label(i), unknown));
+        }
+        layers.push(cells);
+    }
+
+    if !orientation.is_orthogonal() && !scene.markers.is_empty() {
+        warnings.push(format!("markers were placed as on an orthogonal map, Tiled measures objects differently on {} maps", orientation.tiled_name().0));
+    }
+    let markers = scene.markers.iter()
+        .map(|m| (m.name.as_str(), (m.position[0] + tile_w as f32 / 2.0, tile_h as f32 / 2.0 - m.position[1])))
+        .collect::<Vec<(&str, (f32, f32))>>();
+
+    let mut bounds: Option<((i32, i32), (i32, i32))> = None;
+    let marker_cells = markers.iter()
+        .map(|(_, p)| ((p.0 / tile_w as f32).floor() as i32, (p.1 / tile_h as f32).floor() as i32));
+    for cell in layers.iter().flat_map(|l| l.keys().copied()).chain(marker_cells) {
+        bounds = Some(match bounds {
+            Some((min, max)) => ((min.0.min(cell.0), min.1.min(cell.1)), (max.0.max(cell.0), max.1.max(cell.1))),
+            None => (cell, cell)
+        });
+    }
+    let (mut origin, max) = bounds.unwrap_or(((0, 0), (0, 0)));
+    // Moving the map by an odd row (or column) would shift the other rows instead
+    match orientation {
+        Orientation::Staggered | Orientation::HexPointy => origin.1 -= origin.1.rem_euclid(2),
+        Orientation::HexFlat => origin.0 -= origin.0.rem_euclid(2),
+        _ => ()
+    }
+    let (width, height) = ((max.0 - origin.0 + 1) as usize, (max.1 - origin.1 + 1) as usize);
+
+    let grid = |cells: &HashMap<(i32, i32), u32>| {
+        let mut data = vec![0u32; width * height];
+        for (cell, gid) in cells {
+            data[(cell.1 - origin.1) as usize * width + (cell.0 - origin.0) as usize] = *gid;
+        }
+        data
+    };
+    let sheet_name = |layer: &Layer| {
+        scene.tile_sheets.iter()
+            .position(|s| s.path == layer.tile_sheet)
+            .map(|i| tilesets[i].name.clone())
+            .unwrap_or_default()
+    };
+    let shift = ((origin.0 * tile_w as i32) as f32, (origin.1 * tile_h as i32) as f32);
+    // Tile layers take the first ids, then the markers' object layer if there is one
+    let next_layer_id = layers.len() + 1 + !markers.is_empty() as usize;
+
+    let text = match format {
+        TiledFormat::Tmj => {
+            let mut json_layers = vec!();
+            for (i, (layer, cells)) in scene.layers.iter().zip(&layers).enumerate() {
+                let mut json_layer = json!({
+                    "id": i + 1,
+                    "name": layer.label(i),
+                    "type": "tilelayer",
+                    "x": 0,
+                    "y": 0,
+                    "width": width,
+                    "height": height,
+                    "opacity": layer.opacity,
+                    "offsetx": layer.offset[0],
+                    "offsety": -layer.offset[1],
+                    "parallaxx": layer.parallax[0],
+                    "parallaxy": layer.parallax[1],
+                    "locked": layer.locked,
+                    "visible": layer.visible,
+                    "data": grid(cells),
+                    "properties": [
+                        { "name": "visible", "type": "bool", "value": layer.visible },
+                        { "name": "collision", "type": "bool", "value": layer.collision },
+                        { "name": "tile_sheet", "type": "string", "value": sheet_name(layer) }
+                    ]
+                });
+                if layer.tint != [1.0; 4] {
+                    json_layer["tintcolor"] = json!(format_color(layer.tint));
+                }
+                json_layers.push(json_layer);
+            }
+            if !markers.is_empty() {
+                json_layers.push(json!({
+                    "id": layers.len() + 1,
+                    "name": "markers",
+                    "type": "objectgroup",
+                    "draworder": "topdown",
+                    "x": 0,
+                    "y": 0,
+                    "opacity": 1,
+                    "visible": true,
+                    "objects": markers.iter().enumerate().map(|(i, (name, p))| json!({
+                        "id": i + 1,
+                        "name": name,
+                        "type": "",
+                        "point": true,
+                        "x": p.0 - shift.0,
+                        "y": p.1 - shift.1,
+                        "width": 0,
+                        "height": 0,
+                        "rotation": 0,
+                        "visible": true
+                    })).collect::<Vec<serde_json::Value>>()
+                }));
+            }
+
+            let mut map = json!({
+                "type": "map",
+                "version": "1.10",
+                "orientation": orientation.tiled_name().0,
+                "renderorder": "right-down",
+                "infinite": false,
+                "compressionlevel": -1,
+                "width": width,
+                "height": height,
+                "tilewidth": tile_w,
+                "tileheight": tile_h,
+                "nextlayerid": next_layer_id,
+                "nextobjectid": markers.len() + 1,
+                "properties": [
+                    { "name": "lilah_origin_x", "type": "int", "value": origin.0 },
+                    { "name": "lilah_origin_y", "type": "int", "value": origin.1 }
+                ],
+                "tilesets": tilesets.iter()
+                    .map(|t| json!({ "firstgid": t.first_gid, "source": t.source }))
+                    .collect::<Vec<serde_json::Value>>(),
+                "layers": json_layers
+            });
+            if let Some(axis) = orientation.tiled_name().1 {
+                map["staggeraxis"] = json!(axis);
+                map["staggerindex"] = json!("odd");
+            }
+            if orientation.hex_side_length((tile_w, tile_h)) > 0 {
+                map["hexsidelength"] = json!(orientation.hex_side_length((tile_w, tile_h)));
+            }
+            serde_json::to_string_pretty(&map).with_path(file)?
+        }
+        TiledFormat::Tmx => {
+            let mut stagger = String::new();
+            if let Some(axis) = orientation.tiled_name().1 {
+                stagger = format!(" staggeraxis=\"{}\" staggerindex=\"odd\"", axis);
+            }
+            if orientation.hex_side_length((tile_w, tile_h)) > 0 {
+                stagger.push_str(&format!(" hexsidelength=\"{}\"", orientation.hex_side_length((tile_w, tile_h))));
+            }
+            let mut xml = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                <map version=\"1.10\" orientation=\"{}\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" \
+                tilewidth=\"{}\" tileheight=\"{}\"{} infinite=\"0\" nextlayerid=\"{}\" nextobjectid=\"{}\">\n \
+                <properties>\n  \
+                <property name=\"lilah_origin_x\" type=\"int\" value=\"{}\"/>\n  \
+                <property name=\"lilah_origin_y\" type=\"int\" value=\"{}\"/>\n \
+                </properties>\n",
+                orientation.tiled_name().0, width, height, tile_w, tile_h, stagger, next_layer_id, markers.len() + 1, origin.0, origin.1
+            );
+            for tileset in &tilesets {
+                xml.push_str(&format!(" <tileset firstgid=\"{}\" source=\"{}\"/>\n", tileset.first_gid, escape(&tileset.source)));
+            }
+            for (i, (layer, cells)) in scene.layers.iter().zip(&layers).enumerate() {
+                let rows = grid(cells)
+                    .chunks(width)
+                    .map(|row| row.iter().map(|gid| gid.to_string()).collect::<Vec<String>>().join(","))
+                    .collect::<Vec<String>>()
+                    .join(",\n");
+                let tint = match layer.tint == [1.0; 4] {
+                    true => String::new(),
+                    false => format!(" tintcolor=\"{}\"", format_color(layer.tint))
+                };
+                xml.push_str(&format!(
+                    " <layer id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\" visible=\"{}\" locked=\"{}\" opacity=\"{}\" \
+                    offsetx=\"{}\" offsety=\"{}\" parallaxx=\"{}\" parallaxy=\"{}\"{}>\n  \
+                    <properties>\n   \
+                    <property name=\"visible\" type=\"bool\" value=\"{}\"/>\n   \
+                    <property name=\"collision\" type=\"bool\" value=\"{}\"/>\n   \
+                    <property name=\"tile_sheet\" value=\"{}\"/>\n  \
+                    </properties>\n  \
+                    <data encoding=\"csv\">\n{}\n</data>\n \
+                    </layer>\n",
+                    i + 1, escape(&layer.label(i)), width, height, layer.visible as u8, layer.locked as u8, layer.opacity,
+                    layer.offset[0], -layer.offset[1], layer.parallax[0], layer.parallax[1], tint,
+                    layer.visible, layer.collision, escape(&sheet_name(layer)), rows
+                ));
+            }
+            if !markers.is_empty() {
+                xml.push_str(&format!(" <objectgroup id=\"{}\" name=\"markers\">\n", layers.len() + 1));
+                for (i, (name, p)) in markers.iter().enumerate() {
+                    xml.push_str(&format!(
+                        "  <object id=\"{}\" name=\"{}\" x=\"{}\" y=\"{}\">\n   <point/>\n  </object>\n",
+                        i + 1, escape(name), p.0 - shift.0, p.1 - shift.1
+                    ));
+                }
+                xml.push_str(" </objectgroup>\n");
+            }
+            xml.push_str("</map>\n");
+            xml
+        }
+    };
+
+    fs::write(file, text).with_path(file)?;
+    Ok(warnings)
+}
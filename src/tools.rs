@@ -0,0 +1,174 @@
+//! Shapes and fills for the tile tools.
+//!
+//! Everything here works in cells, `(column, row)` with y up like the viewport, the same as
+//! tile keys.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::application::{Layer, Tile};
+use crate::orientation::Orientation;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tool {
+    /// Paints the cell under the mouse while a button is held
+    Brush,
+    Fill,
+    Rect,
+    FilledRect,
+    Line,
+    Ellipse,
+    /// Drags out a rectangle of the canvas to paint with as a stamp
+    Capture
+}
+
+impl Tool {
+    pub const ALL: [Tool; 7] = [Tool::Brush, Tool::Fill, Tool::Rect, Tool::FilledRect, Tool::Line, Tool::Ellipse, Tool::Capture];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Fill => "Bucket Fill",
+            Tool::Rect => "Rectangle",
+            Tool::FilledRect => "Filled Rectangle",
+            Tool::Line => "Line",
+            Tool::Ellipse => "Ellipse",
+            Tool::Capture => "Capture Stamp"
+        }
+    }
+
+    /// Whether the tool is dragged out from where the mouse was pressed to where it is released.
+    pub fn is_shape(&self) -> bool {
+        matches!(self, Tool::Rect | Tool::FilledRect | Tool::Line | Tool::Ellipse | Tool::Capture)
+    }
+
+    /// The cells the tool covers when dragged from `a` to `b`. The brush and fill cover just `b`.
+    pub fn shape(&self, a: (i32, i32), b: (i32, i32)) -> Vec<(i32, i32)> {
+        match self {
+            Tool::Brush | Tool::Fill => vec!(b),
+            Tool::Rect => rect(a, b, false),
+            Tool::FilledRect | Tool::Capture => rect(a, b, true),
+            Tool::Line => line(a, b),
+            Tool::Ellipse => ellipse(a, b)
+        }
+    }
+}
+
+fn bounds(a: (i32, i32), b: (i32, i32)) -> ((i32, i32), (i32, i32)) {
+    ((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1)))
+}
+
+/// The cells of the rectangle with corners `a` and `b`, just its border unless `filled`.
+pub fn rect(a: (i32, i32), b: (i32, i32), filled: bool) -> Vec<(i32, i32)> {
+    let (min, max) = bounds(a, b);
+
+    let mut cells = vec!();
+    for y in min.1..=max.1 {
+        for x in min.0..=max.0 {
+            if filled || x == min.0 || x == max.0 || y == min.1 || y == max.1 {
+                cells.push((x, y));
+            }
+        }
+    }
+
+    cells
+}
+
+/// The cells of a line from `a` to `b`, without gaps or doubled up corners.
+pub fn line(a: (i32, i32), b: (i32, i32)) -> Vec<(i32, i32)> {
+    let d = ((b.0 - a.0).abs(), -(b.1 - a.1).abs());
+    let s = ((b.0 - a.0).signum(), (b.1 - a.1).signum());
+    let mut error = d.0 + d.1;
+    let mut p = a;
+
+    let mut cells = vec!(p);
+    while p != b {
+        let e2 = error * 2;
+        if e2 >= d.1 {
+            error += d.1;
+            p.0 += s.0;
+        }
+        if e2 <= d.0 {
+            error += d.0;
+            p.1 += s.1;
+        }
+        cells.push(p);
+    }
+
+    cells
+}
+
+/// The outline of the ellipse that fits in the rectangle with corners `a` and `b`.
+pub fn ellipse(a: (i32, i32), b: (i32, i32)) -> Vec<(i32, i32)> {
+    let (min, max) = bounds(a, b);
+    let centre = ((min.0 + max.0) as f32 / 2.0, (min.1 + max.1) as f32 / 2.0);
+    let radius = ((max.0 - min.0 + 1) as f32 / 2.0, (max.1 - min.1 + 1) as f32 / 2.0);
+
+    let inside = |x: i32, y: i32| {
+        let d = ((x as f32 - centre.0) / radius.0, (y as f32 - centre.1) / radius.1);
+        d.0 * d.0 + d.1 * d.1 <= 1.0
+    };
+
+    // A cell is on the outline if it is inside and one of its edges isn't
+    let mut cells = vec!();
+    for y in min.1..=max.1 {
+        for x in min.0..=max.0 {
+            if inside(x, y) && !(inside(x - 1, y) && inside(x + 1, y) && inside(x, y - 1) && inside(x, y + 1)) {
+                cells.push((x, y));
+            }
+        }
+    }
+
+    cells
+}
+
+/// Whether two cells hold the same tile the same way round, ignoring where it sits.
+fn same(a: Option<&Tile>, b: Option<&Tile>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            a.sheet == b.sheet && a.sheet_id == b.sheet_id &&
+            (a.flip_x, a.flip_y, a.rotate) == (b.flip_x, b.flip_y, b.rotate)
+        }
+        (None, None) => true,
+        _ => false
+    }
+}
+
+/// The cells a bucket fill from `start` in `layer` covers: every cell holding the same tile as
+/// `start` that is joined to it by edges, or anywhere in the layer unless `contiguous`. `None`
+/// if that is more than `max_area` cells, which an empty area that isn't closed in always is. A
+/// fill that isn't `contiguous` only replaces tiles, so from an empty cell it covers nothing.
+/// Which cells share an edge depends on the scene's `orientation`.
+pub fn fill(layer: &Layer, orientation: Orientation, start: (i32, i32), contiguous: bool, max_area: usize) -> Option<Vec<(i32, i32)>> {
+    let target = layer.tiles.get(&start);
+
+    if !contiguous {
+        if target.is_none() {
+            return Some(vec!());
+        }
+
+        let cells = layer.tiles.iter()
+            .filter(|(_, tile)| same(Some(tile), target))
+            .map(|(cell, _)| *cell)
+            .collect::<Vec<(i32, i32)>>();
+
+        return (cells.len() <= max_area).then_some(cells);
+    }
+
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut cells = vec!();
+    while let Some(cell) = queue.pop_front() {
+        cells.push(cell);
+        if cells.len() > max_area {
+            return None;
+        }
+
+        for next in orientation.neighbours(cell) {
+            if same(layer.tiles.get(&next), target) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    Some(cells)
+}
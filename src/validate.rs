@@ -0,0 +1,234 @@
+//! Checks a project config and scenes for broken references and settings the generated main.rs
+//! or the lilah runtime won't accept.
+
+use std::{collections::{hash_map::Entry, HashMap}, fmt, path::Path};
+
+use crate::application::{AssetType, Config, LoadType, Scene};
+use crate::collision::CollisionShape;
+use crate::prefab::{self, Prefab};
+
+/// What a problem points at, so the editor can take the user there.
+#[derive(Clone, Debug)]
+pub enum Location {
+    Tile { layer: usize, position: (i32, i32) },
+    Layer(usize),
+    TileSheet(usize),
+    Marker(usize),
+    Entity { layer: usize, index: usize },
+    /// Key into `Config::assets`
+    Asset(String)
+}
+
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub location: Location,
+    pub message: String
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub fn check_scene(scene: &Scene) -> Vec<Problem> {
+    let mut problems = vec!();
+    let mut problem = |location: Location, message: String| problems.push(Problem { location, message });
+
+    for (i, sheet) in scene.tile_sheets.iter().enumerate() {
+        if sheet.tile_size.0 == 0 || sheet.tile_size.1 == 0 {
+            problem(Location::TileSheet(i), format!("tile sheet {} has a tile size of 0", sheet.filename));
+        }
+
+        let count = sheet.get_num_of_tiles();
+        for terrain in &sheet.terrains {
+            if terrain.rules.is_empty() {
+                problem(Location::TileSheet(i), format!("terrain {} of {} has no rules and can't be painted", terrain.name, sheet.filename));
+            }
+            for rule in terrain.rules.iter().filter(|r| r.sheet_id.0 >= count.0 || r.sheet_id.1 >= count.1) {
+                problem(Location::TileSheet(i), format!(
+                    "terrain {} of {} uses cell {:?}, {} only has {}x{}",
+                    terrain.name, sheet.filename, rule.sheet_id, sheet.filename, count.0, count.1
+                ));
+            }
+        }
+
+        let mut cells = sheet.properties.keys().filter(|c| c.0 >= count.0 || c.1 >= count.1).collect::<Vec<_>>();
+        cells.sort();
+        for cell in cells {
+            problem(Location::TileSheet(i), format!(
+                "{} has properties on cell {:?}, it only has {}x{}",
+                sheet.filename, cell, count.0, count.1
+            ));
+        }
+
+        let mut cells = sheet.collision.keys().collect::<Vec<_>>();
+        cells.sort();
+        for cell in cells {
+            if cell.0 >= count.0 || cell.1 >= count.1 {
+                problem(Location::TileSheet(i), format!(
+                    "{} has collision shapes on cell {:?}, it only has {}x{}",
+                    sheet.filename, cell, count.0, count.1
+                ));
+            }
+            if sheet.collision[cell].iter().any(|s| matches!(s, CollisionShape::Polygon(points) if points.len() < 3)) {
+                problem(Location::TileSheet(i), format!("{} cell {:?} has a polygon with fewer than 3 points", sheet.filename, cell));
+            }
+        }
+    }
+
+    for (i, layer) in scene.layers.iter().enumerate() {
+        let label = layer.label(i);
+        if !layer.tile_sheet.is_empty() && !scene.tile_sheets.iter().any(|s| s.path == layer.tile_sheet) {
+            problem(Location::Layer(i), format!("{} uses tile sheet {} which was removed", label, layer.tile_sheet));
+        }
+        if layer.grid_size.0 == 0 || layer.grid_size.1 == 0 {
+            problem(Location::Layer(i), format!("{} has a grid size of 0", label));
+        }
+        if !(0.0..=1.0).contains(&layer.opacity) {
+            problem(Location::Layer(i), format!("{} has opacity {}, it must be between 0 and 1", label, layer.opacity));
+        }
+
+        let mut positions = layer.tiles.keys().copied().collect::<Vec<(i32, i32)>>();
+        positions.sort();
+        for position in positions {
+            let tile = &layer.tiles[&position];
+            let location = Location::Tile { layer: i, position };
+            match scene.tile_sheets.iter().find(|s| s.path == tile.sheet) {
+                Some(sheet) => {
+                    let count = sheet.get_num_of_tiles();
+                    if tile.sheet_id.0 >= count.0 || tile.sheet_id.1 >= count.1 {
+                        problem(location, format!(
+                            "{} tile at cell {:?} uses sheet cell {:?}, {} only has {}x{}",
+                            label, position, tile.sheet_id, sheet.filename, count.0, count.1
+                        ));
+                    }
+                }
+                None => {
+                    problem(location, format!("{} tile at cell {:?} uses unknown tile sheet {}", label, position, tile.sheet));
+                }
+            }
+        }
+    }
+
+    for (l, layer) in scene.object_layers.iter().enumerate() {
+        for (e, entity) in layer.entities.iter().enumerate() {
+            let Some(sprite) = &entity.sprite else {
+                continue;
+            };
+            let location = Location::Entity { layer: l, index: e };
+            match entity.sheet(&scene.tile_sheets, &[]) {
+                Some(sheet) => {
+                    let count = sheet.get_num_of_tiles();
+                    if sprite.sheet_id.0 >= count.0 || sprite.sheet_id.1 >= count.1 {
+                        problem(location, format!(
+                            "entity {} on {} uses cell {:?}, {} only has {}x{}",
+                            entity.name, layer.name, sprite.sheet_id, sheet.filename, count.0, count.1
+                        ));
+                    }
+                }
+                None => {
+                    problem(location, format!("entity {} on {} uses unknown tile sheet {}", entity.name, layer.name, sprite.sheet));
+                }
+            }
+        }
+    }
+
+    let mut names = HashMap::new();
+    for (i, marker) in scene.markers.iter().enumerate() {
+        match names.entry(&marker.name) {
+            Entry::Occupied(first) => {
+                problem(Location::Marker(i), format!("marker {} has the same name as marker {}: {}", i, first.get(), marker.name));
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(i);
+            }
+        }
+    }
+
+    problems
+}
+
+/// Checks that the prefab instances of `scene` refer to prefabs in `prefabs`, and that the
+/// sprites they inherit are from tile sheets the scene has.
+pub fn check_instances(scene: &Scene, prefabs: &[Prefab]) -> Vec<Problem> {
+    let mut problems = vec!();
+    let mut problem = |location: Location, message: String| problems.push(Problem { location, message });
+
+    for (l, layer) in scene.object_layers.iter().enumerate() {
+        for (e, entity) in layer.entities.iter().enumerate() {
+            let Some(name) = &entity.prefab else {
+                continue;
+            };
+            let location = Location::Entity { layer: l, index: e };
+            match prefab::find(prefabs, name) {
+                Some(prefab) => {
+                    let inherited = prefab.sprite.as_ref().filter(|_| entity.sprite.is_none());
+                    if let Some(sprite) = inherited.filter(|s| !scene.tile_sheets.iter().any(|t| t.path == s.sheet)) {
+                        problem(location, format!(
+                            "entity {} on {} inherits a sprite from prefab {} on tile sheet {}, which the scene doesn't have",
+                            entity.name, layer.name, name, sprite.sheet
+                        ));
+                    }
+                }
+                None => {
+                    problem(location, format!("entity {} on {} is an instance of unknown prefab {}", entity.name, layer.name, name));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// Checks `config`, resolving asset paths against `project_dir`.
+pub fn check_config(config: &Config, project_dir: &Path) -> Vec<Problem> {
+    let mut problems = vec!();
+    let mut problem = |key: &str, message: String| problems.push(Problem { location: Location::Asset(key.to_string()), message });
+
+    let mut keys = config.assets.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    let mut load_orders: HashMap<usize, &str> = HashMap::new();
+    for key in keys {
+        let asset = &config.assets[key];
+
+        // Embedded assets are included from src/, external ones are loaded relative to the project root
+        let base = match asset.load_type {
+            LoadType::External => project_dir.to_path_buf(),
+            LoadType::Emdedded => project_dir.join("src")
+        };
+        let path = base.join(&asset.path);
+        let absolute_path = Path::new(&asset.absolute_path);
+        if !path.exists() {
+            problem(key, format!("{}: {} does not exist", asset.name, path.display()));
+        }
+        if absolute_path != path && !absolute_path.exists() {
+            problem(key, format!("{}: {} does not exist", asset.name, absolute_path.display()));
+        }
+
+        if let (AssetType::Script | AssetType::Font, LoadType::External) = (&asset.type_of, &asset.load_type) {
+            problem(key, format!("{}: {:?} assets cannot be external", asset.name, asset.type_of));
+        }
+
+        if let AssetType::Script = asset.type_of {
+            match asset.load_order {
+                Some(order) => {
+                    match load_orders.entry(order) {
+                        Entry::Occupied(other) => {
+                            problem(key, format!("{}: load order {} is also used by {}", asset.name, order, other.get()));
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(&asset.name);
+                        }
+                    }
+                }
+                None => {
+                    problem(key, format!("{}: script has no load order", asset.name));
+                }
+            }
+        }
+    }
+
+    problems
+}
@@ -0,0 +1,58 @@
+use lilah_editor::TileSheet;
+use lilah_editor::animation::{self, Frame};
+
+fn frames() -> Vec<Frame> {
+    vec!(
+        Frame { sheet_id: (0, 0), duration: 100 },
+        Frame { sheet_id: (1, 0), duration: 300 },
+        Frame { sheet_id: (2, 0), duration: 0 },
+        Frame { sheet_id: (3, 0), duration: 100 }
+    )
+}
+
+#[test]
+fn frame_at_steps_through_durations() {
+    let frames = frames();
+    assert_eq!(animation::length(&frames), 500);
+    assert_eq!(animation::frame_at(&frames, 0.0), Some((0, 0)));
+    assert_eq!(animation::frame_at(&frames, 0.099), Some((0, 0)));
+    assert_eq!(animation::frame_at(&frames, 0.1), Some((1, 0)));
+    assert_eq!(animation::frame_at(&frames, 0.399), Some((1, 0)));
+    // 0ms frames never show
+    assert_eq!(animation::frame_at(&frames, 0.4), Some((3, 0)));
+}
+
+#[test]
+fn frame_at_loops() {
+    let frames = frames();
+    assert_eq!(animation::frame_at(&frames, 0.5), Some((0, 0)));
+    assert_eq!(animation::frame_at(&frames, 10.45), Some((3, 0)));
+    // Times before the start clamp to it
+    assert_eq!(animation::frame_at(&frames, -1.0), Some((0, 0)));
+}
+
+#[test]
+fn frame_at_without_length_is_none() {
+    assert_eq!(animation::frame_at(&[], 1.0), None);
+    assert_eq!(animation::frame_at(&[Frame { sheet_id: (0, 0), duration: 0 }], 1.0), None);
+}
+
+#[test]
+fn animations_are_saved_with_the_sheet_only_when_there_are_some() {
+    let mut sheet: TileSheet = serde_json::from_value(serde_json::json!({
+        "filename": "tiles.png",
+        "path": "tiles.png",
+        "absolute_path": "tiles.png",
+        "tile_size": [16, 16],
+        "sheet_size": [64, 32]
+    })).unwrap();
+    assert!(serde_json::to_value(&sheet).unwrap().get("animations").is_none());
+
+    sheet.animations.insert((0, 1), frames());
+    let json = serde_json::to_value(&sheet).unwrap();
+    assert_eq!(json["animations"][0][0], serde_json::json!([0, 1]));
+    assert_eq!(json["animations"][0][1][1], serde_json::json!({ "sheet_id": [1, 0], "duration": 300 }));
+
+    let read: TileSheet = serde_json::from_value(json).unwrap();
+    assert_eq!(read.animations.get(&(0, 1)), Some(&frames()));
+}
@@ -0,0 +1,95 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{App, AssetType, EditorError, LoadType};
+
+/// An empty directory of its own for a test to use as a project.
+fn project(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("app").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn loading_a_directory_without_config_writes_one() {
+    let dir = project("loading_a_directory_without_config_writes_one");
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+    assert!(dir.join("config.json").is_file());
+    assert_eq!(app.config.window_size, (800.0, 600.0));
+}
+
+#[test]
+fn scenes_are_saved_and_read_back_by_path() {
+    let dir = project("scenes_are_saved_and_read_back_by_path");
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+
+    app.create_scene(&dir.join("level")).unwrap();
+    let scene = app.current_scene.as_ref().unwrap();
+    assert_eq!(scene.path, "level.json");
+    assert_eq!(scene.layers.len(), 1);
+
+    let sheet = dir.join("tiles.png");
+    app.add_tile_sheet(sheet.to_string_lossy().to_string(), String::from("tiles.png"), (64, 32), &[4, 2]).unwrap();
+
+    let mut other = App::new();
+    other.load_project(&dir).unwrap();
+    other.load_scene(&dir.join("level.json")).unwrap();
+    let sheets = &other.current_scene.as_ref().unwrap().tile_sheets;
+    assert_eq!(sheets.len(), 1);
+    assert_eq!(sheets[0].filename, "tiles.png");
+    assert_eq!(sheets[0].tile_size, (16, 16));
+}
+
+#[test]
+fn assets_are_typed_by_extension_and_undoable() {
+    let dir = project("assets_are_typed_by_extension_and_undoable");
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+
+    app.add_asset(&dir.join("assets/player.png"), LoadType::External).unwrap();
+    app.add_asset(&dir.join("src/scripts/main.wren"), LoadType::Emdedded).unwrap();
+    assert!(app.add_asset(&dir.join("notes.txt"), LoadType::External).is_err());
+
+    let script = app.config.assets.values().find(|a| a.name == "main.wren").unwrap();
+    assert!(matches!(script.type_of, AssetType::Script));
+    assert_eq!(script.path, "scripts/main.wren");
+    assert_eq!(script.load_order, Some(0));
+    let texture = app.config.assets.values().find(|a| a.name == "player.png").unwrap();
+    assert!(matches!(texture.type_of, AssetType::Texture));
+    assert_eq!(texture.path, "assets/player.png");
+
+    app.undo();
+    assert_eq!(app.config.assets.len(), 1);
+    app.undo();
+    assert!(app.config.assets.is_empty());
+    app.redo();
+    assert_eq!(app.config.assets.len(), 1);
+}
+
+#[test]
+fn errors_say_what_went_wrong_and_where() {
+    let mut app = App::new();
+    assert!(matches!(app.write_config(), Err(EditorError::NoProject)));
+
+    let dir = project("errors_say_what_went_wrong_and_where");
+    app.load_project(&dir).unwrap();
+    assert!(matches!(
+        app.add_asset(&dir.join("notes.txt"), LoadType::External),
+        Err(EditorError::UnsupportedAsset { extension, .. }) if extension == "txt"
+    ));
+
+    let broken = dir.join("broken.json");
+    fs::write(&broken, "{ \"name\": ").unwrap();
+    match app.load_scene(&broken) {
+        Err(e @ EditorError::Json { .. }) => assert!(e.to_string().starts_with(&broken.display().to_string())),
+        _ => panic!("expected a json error")
+    }
+    assert!(app.current_scene.is_none());
+
+    app.create_scene(&dir.join("level")).unwrap();
+    assert!(matches!(
+        app.add_tile_sheet(String::from("tiles.png"), String::from("tiles.png"), (64, 32), &[0, 2]),
+        Err(EditorError::InvalidTileCount { count: [0, 2], .. })
+    ));
+}
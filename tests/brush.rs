@@ -0,0 +1,119 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{App, Layer, Scene, Tile, TileSheet};
+use lilah_editor::brush::Stamp;
+use lilah_editor::schema::Extra;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("brush").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A 4x2 sheet of 16px tiles.
+fn sheet(path: &str) -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": path,
+        "path": path,
+        "absolute_path": path,
+        "tile_size": [16, 16],
+        "sheet_size": [64, 32]
+    })).unwrap()
+}
+
+fn tile(sheet: &str, sheet_id: (u32, u32)) -> Tile {
+    Tile { sheet: sheet.to_string(), sheet_id, flip_x: false, flip_y: false, rotate: false, extra: Extra::new() }
+}
+
+/// A layer's tiles as `(key, sheet_id)`, sorted.
+fn tiles(layer: &Layer) -> Vec<((i32, i32), (u32, u32))> {
+    let mut tiles = layer.tiles.iter().map(|(key, t)| (*key, t.sheet_id)).collect::<Vec<_>>();
+    tiles.sort();
+    tiles
+}
+
+fn app_with_layers(count: usize) -> App {
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet("tiles.png"));
+    scene.layers = (0..count).map(|_| Layer::new()).collect();
+
+    let mut app = App::new();
+    app.current_scene = Some(scene);
+    app
+}
+
+#[test]
+fn palette_stamps_keep_the_sheet_layout() {
+    let stamp = Stamp::from_sheet(&sheet("tiles.png"), (2, 1), (1, 0));
+    assert_eq!(stamp.name, "tiles.png 2x2");
+    assert_eq!(stamp.tile_size, (16, 16));
+
+    // Sheet rows go down, cell rows go up
+    let cells = stamp.layers[0].iter().map(|t| (t.cell, t.sheet_id)).collect::<Vec<_>>();
+    assert_eq!(cells, vec!(((0, 0), (1, 0)), ((1, 0), (2, 0)), ((0, -1), (1, 1)), ((1, -1), (2, 1))));
+
+    let writes = &stamp.tiles((3, 3), true)[0];
+    assert_eq!(writes[0].0, (3, 3));
+    assert_eq!(writes[3].0, (4, 2));
+    assert!(stamp.tiles((3, 3), false)[0].iter().all(|(_, tile)| tile.is_none()));
+}
+
+#[test]
+fn captured_stamps_paint_back_on_every_layer_and_undo_together() {
+    let mut app = app_with_layers(3);
+    {
+        let scene = app.current_scene.as_mut().unwrap();
+        scene.layers[0].tiles.insert((0, 0), tile("tiles.png", (0, 0)));
+        scene.layers[0].tiles.insert((1, 0), tile("tiles.png", (1, 0)));
+        scene.layers[1].tiles.insert((1, -1), tile("tiles.png", (3, 1)));
+    }
+
+    let stamp = Stamp::capture(&app.current_scene.as_ref().unwrap().layers[0..2], (0, -1), (1, 0));
+    assert_eq!(stamp.name, "Stamp 2x2");
+    assert_eq!(stamp.tile_size, (16, 16));
+    assert_eq!(stamp.layers.len(), 2);
+    assert!(!stamp.is_empty());
+
+    // One layer up, so the stamp's second layer lands on the top one
+    app.current_layer = 1;
+    app.paint_stamp(&stamp, (4, 0), true);
+    app.history.end_stroke();
+    let scene = app.current_scene.as_ref().unwrap();
+    assert_eq!(tiles(&scene.layers[1])[1..], [((4, 0), (0, 0)), ((5, 0), (1, 0))]);
+    assert_eq!(tiles(&scene.layers[2]), vec!(((5, -1), (3, 1))));
+
+    app.undo();
+    let scene = app.current_scene.as_ref().unwrap();
+    assert_eq!(tiles(&scene.layers[1]), vec!(((1, -1), (3, 1))));
+    assert!(scene.layers[2].tiles.is_empty());
+}
+
+#[test]
+fn stamps_skip_missing_sheets_and_layers() {
+    let mut stamp = Stamp::from_sheet(&sheet("other.png"), (0, 0), (0, 0));
+    stamp.layers.push(Stamp::from_sheet(&sheet("tiles.png"), (0, 0), (0, 0)).layers.remove(0));
+    assert_eq!(stamp.missing_sheets(&[sheet("tiles.png")]), vec!(String::from("other.png")));
+
+    let mut app = app_with_layers(1);
+    let changed = app.paint_stamp(&stamp, (0, 0), true);
+    assert_eq!(changed, vec!((0, vec!())));
+    assert!(app.current_scene.as_ref().unwrap().layers[0].tiles.is_empty());
+}
+
+#[test]
+fn the_brush_library_is_saved_with_the_project() {
+    let dir = scratch("the_brush_library_is_saved_with_the_project");
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+    app.save_brush(Stamp::from_sheet(&sheet("tiles.png"), (0, 0), (1, 1))).unwrap();
+
+    let mut reloaded = App::new();
+    reloaded.load_project(&dir).unwrap();
+    assert_eq!(reloaded.config.brushes.len(), 1);
+    assert_eq!(reloaded.config.brushes[0].layers[0].len(), 4);
+
+    reloaded.remove_brush(0).unwrap();
+    let mut again = App::new();
+    again.load_project(&dir).unwrap();
+    assert!(again.config.brushes.is_empty());
+}
@@ -0,0 +1,88 @@
+use lilah_editor::Tile;
+use lilah_editor::chunk::{chunk_of, ChunkedTiles, CHUNK_SIZE};
+use lilah_editor::schema::Extra;
+
+fn tile(sheet_id: (u32, u32)) -> Tile {
+    Tile { sheet: String::from("tiles.png"), sheet_id, flip_x: false, flip_y: false, rotate: false, extra: Extra::new() }
+}
+
+#[test]
+fn keys_are_filed_by_floor_division() {
+    assert_eq!(chunk_of((0, 0)), (0, 0));
+    assert_eq!(chunk_of((CHUNK_SIZE - 1, CHUNK_SIZE)), (0, 1));
+    assert_eq!(chunk_of((-1, -CHUNK_SIZE)), (-1, -1));
+    assert_eq!(chunk_of((-CHUNK_SIZE - 1, 0)), (-2, 0));
+}
+
+#[test]
+fn tiles_read_like_a_map() {
+    let mut tiles = ChunkedTiles::new();
+    assert!(tiles.insert((0, 0), tile((0, 0))).is_none());
+    assert!(tiles.insert((-16, 16), tile((1, 0))).is_none());
+    assert_eq!(tiles.insert((0, 0), tile((2, 0))).map(|t| t.sheet_id), Some((0, 0)));
+    assert_eq!(tiles.len(), 2);
+    assert_eq!(tiles[&(0, 0)].sheet_id, (2, 0));
+    assert_eq!(tiles.chunks().count(), 2);
+
+    assert!(tiles.remove(&(-16, 16)).is_some());
+    assert!(tiles.remove(&(-16, 16)).is_none());
+    assert_eq!(tiles.len(), 1);
+    // Emptied chunks go
+    assert_eq!(tiles.chunks().collect::<Vec<_>>(), vec!(&(0, 0)));
+}
+
+#[test]
+fn chunks_in_finds_the_chunks_a_view_overlaps() {
+    let tiles = [(0, 0), (CHUNK_SIZE, 0), (-1, -1), (CHUNK_SIZE * 10, CHUNK_SIZE * 10)].into_iter()
+        .map(|position| (position, tile((0, 0))))
+        .collect::<ChunkedTiles>();
+
+    let mut near = tiles.chunks_in((-8, -8), (CHUNK_SIZE + 8, 8));
+    near.sort();
+    assert_eq!(near, vec!((-1, -1), (0, 0), (1, 0)));
+
+    // Zoomed far out, more chunk coordinates are in view than there are chunks
+    let mut far = tiles.chunks_in((-CHUNK_SIZE * 100, -CHUNK_SIZE * 100), (CHUNK_SIZE * 100, CHUNK_SIZE * 100));
+    far.sort();
+    assert_eq!(far, vec!((-1, -1), (0, 0), (1, 0), (10, 10)));
+}
+
+#[test]
+fn chunks_round_trip_and_are_only_serialized_again_once_changed() {
+    let mut tiles = ChunkedTiles::new();
+    tiles.insert((0, 0), tile((1, 0)));
+    tiles.insert((CHUNK_SIZE, 0), tile((2, 0)));
+    tiles.insert((-16, -16), tile((3, 0)));
+    assert_eq!(tiles.dirty_chunks(), 3);
+
+    let json = serde_json::to_value(&tiles).unwrap();
+    assert_eq!(tiles.dirty_chunks(), 0);
+    // Sorted by chunk, each naming itself
+    assert_eq!(json[0]["chunk"], serde_json::json!([-1, -1]));
+    assert_eq!(json[2]["chunk"], serde_json::json!([1, 0]));
+
+    let read: ChunkedTiles = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(read.len(), 3);
+    assert_eq!(read[&(CHUNK_SIZE, 0)].sheet_id, (2, 0));
+
+    tiles.insert((8, 8), tile((0, 1)));
+    assert_eq!(tiles.dirty_chunks(), 1);
+    let again = serde_json::to_value(&tiles).unwrap();
+    assert_eq!(again[0], json[0]);
+    assert_eq!(again[2], json[2]);
+    assert_ne!(again[1], json[1]);
+}
+
+#[test]
+fn tiles_are_filed_by_their_own_key_whatever_chunk_the_file_says() {
+    let read: ChunkedTiles = serde_json::from_value(serde_json::json!([
+        { "chunk": [5, 5], "tiles": [[[0, 0], { "sheet": "tiles.png", "sheet_id": [1, 0], "position": [0, 0] }]] }
+    ])).unwrap();
+    assert_eq!(read.chunks().collect::<Vec<_>>(), vec!(&(0, 0)));
+
+    let twice = serde_json::from_value::<ChunkedTiles>(serde_json::json!([
+        { "chunk": [0, 0], "tiles": [[[0, 0], { "sheet": "tiles.png", "sheet_id": [1, 0], "position": [0, 0] }]] },
+        { "chunk": [0, 0], "tiles": [[[0, 0], { "sheet": "tiles.png", "sheet_id": [2, 0], "position": [0, 0] }]] }
+    ]));
+    assert!(twice.is_err());
+}
@@ -0,0 +1,101 @@
+use std::{fs, path::{Path, PathBuf}, process::{Command, Output}};
+
+fn lilah_editor(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lilah_editor")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// An empty directory of its own for a test to write into.
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A scene with one 4x2 tile sheet and a tile at each of `cells`, written to `dir`.
+fn write_scene(dir: &Path, cells: &[(i32, i32, u32, u32)]) -> PathBuf {
+    let tiles = cells.iter()
+        .map(|(x, y, cx, cy)| serde_json::json!([[x, y], { "sheet": "tiles.png", "sheet_id": [cx, cy], "position": [x, y] }]))
+        .collect::<Vec<serde_json::Value>>();
+    let scene = serde_json::json!({
+        "name": "level",
+        "path": "level.json",
+        "tile_sheets": [{
+            "filename": "tiles.png",
+            "path": "tiles.png",
+            "absolute_path": "tiles.png",
+            "tile_size": [16, 16],
+            "sheet_size": [64, 32]
+        }],
+        "layers": [{ "tiles": tiles, "visible": true, "collision": false, "tile_sheet": "tiles.png", "current_tile_item": 0 }],
+        "markers": [{ "position": [0.0, 0.0], "name": "spawn" }]
+    });
+    let file = dir.join("level.json");
+    fs::write(&file, scene.to_string()).unwrap();
+    file
+}
+
+#[test]
+fn new_open_and_wrangle_a_project() {
+    let dir = scratch("new_open_and_wrangle_a_project").join("game");
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = lilah_editor(&["new", dir_arg]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("config.json").is_file());
+    assert!(dir.join("assets").is_dir());
+    assert!(fs::read_to_string(dir.join("Cargo.toml")).unwrap().contains("lilah"));
+
+    let output = lilah_editor(&["open", dir_arg]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("window size: 800 x 600"));
+    assert!(stdout(&output).contains("assets: 0"));
+
+    fs::write(dir.join("src/main.rs"), "").unwrap();
+    let output = lilah_editor(&["wrangle", dir_arg]);
+    assert!(output.status.success());
+    assert!(fs::read_to_string(dir.join("src/main.rs")).unwrap().contains("lilah::application"));
+}
+
+#[test]
+fn open_fails_on_a_missing_directory() {
+    let dir = scratch("open_fails_on_a_missing_directory").join("missing");
+    let output = lilah_editor(&["open", dir.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a directory"));
+}
+
+#[test]
+fn validate_reports_cells_off_the_sheet() {
+    let dir = scratch("validate_reports_cells_off_the_sheet");
+    let good = write_scene(&dir, &[(0, 0, 0, 0), (16, 0, 3, 1)]);
+    let output = lilah_editor(&["validate", good.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with(": ok\n"));
+
+    let bad = write_scene(&dir, &[(0, 0, 4, 0)]);
+    let output = lilah_editor(&["validate", bad.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("uses sheet cell (4, 0), tiles.png only has 4x2"));
+}
+
+#[test]
+fn export_writes_the_scene_as_json() {
+    let dir = scratch("export_writes_the_scene_as_json");
+    let scene = write_scene(&dir, &[(0, 0, 1, 0)]);
+
+    let output = lilah_editor(&["export", scene.to_str().unwrap()]);
+    assert!(output.status.success());
+    let exported: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(exported["name"], "level");
+    assert_eq!(exported["markers"][0]["name"], "spawn");
+
+    let file = dir.join("out.json");
+    let output = lilah_editor(&["export", "-o", file.to_str().unwrap(), scene.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&fs::read_to_string(file).unwrap()).unwrap(), exported);
+}
@@ -0,0 +1,231 @@
+use std::{collections::HashSet, fs, path::{Path, PathBuf}};
+use lilah_editor::{Layer, Scene, Tile, TileSheet};
+use lilah_editor::application::collision_path;
+use lilah_editor::collision::{self, CollisionShape, MergedCollision};
+use lilah_editor::schema::Extra;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("collision").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// An alpha mask from rows of `#` (opaque) and `.` (clear).
+fn mask(rows: &[&str]) -> (Vec<u8>, u32, u32) {
+    let alpha = rows.iter().flat_map(|r| r.bytes().map(|b| if b == b'#' { 255 } else { 0 })).collect();
+    (alpha, rows[0].len() as u32, rows.len() as u32)
+}
+
+fn tile(flip_x: bool, flip_y: bool, rotate: bool) -> Tile {
+    Tile { sheet: String::from("tiles.png"), sheet_id: (0, 0), flip_x, flip_y, rotate, extra: Extra::new() }
+}
+
+/// A 4x4 sheet of `tile_size` tiles.
+fn sheet(tile_size: (u32, u32)) -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": "tiles.png",
+        "path": "tiles.png",
+        "absolute_path": "tiles.png",
+        "tile_size": tile_size,
+        "sheet_size": [tile_size.0 * 4, tile_size.1 * 4]
+    })).unwrap()
+}
+
+/// A scene with one collision layer on a `grid` grid, solid at `cells`.
+fn solid_scene(grid: (u32, u32), cells: &[(i32, i32)]) -> Scene {
+    let mut scene = Scene::new("collision", "collision.json");
+    scene.tile_sheets.push(sheet(grid));
+    let mut layer = Layer::new();
+    layer.tile_sheet = String::from("tiles.png");
+    layer.grid_size = grid;
+    layer.collision = true;
+    for cell in cells {
+        layer.tiles.insert(*cell, tile(false, false, false));
+    }
+    scene.layers.push(layer);
+    scene
+}
+
+/// Every cell `rects` cover, failing if two of them overlap.
+fn covered(rects: &[[i32; 4]]) -> HashSet<(i32, i32)> {
+    let mut cells = HashSet::new();
+    for [x, y, w, h] in rects {
+        for cx in *x..x + w {
+            for cy in *y..y + h {
+                assert!(cells.insert((cx, cy)), "({}, {}) is covered twice", cx, cy);
+            }
+        }
+    }
+    cells
+}
+
+/// Twice the signed area of `outline`, positive when it runs anticlockwise with y up.
+fn winding(outline: &[[f32; 2]]) -> f32 {
+    outline.iter().zip(outline.iter().cycle().skip(1))
+        .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+        .sum()
+}
+
+fn merged(scene: &Scene) -> MergedCollision {
+    let mut merged = collision::merge(scene, true);
+    assert_eq!(merged.len(), 1);
+    merged.remove(0)
+}
+
+#[test]
+fn an_opaque_cell_traces_to_a_box() {
+    let (alpha, w, h) = mask(&["###", "###"]);
+    assert_eq!(collision::trace(&alpha, w, h, 0, 0.5), vec!(CollisionShape::Box));
+
+    let (alpha, w, h) = mask(&["...", "..."]);
+    assert!(collision::trace(&alpha, w, h, 0, 0.5).is_empty());
+}
+
+#[test]
+fn trace_outlines_each_blob_clockwise() {
+    let (alpha, w, h) = mask(&[
+        "##..",
+        "##..",
+        "...#"
+    ]);
+    let shapes = collision::trace(&alpha, w, h, 128, 0.0);
+    assert_eq!(shapes.len(), 2);
+    assert!(shapes.contains(&CollisionShape::Polygon(vec!([0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]))));
+    assert!(shapes.contains(&CollisionShape::Polygon(vec!([3.0, 2.0], [4.0, 2.0], [4.0, 3.0], [3.0, 3.0]))));
+}
+
+#[test]
+fn trace_fills_holes_and_simplifies_steps() {
+    // A ring, whose hole becomes part of the shape
+    let (alpha, w, h) = mask(&["###", "#.#", "###"]);
+    assert_eq!(collision::trace(&alpha, w, h, 0, 0.0), vec!(CollisionShape::Polygon(vec!([0.0, 0.0], [3.0, 0.0], [3.0, 3.0], [0.0, 3.0]))));
+
+    // A staircase is cut down to its corners once the steps are within the tolerance
+    let (alpha, w, h) = mask(&["#...", "##..", "###.", "####"]);
+    let CollisionShape::Polygon(exact) = &collision::trace(&alpha, w, h, 0, 0.0)[0] else { panic!() };
+    let CollisionShape::Polygon(rough) = &collision::trace(&alpha, w, h, 0, 1.0)[0] else { panic!() };
+    assert!(rough.len() < exact.len());
+    assert!(rough.len() >= 3);
+}
+
+#[test]
+fn shapes_have_closed_edges_except_one_way_ledges() {
+    assert_eq!(CollisionShape::Box.edges((16, 8)).len(), 4);
+    assert_eq!(CollisionShape::Slope { left: 0.0, right: 8.0 }.edges((16, 8))[0], ([0.0, 8.0], [16.0, 0.0]));
+    assert_eq!(CollisionShape::OneWay { y: 2.0 }.edges((16, 8)), vec!(([0.0, 2.0], [16.0, 2.0])));
+}
+
+#[test]
+fn orient_follows_the_tile_flags() {
+    let point = [4.0, 1.0];
+    assert_eq!(collision::orient(point, (16, 8), &tile(false, false, false)), [4.0, 1.0]);
+    assert_eq!(collision::orient(point, (16, 8), &tile(true, false, false)), [12.0, 1.0]);
+    assert_eq!(collision::orient(point, (16, 8), &tile(false, true, false)), [4.0, 7.0]);
+    // Turned clockwise the cell is 8 wide and 16 high
+    assert_eq!(collision::orient(point, (16, 8), &tile(false, false, true)), [7.0, 4.0]);
+    assert_eq!(collision::orient(point, (16, 8), &tile(true, false, true)), [1.0, 4.0]);
+}
+
+#[test]
+fn merge_rects_joins_a_block_into_one() {
+    let cells = (0..3).flat_map(|x| (0..2).map(move |y| (x, y))).collect::<HashSet<(i32, i32)>>();
+    assert_eq!(collision::merge_rects(&cells), vec!([0, 0, 3, 2]));
+}
+
+#[test]
+fn merge_rects_covers_every_cell_once() {
+    // An L with a separate cell and a gap in the long arm
+    let cells = HashSet::from([
+        (0, 0), (1, 0), (2, 0), (4, 0),
+        (0, 1), (0, 2), (0, 3),
+        (6, 5), (-2, -3)
+    ]);
+    let rects = collision::merge_rects(&cells);
+    assert_eq!(covered(&rects), cells);
+    assert!(rects.len() < cells.len());
+}
+
+#[test]
+fn outlines_wind_around_ground_and_holes() {
+    // A 3x3 ring, its middle cell empty
+    let cells = (0..3).flat_map(|x| (0..3).map(move |y| (x, y)))
+        .filter(|c| *c != (1, 1))
+        .collect::<Vec<(i32, i32)>>();
+    let merged = merged(&solid_scene((16, 16), &cells));
+
+    assert_eq!(merged.outlines.len(), 2);
+    let (outer, hole) = match winding(&merged.outlines[0]) > 0.0 {
+        true => (&merged.outlines[0], &merged.outlines[1]),
+        false => (&merged.outlines[1], &merged.outlines[0])
+    };
+    assert!(winding(outer) > 0.0);
+    assert!(winding(hole) < 0.0);
+
+    // Cell positions are the middle of the cell, so the ring's edges are half a cell out
+    let bounds = |outline: &[[f32; 2]]| outline.iter().fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |b, p| {
+        [b[0].min(p[0]), b[1].min(p[1]), b[2].max(p[0]), b[3].max(p[1])]
+    });
+    assert_eq!(outer.len(), 4);
+    assert_eq!(bounds(outer), [-8.0, -8.0, 40.0, 40.0]);
+    assert_eq!(hole.len(), 4);
+    assert_eq!(bounds(hole), [8.0, 8.0, 24.0, 24.0]);
+}
+
+#[test]
+fn merged_rects_on_an_odd_grid_keep_their_half_pixels() {
+    let merged = merged(&solid_scene((15, 9), &[(0, 0), (1, 0)]));
+    assert_eq!(merged.rects, vec!([-7.5, -4.5, 30.0, 9.0]));
+    assert!(merged.tiles.is_empty());
+}
+
+#[test]
+fn merged_geometry_moves_with_the_layer_offset() {
+    let mut scene = solid_scene((16, 16), &[(0, 0), (1, 0)]);
+    scene.layers[0].offset = [4.0, -2.0];
+    scene.collision_outlines = true;
+
+    let merged = merged(&scene);
+    assert_eq!(merged.rects, vec!([-4.0, -10.0, 32.0, 16.0]));
+    assert!(merged.outlines[0].contains(&[28.0, 6.0]));
+    assert_eq!(merged.offset, [4.0, -2.0]);
+}
+
+#[test]
+fn tiles_with_other_shapes_are_left_to_the_sheet() {
+    let mut scene = solid_scene((16, 16), &[(0, 0), (1, 0), (2, 0)]);
+    let mut slope = tile(false, false, false);
+    slope.sheet_id = (1, 0);
+    scene.tile_sheets[0].collision.insert((1, 0), vec!(CollisionShape::Slope { left: 0.0, right: 16.0 }));
+    scene.layers[0].tiles.insert((3, 0), slope);
+    // From a sheet of bigger tiles than the layer's grid
+    let mut big = sheet((32, 32));
+    big.path = String::from("big.png");
+    scene.tile_sheets.push(big);
+    let mut boulder = tile(false, false, false);
+    boulder.sheet = String::from("big.png");
+    scene.layers[0].tiles.insert((5, 0), boulder);
+
+    let merged = merged(&scene);
+    assert_eq!(merged.rects, vec!([-8.0, -8.0, 48.0, 16.0]));
+    assert_eq!(merged.tiles, vec!((3, 0), (5, 0)));
+}
+
+#[test]
+fn writing_a_scene_keeps_its_collision_sidecar_in_step() {
+    let dir = scratch("writing_a_scene_keeps_its_collision_sidecar_in_step");
+    let file = dir.join("level.json");
+    let mut scene = solid_scene((16, 16), &[(0, 0), (1, 0)]);
+
+    scene.write(&file).unwrap();
+    let sidecar: serde_json::Value = serde_json::from_str(&fs::read_to_string(collision_path(&file)).unwrap()).unwrap();
+    assert_eq!(sidecar["layers"][0]["layer"], 0);
+    assert_eq!(sidecar["layers"][0]["rects"], serde_json::json!([[-8.0, -8.0, 32.0, 16.0]]));
+    assert!(sidecar["layers"][0].get("outlines").is_none());
+
+    // A scene with nothing solid left takes its sidecar with it
+    scene.layers[0].collision = false;
+    scene.write(&file).unwrap();
+    assert!(!collision_path(&file).exists());
+    assert!(file.exists());
+}
@@ -0,0 +1,334 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{App, Layer, Orientation, Scene, Tile, TileSheet};
+use lilah_editor::history::Edit;
+use lilah_editor::schema::Extra;
+use lilah_editor::validate;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("layers").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn tile() -> Tile {
+    sheet_tile("tiles.png", (0, 0))
+}
+
+fn sheet_tile(sheet: &str, sheet_id: (u32, u32)) -> Tile {
+    Tile { sheet: sheet.to_string(), sheet_id, flip_x: false, flip_y: false, rotate: false, extra: Extra::new() }
+}
+
+fn sheet(path: &str) -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": path,
+        "path": path,
+        "absolute_path": path,
+        "tile_size": [16, 16],
+        "sheet_size": [64, 32]
+    })).unwrap()
+}
+
+/// The names of the current scene's layers, bottom first.
+fn names(app: &App) -> Vec<String> {
+    app.current_scene.as_ref().unwrap().layers.iter().map(|l| l.name.clone()).collect()
+}
+
+/// An app with a scene of layers named `names`, bottom first, each with a tile at `(0, 0)`.
+fn named(names: &[&str]) -> App {
+    let mut app = app(names.len());
+    for (layer, name) in app.current_scene.as_mut().unwrap().layers.iter_mut().zip(names) {
+        layer.name = name.to_string();
+        layer.tiles.insert((0, 0), tile());
+    }
+    app
+}
+
+/// An app with a scene of `layers` empty layers and no project.
+fn app(layers: usize) -> App {
+    let mut scene = Scene::new("level", "level.json");
+    scene.layers = (0..layers).map(|_| Layer::new()).collect();
+    let mut app = App::new();
+    app.current_scene = Some(scene);
+    app
+}
+
+#[test]
+fn unnamed_layers_are_labelled_by_index() {
+    let mut layer = Layer::new();
+    assert_eq!(layer.label(3), "Layer 3");
+    layer.name = String::from("Background");
+    assert_eq!(layer.label(3), "Background");
+}
+
+#[test]
+fn opacity_folds_into_the_tint() {
+    let mut layer = Layer::new();
+    assert_eq!(layer.color(), [1.0, 1.0, 1.0, 1.0]);
+    layer.tint = [1.0, 0.5, 0.0, 0.5];
+    layer.opacity = 0.5;
+    assert_eq!(layer.color(), [1.0, 0.5, 0.0, 0.25]);
+}
+
+#[test]
+fn parallax_holds_a_layer_back_from_the_camera() {
+    let mut layer = Layer::new();
+    layer.offset = [10.0, -4.0];
+    assert_eq!(layer.shift([100.0, 50.0]), [10.0, -4.0]);
+
+    layer.parallax = [0.5, 0.0];
+    assert_eq!(layer.shift([100.0, 50.0]), [60.0, 46.0]);
+    layer.parallax = [2.0, 1.0];
+    assert_eq!(layer.shift([100.0, 50.0]), [-90.0, -4.0]);
+}
+
+#[test]
+fn layers_from_older_scenes_read_with_defaults() {
+    let layer: Layer = serde_json::from_value(serde_json::json!({
+        "tile_sheet": "tiles.png", "visible": true, "collision": false, "chunks": [], "current_tile_item": 0
+    })).unwrap();
+    assert_eq!(layer.name, "");
+    assert_eq!((layer.opacity, layer.offset, layer.parallax), (1.0, [0.0, 0.0], [1.0, 1.0]));
+    assert_eq!(layer.tint, [1.0, 1.0, 1.0, 1.0]);
+    assert!(!layer.locked);
+}
+
+#[test]
+fn locked_layers_are_not_painted() {
+    let mut app = app(2);
+    app.current_scene.as_mut().unwrap().layers[1].locked = true;
+
+    assert_eq!(app.set_layer_tiles(1, vec!(((0, 0), Some(tile())))), vec!());
+    assert_eq!(app.set_layer_tiles(0, vec!(((0, 0), Some(tile())))), vec!((0, 0)));
+    let scene = app.current_scene.as_ref().unwrap();
+    assert!(scene.layers[1].tiles.is_empty());
+    assert_eq!(scene.layers[0].tiles.len(), 1);
+}
+
+#[test]
+fn layer_settings_undo_and_redo() {
+    let mut app = named(&["ground"]);
+    let layer = &mut app.current_scene.as_mut().unwrap().layers[0];
+    let before = layer.settings();
+    layer.name = String::from("hills");
+    layer.grid_size = (8, 8);
+    layer.collision = true;
+    layer.locked = true;
+    layer.opacity = 0.5;
+    layer.tint = [1.0, 0.0, 0.0, 1.0];
+    layer.offset = [4.0, 2.0];
+    layer.parallax = [0.5, 0.5];
+    let after = layer.settings();
+    app.history.push(Edit::ChangeLayer { index: 0, before: before.clone(), after: after.clone() });
+
+    app.undo();
+    let layer = &app.current_scene.as_ref().unwrap().layers[0];
+    assert_eq!(layer.settings(), before);
+    assert_eq!((layer.name.as_str(), layer.grid_size, layer.locked), ("ground", (16, 16), false));
+    // Tiles are left as they are
+    assert_eq!(layer.tiles.len(), 1);
+
+    app.redo();
+    assert_eq!(app.current_scene.as_ref().unwrap().layers[0].settings(), after);
+}
+
+#[test]
+fn deleting_a_layer_undoes_back_into_place() {
+    let mut app = named(&["a", "b", "c"]);
+    app.current_layer = 2;
+
+    assert_eq!(app.remove_layer(1).unwrap().name, "b");
+    assert_eq!(names(&app), vec!("a", "c"));
+    assert_eq!(app.current_layer, 1);
+
+    app.undo();
+    assert_eq!(names(&app), vec!("a", "b", "c"));
+    assert_eq!(app.current_scene.as_ref().unwrap().layers[1].tiles.len(), 1);
+    app.redo();
+    assert_eq!(names(&app), vec!("a", "c"));
+
+    // The last layer stays
+    let mut app = named(&["only"]);
+    assert!(app.remove_layer(0).is_none());
+    assert!(!app.history.can_undo());
+}
+
+#[test]
+fn duplicating_a_layer_puts_the_copy_above_it() {
+    let mut app = named(&["a", "b"]);
+    assert_eq!(app.duplicate_layer(0), Some(1));
+    assert_eq!(names(&app), vec!("a", "a copy", "b"));
+    assert_eq!(app.current_layer, 1);
+    assert_eq!(app.current_scene.as_ref().unwrap().layers[1].tiles.len(), 1);
+
+    app.undo();
+    assert_eq!(names(&app), vec!("a", "b"));
+    assert_eq!(app.current_layer, 1);
+}
+
+#[test]
+fn moving_a_layer_takes_the_current_layer_with_it() {
+    let mut app = named(&["a", "b", "c"]);
+    app.current_layer = 0;
+
+    assert!(app.move_layer(0, 2));
+    assert_eq!(names(&app), vec!("b", "c", "a"));
+    assert_eq!(app.current_layer, 2);
+
+    app.undo();
+    assert_eq!(names(&app), vec!("a", "b", "c"));
+    assert_eq!(app.current_layer, 0);
+    app.redo();
+    assert_eq!(names(&app), vec!("b", "c", "a"));
+    assert_eq!(app.current_layer, 2);
+
+    assert!(!app.move_layer(1, 1));
+    assert!(!app.move_layer(0, 3));
+}
+
+#[test]
+fn merging_down_covers_the_layer_below_and_undoes_in_one_step() {
+    let mut app = named(&["ground", "detail"]);
+    let layers = &mut app.current_scene.as_mut().unwrap().layers;
+    layers[1].tiles.insert((0, 0), sheet_tile("tiles.png", (1, 0)));
+    layers[1].tiles.insert((1, 0), sheet_tile("tiles.png", (2, 0)));
+    layers[0].tiles.insert((2, 0), sheet_tile("tiles.png", (3, 0)));
+    app.current_layer = 1;
+
+    let mut positions = app.merge_layer_down(1).unwrap().unwrap();
+    positions.sort();
+    assert_eq!(positions, vec!((0, 0), (1, 0)));
+    assert_eq!(names(&app), vec!("ground"));
+    assert_eq!(app.current_layer, 0);
+    let ground = &app.current_scene.as_ref().unwrap().layers[0];
+    assert_eq!(ground.tiles[&(0, 0)].sheet_id, (1, 0));
+    assert_eq!(ground.tiles[&(1, 0)].sheet_id, (2, 0));
+    assert_eq!(ground.tiles[&(2, 0)].sheet_id, (3, 0));
+
+    app.undo();
+    assert_eq!(names(&app), vec!("ground", "detail"));
+    let ground = &app.current_scene.as_ref().unwrap().layers[0];
+    assert_eq!(ground.tiles.len(), 2);
+    assert_eq!(ground.tiles[&(0, 0)].sheet_id, (0, 0));
+    assert!(!app.history.can_undo());
+
+    // Not onto a locked layer, and not from the bottom
+    app.current_scene.as_mut().unwrap().layers[0].locked = true;
+    assert!(app.merge_layer_down(1).unwrap().is_none());
+    assert!(app.merge_layer_down(0).unwrap().is_none());
+}
+
+#[test]
+fn merging_down_onto_another_grid_undoes_to_the_lower_tiles() {
+    let mut app = named(&["ground", "detail"]);
+    let layers = &mut app.current_scene.as_mut().unwrap().layers;
+    // The 8px cells (3, 0) and (4, 0) both round to the 16px cell (2, 0)
+    layers[1].grid_size = (8, 8);
+    layers[1].tiles.insert((3, 0), sheet_tile("tiles.png", (1, 0)));
+    layers[1].tiles.insert((4, 0), sheet_tile("tiles.png", (2, 0)));
+
+    // The copy of ground's own tile at (0, 0) isn't a change
+    assert_eq!(app.merge_layer_down(1).unwrap().unwrap(), vec!((2, 0)));
+    let ground = &app.current_scene.as_ref().unwrap().layers[0];
+    assert_eq!(ground.tiles.len(), 2);
+
+    app.undo();
+    let ground = &app.current_scene.as_ref().unwrap().layers[0];
+    assert_eq!(ground.tiles.len(), 1);
+    assert_eq!(ground.tiles[&(0, 0)].sheet_id, (0, 0));
+    assert_eq!(app.current_scene.as_ref().unwrap().layers[1].tiles.len(), 3);
+}
+
+#[test]
+fn merging_down_keeps_tiles_where_the_offset_drew_them() {
+    let mut app = named(&["ground", "detail"]);
+    let layers = &mut app.current_scene.as_mut().unwrap().layers;
+    // A cell and a half apart, more than half a cell past ground's (2, 0) is its (3, 0)
+    layers[1].offset = [24.0, 0.0];
+    layers[0].offset = [-2.0, 0.0];
+    layers[1].tiles.remove(&(0, 0));
+    layers[1].tiles.insert((1, 0), sheet_tile("tiles.png", (1, 0)));
+
+    assert_eq!(app.merge_layer_down(1).unwrap().unwrap(), vec!((3, 0)));
+    let ground = &app.current_scene.as_ref().unwrap().layers[0];
+    assert_eq!(ground.tiles[&(3, 0)].sheet_id, (1, 0));
+}
+
+#[test]
+fn merging_down_refuses_layers_drawn_differently() {
+    let mut app = named(&["ground", "detail"]);
+    let layers = &mut app.current_scene.as_mut().unwrap().layers;
+    layers[1].tiles.insert((1, 0), sheet_tile("tiles.png", (1, 0)));
+    layers[1].opacity = 0.5;
+    layers[1].visible = false;
+
+    let error = app.merge_layer_down(1).unwrap_err().to_string();
+    assert_eq!(error, "layer 1 can't be merged down, its visibility, opacity differ from the layer below");
+    assert_eq!(names(&app), vec!("ground", "detail"));
+    assert!(!app.history.can_undo());
+}
+
+#[test]
+fn cells_sit_on_the_layer_grid() {
+    let mut layer = Layer::new();
+    assert_eq!(layer.grid_size, (16, 16));
+    assert_eq!(layer.cell_position(Orientation::Orthogonal, (2, -1)), [32.0, -16.0]);
+    assert_eq!(layer.cell_at(Orientation::Orthogonal, [39.0, -9.0]), (2, -1));
+
+    layer.grid_size = (8, 24);
+    assert_eq!(layer.cell_position(Orientation::Orthogonal, (3, 1)), [24.0, 24.0]);
+    assert_eq!(layer.cell_at(Orientation::Orthogonal, [20.5, 35.0]), (3, 1));
+}
+
+#[test]
+fn copying_a_layer_to_another_scene_brings_its_sheets() {
+    let dir = scratch("copying_a_layer_to_another_scene_brings_its_sheets");
+    let other = dir.join("other.json");
+    let mut target = Scene::new("other", "other.json");
+    target.tile_sheets.push(sheet("props.png"));
+    target.write(&other).unwrap();
+
+    let mut app = app(2);
+    let scene = app.current_scene.as_mut().unwrap();
+    scene.tile_sheets = vec!(sheet("tiles.png"), sheet("props.png"), sheet("unused.png"));
+    let layer = &mut scene.layers[1];
+    layer.tile_sheet = String::from("tiles.png");
+    layer.tiles.insert((0, 0), sheet_tile("tiles.png", (1, 0)));
+    layer.tiles.insert((16, 0), sheet_tile("props.png", (0, 1)));
+
+    app.copy_layer_to_scene(1, &other).unwrap();
+    let target = Scene::load(&other).unwrap();
+    let paths = target.tile_sheets.iter().map(|s| s.path.as_str()).collect::<Vec<&str>>();
+    assert_eq!(paths, vec!("props.png", "tiles.png"));
+    assert_eq!(target.layers.len(), 1);
+    let layer = &target.layers[0];
+    assert_eq!(layer.name, "Layer 1");
+    assert_eq!(layer.current_tile_item, 1);
+    assert_eq!(layer.tiles.len(), 2);
+
+    // The current scene is left as it was
+    assert!(!app.history.can_undo());
+}
+
+#[test]
+fn a_layer_keeps_tiles_from_several_sheets() {
+    let dir = scratch("a_layer_keeps_tiles_from_several_sheets");
+    let file = dir.join("level.json");
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets = vec!(sheet("tiles.png"), sheet("props.png"));
+    let mut layer = Layer::new();
+    layer.tile_sheet = String::from("tiles.png");
+    layer.tiles.insert((0, 0), sheet_tile("tiles.png", (1, 0)));
+    layer.tiles.insert((16, 0), sheet_tile("props.png", (2, 1)));
+    // Picking another sheet for the palette leaves the painted tiles alone
+    layer.tile_sheet = String::from("props.png");
+    layer.current_tile_item = 1;
+    scene.layers.push(layer);
+
+    scene.write(&file).unwrap();
+    let layer = &Scene::load(&file).unwrap().layers[0];
+    assert_eq!((layer.tile_sheet.as_str(), layer.current_tile_item), ("props.png", 1));
+    assert_eq!(layer.tiles[&(0, 0)].sheet, "tiles.png");
+    assert_eq!(layer.tiles[&(16, 0)].sheet, "props.png");
+    assert!(validate::check_scene(&Scene::load(&file).unwrap()).is_empty());
+}
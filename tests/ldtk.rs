@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use lilah_editor::Layer;
+use lilah_editor::{collision, ldtk};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ldtk").join(name)
+}
+
+/// A tile as `(key, sheet_id, (flip_x, flip_y, rotate))`.
+type KeyedTile = ((i32, i32), (u32, u32), (bool, bool, bool));
+
+/// A layer's tiles, sorted.
+fn tiles(layer: &Layer) -> Vec<KeyedTile> {
+    let mut tiles = layer.tiles.iter()
+        .map(|(key, t)| (*key, t.sheet_id, (t.flip_x, t.flip_y, t.rotate)))
+        .collect::<Vec<_>>();
+    tiles.sort();
+    tiles
+}
+
+#[test]
+fn levels_become_scenes_with_layers_bottom_first() {
+    let import = ldtk::import(&fixture("project.ldtk")).unwrap();
+    assert_eq!(import.scenes.len(), 2);
+
+    let scene = &import.scenes[0];
+    assert_eq!((scene.name.as_str(), scene.path.as_str()), ("Level_0", "Level_0.json"));
+    assert_eq!(scene.tile_sheets.len(), 1);
+    assert_eq!(scene.tile_sheets[0].filename, "tiles.png");
+    assert_eq!((scene.tile_sheets[0].tile_size, scene.tile_sheets[0].sheet_size), ((16, 16), (64, 32)));
+
+    // LDtk lists "Ground" last, so it is at the bottom
+    assert_eq!(scene.layers.len(), 2);
+    let ground = &scene.layers[0];
+    assert!(ground.visible && !ground.collision);
+    assert_eq!((ground.name.as_str(), ground.opacity), ("Ground", 0.5));
+    assert_eq!(tiles(ground), vec!(
+        ((0, 0), (1, 0), (false, false, false)),
+        ((1, -1), (0, 1), (true, false, false))
+    ));
+
+    let collision = &scene.layers[1];
+    assert!(!collision.visible && collision.collision);
+    assert_eq!(collision.name, "Collisions collision");
+    assert_eq!(collision.tiles.keys().copied().collect::<std::collections::BTreeSet<_>>(), [(0, 0), (2, 0), (2, -1)].into());
+    assert_eq!((ground.grid_size, collision.grid_size), ((16, 16), (16, 16)));
+
+    assert_eq!(scene.markers.len(), 1);
+    assert_eq!((scene.markers[0].name.as_str(), scene.markers[0].position), ("Player", [16.0, 0.0]));
+
+    assert_eq!(import.warnings, vec!(
+        String::from("tileset 'Atlas' has no image and was skipped"),
+        String::from("level 'Level_0': layer 'Collisions': 2 int grid values were merged into one collision layer")
+    ));
+}
+
+#[test]
+fn external_levels_are_read_from_their_own_files() {
+    let import = ldtk::import(&fixture("project.ldtk")).unwrap();
+    let scene = &import.scenes[1];
+    assert_eq!(scene.name, "Level_1");
+
+    // A level with nothing to paint on still gets a layer
+    assert_eq!(scene.layers.len(), 1);
+    assert!(scene.layers[0].tiles.is_empty());
+    assert_eq!((scene.markers[0].name.as_str(), scene.markers[0].position), ("Exit", [8.0, 0.0]));
+}
+
+#[test]
+fn import_fails_on_missing_or_broken_projects() {
+    assert!(ldtk::import(&fixture("missing.ldtk")).is_err());
+    assert!(ldtk::import(&fixture("../tiled/map.tmx")).is_err());
+}
+
+#[test]
+fn collision_layers_merge_into_boxes() {
+    let import = ldtk::import(&fixture("project.ldtk")).unwrap();
+    let scene = &import.scenes[0];
+    assert_eq!(scene.layers[1].tile_sheet, scene.tile_sheets[0].path);
+
+    let merged = collision::merge(scene, false);
+    assert_eq!(merged.len(), 1);
+    assert!(merged[0].tiles.is_empty(), "{:?}", merged[0].tiles);
+    assert_eq!(merged[0].rects.len(), 2);
+}
+
+#[test]
+fn collision_layers_without_a_sheet_their_size_are_left_unmerged() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ldtk").join("collision_grid");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // Only the first level, with its int grid on 8px cells
+    let mut project: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(fixture("project.ldtk")).unwrap()).unwrap();
+    project["levels"].as_array_mut().unwrap().truncate(1);
+    for layer in project["levels"][0]["layerInstances"].as_array_mut().unwrap() {
+        if layer["__type"] == "IntGrid" {
+            layer["__gridSize"] = serde_json::json!(8);
+        }
+    }
+    std::fs::write(dir.join("project.ldtk"), project.to_string()).unwrap();
+
+    let import = ldtk::import(&dir.join("project.ldtk")).unwrap();
+    assert!(import.warnings.contains(&String::from(
+        "level 'Level_0': layer 'Collisions': no tile sheet has 8x8 tiles to stand in for collision boxes, merged collision lists each tile"
+    )), "{:?}", import.warnings);
+    let merged = collision::merge(&import.scenes[0], false);
+    assert_eq!(merged[0].tiles.len(), 3);
+}
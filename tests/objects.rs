@@ -0,0 +1,157 @@
+use lilah_editor::{Config, Entity, EntitySprite, ObjectLayer, Scene, TileSheet};
+use lilah_editor::history::{Edit, History};
+use lilah_editor::validate::{self, Location};
+
+/// A 4x2 sheet of 16x8 tiles.
+fn sheet() -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": "tiles.png",
+        "path": "tiles.png",
+        "absolute_path": "tiles.png",
+        "tile_size": [16, 8],
+        "sheet_size": [64, 16]
+    })).unwrap()
+}
+
+fn sprite(sheet_id: (u32, u32)) -> Option<EntitySprite> {
+    Some(EntitySprite { sheet: String::from("tiles.png"), sheet_id })
+}
+
+fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+    (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4
+}
+
+#[test]
+fn entities_are_sized_by_their_sprite_and_turn_about_their_centre() {
+    let sheets = vec!(sheet());
+    let mut entity = Entity::new("crate", [100.0, 50.0]);
+    assert_eq!(entity.base_size(&sheets, &[]), Entity::PLACEHOLDER_SIZE);
+
+    entity.sprite = sprite((1, 0));
+    entity.scale = [2.0, 1.0];
+    let corners = entity.corners(&sheets, &[]);
+    assert!(close(corners[0], [84.0, 46.0]));
+    assert!(close(corners[2], [116.0, 54.0]));
+
+    entity.rotation = 90.0;
+    let corners = entity.corners(&sheets, &[]);
+    assert!(close(corners[0], [104.0, 34.0]));
+    assert!(close(corners[2], [96.0, 66.0]));
+    assert!(entity.contains(&sheets, &[], [100.0, 65.0]));
+    assert!(!entity.contains(&sheets, &[], [115.0, 50.0]));
+}
+
+#[test]
+fn the_entity_on_top_is_picked() {
+    let mut layer = ObjectLayer::new("Objects");
+    let mut low = Entity::new("low", [0.0, 0.0]);
+    low.z = -1;
+    layer.entities.push(Entity::new("middle", [4.0, 0.0]));
+    layer.entities.push(low);
+    layer.entities.push(Entity::new("apart", [100.0, 0.0]));
+
+    assert_eq!(layer.draw_order(), vec!(1, 0, 2));
+    assert_eq!(layer.entity_at(&[], &[], [2.0, 0.0]), Some(0));
+    assert_eq!(layer.entity_at(&[], &[], [-6.0, 0.0]), Some(1));
+    assert_eq!(layer.entity_at(&[], &[], [50.0, 0.0]), None);
+}
+
+#[test]
+fn entity_edits_undo_and_redo() {
+    let mut history = History::new();
+    let mut config = Config::new();
+    let mut scene = Scene::new("level", "level.json");
+
+    let layer = ObjectLayer::new("Objects");
+    scene.object_layers.push(layer.clone());
+    history.push(Edit::AddObjectLayer { index: 0, layer });
+
+    let entity = Entity::new("door", [8.0, 8.0]);
+    scene.object_layers[0].entities.push(entity.clone());
+    history.push(Edit::AddEntity { layer: 0, index: 0, entity: entity.clone() });
+
+    let mut moved = entity.clone();
+    moved.position = [40.0, 8.0];
+    moved.rotation = 45.0;
+    scene.object_layers[0].entities[0] = moved.clone();
+    history.push(Edit::ChangeEntity { layer: 0, index: 0, before: entity.clone(), after: moved.clone() });
+
+    history.undo(Some(&mut scene), &mut config);
+    assert!(scene.object_layers[0].entities[0] == entity);
+    history.undo(Some(&mut scene), &mut config);
+    assert!(scene.object_layers[0].entities.is_empty());
+    history.undo(Some(&mut scene), &mut config);
+    assert!(scene.object_layers.is_empty());
+
+    for _ in 0..3 {
+        history.redo(Some(&mut scene), &mut config);
+    }
+    assert!(scene.object_layers[0].entities == vec!(moved));
+}
+
+#[test]
+fn object_layer_renames_and_visibility_undo() {
+    let mut history = History::new();
+    let mut config = Config::new();
+    let mut scene = Scene::new("level", "level.json");
+    scene.object_layers.push(ObjectLayer::new("Objects"));
+
+    scene.object_layers[0].name = String::from("Enemies");
+    history.push(Edit::RenameObjectLayer { index: 0, before: String::from("Objects"), after: String::from("Enemies") });
+    scene.object_layers[0].visible = false;
+    history.push(Edit::SetObjectLayerVisible { index: 0, visible: false });
+
+    history.undo(Some(&mut scene), &mut config);
+    assert!(scene.object_layers[0].visible);
+    history.undo(Some(&mut scene), &mut config);
+    assert_eq!(scene.object_layers[0].name, "Objects");
+
+    history.redo(Some(&mut scene), &mut config);
+    assert_eq!(scene.object_layers[0].name, "Enemies");
+}
+
+#[test]
+fn entities_read_with_defaults_and_keep_unknown_fields() {
+    let layer: ObjectLayer = serde_json::from_value(serde_json::json!({
+        "name": "Objects",
+        "visible": true,
+        "entities": [{ "name": "spawn", "position": [1.0, 2.0], "team": "red" }]
+    })).unwrap();
+    let entity = &layer.entities[0];
+    assert_eq!(entity.scale, [1.0, 1.0]);
+    assert_eq!(entity.tint, [1.0, 1.0, 1.0, 1.0]);
+    assert!(entity.sprite.is_none());
+
+    let json = serde_json::to_value(&layer).unwrap();
+    assert_eq!(json["entities"][0]["team"], "red");
+    assert!(json["entities"][0].get("sprite").is_none());
+    assert!(json["entities"][0].get("properties").is_none());
+
+    // Scenes from before object layers have none
+    let scene: Scene = serde_json::from_value(serde_json::json!({
+        "name": "old", "path": "old.json", "tile_sheets": [], "layers": [], "markers": []
+    })).unwrap();
+    assert!(scene.object_layers.is_empty());
+}
+
+#[test]
+fn entities_with_missing_sprites_are_reported() {
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet());
+    let mut layer = ObjectLayer::new("Objects");
+    let mut fine = Entity::new("fine", [0.0, 0.0]);
+    fine.sprite = sprite((3, 1));
+    let mut off = Entity::new("off", [0.0, 0.0]);
+    off.sprite = sprite((4, 0));
+    let mut lost = Entity::new("lost", [0.0, 0.0]);
+    lost.sprite = Some(EntitySprite { sheet: String::from("gone.png"), sheet_id: (0, 0) });
+    layer.entities = vec!(fine, Entity::new("bare", [0.0, 0.0]), off, lost);
+    scene.object_layers.push(layer);
+
+    let problems = validate::check_scene(&scene);
+    assert_eq!(problems.len(), 2);
+    assert!(matches!(problems[0].location, Location::Entity { layer: 0, index: 2 }));
+    assert_eq!(problems[0].message, "entity off on Objects uses cell (4, 0), tiles.png only has 4x2");
+    assert!(matches!(problems[1].location, Location::Entity { layer: 0, index: 3 }));
+    assert_eq!(problems[1].message, "entity lost on Objects uses unknown tile sheet gone.png");
+}
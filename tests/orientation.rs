@@ -0,0 +1,89 @@
+use lilah_editor::Orientation;
+
+const GRID: (u32, u32) = (32, 16);
+
+/// A block of cells round the origin, odd and even rows and columns both.
+fn cells() -> Vec<(i32, i32)> {
+    (-3..=3).flat_map(|x| (-3..=3).map(move |y| (x, y))).collect()
+}
+
+#[test]
+fn picking_a_cell_middle_finds_that_cell() {
+    for orientation in Orientation::ALL {
+        for cell in cells() {
+            let middle = orientation.cell_position(cell, GRID);
+            assert_eq!(orientation.cell_at(middle, GRID), cell, "{:?} cell {:?}", orientation, cell);
+        }
+    }
+}
+
+#[test]
+fn picking_near_an_edge_finds_the_cell_it_is_inside() {
+    // A point a little way from the middle towards each corner stays inside the cell
+    for orientation in Orientation::ALL {
+        for cell in cells() {
+            let [x, y] = orientation.cell_position(cell, GRID);
+            for [cx, cy] in orientation.cell_outline(cell, GRID) {
+                let point = [x + (cx - x) * 0.8, y + (cy - y) * 0.8];
+                assert_eq!(orientation.cell_at(point, GRID), cell, "{:?} cell {:?} towards {:?}", orientation, cell, [cx, cy]);
+            }
+        }
+    }
+}
+
+#[test]
+fn diamonds_are_laid_out_the_way_tiled_does() {
+    assert_eq!(Orientation::Isometric.cell_position((1, 0), GRID), [16.0, -8.0]);
+    assert_eq!(Orientation::Isometric.cell_position((0, 1), GRID), [16.0, 8.0]);
+    // Staggered rows are half a tile apart, odd ones shifted right
+    assert_eq!(Orientation::Staggered.cell_position((0, 1), GRID), [16.0, 8.0]);
+    assert_eq!(Orientation::Staggered.cell_position((0, 2), GRID), [0.0, 16.0]);
+    assert_eq!(Orientation::HexPointy.hex_side_length(GRID), 8);
+    assert_eq!(Orientation::HexFlat.hex_side_length(GRID), 16);
+    assert_eq!(Orientation::Orthogonal.hex_side_length(GRID), 0);
+}
+
+#[test]
+fn neighbours_share_an_edge() {
+    for orientation in Orientation::ALL {
+        let expected = match orientation {
+            Orientation::HexPointy | Orientation::HexFlat => 6,
+            _ => 4
+        };
+        for cell in cells() {
+            let neighbours = orientation.neighbours(cell);
+            assert_eq!(neighbours.len(), expected);
+            for next in neighbours {
+                // Neighbouring is both ways, and the cells' outlines meet along a side
+                assert!(orientation.neighbours(next).contains(&cell), "{:?} {:?} and {:?}", orientation, cell, next);
+                let outline = orientation.cell_outline(cell, GRID);
+                let shared = orientation.cell_outline(next, GRID).iter()
+                    .filter(|p| outline.iter().any(|q| (p[0] - q[0]).abs() < 0.01 && (p[1] - q[1]).abs() < 0.01))
+                    .count();
+                assert_eq!(shared, 2, "{:?} {:?} and {:?}", orientation, cell, next);
+            }
+        }
+    }
+}
+
+#[test]
+fn cell_range_covers_the_corner_cells() {
+    for orientation in Orientation::ALL {
+        let (min, max) = ([-40.0, -30.0], [50.0, 20.0]);
+        let ((lx, ly), (hx, hy)) = orientation.cell_range(min, max, GRID);
+        for point in [min, [max[0], min[1]], max, [min[0], max[1]], [5.0, -5.0]] {
+            let (x, y) = orientation.cell_at(point, GRID);
+            assert!((lx..=hx).contains(&x) && (ly..=hy).contains(&y), "{:?} {:?}", orientation, point);
+        }
+    }
+}
+
+#[test]
+fn tiled_names_round_trip() {
+    for orientation in Orientation::ALL {
+        let (name, axis) = orientation.tiled_name();
+        assert_eq!(Orientation::from_tiled(name, axis.unwrap_or("y")), Some(orientation));
+    }
+    assert_eq!(Orientation::from_tiled("staggered", "x"), None);
+    assert_eq!(Orientation::from_tiled("oblique", "y"), None);
+}
@@ -0,0 +1,116 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{App, Entity, EntitySprite, ObjectLayer, Scene};
+use lilah_editor::prefab::{self, Prefab};
+use lilah_editor::properties::{Properties, Property};
+use lilah_editor::validate::{self, Location};
+
+/// An empty directory of its own for a test to use as a project.
+fn project(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("prefab").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn sprite(sheet: &str, sheet_id: (u32, u32)) -> Option<EntitySprite> {
+    Some(EntitySprite { sheet: sheet.to_string(), sheet_id })
+}
+
+fn coin() -> Prefab {
+    let mut coin = Prefab::new("coin");
+    coin.sprite = sprite("tiles.png", (2, 0));
+    coin.properties.insert(String::from("value"), Property::Int(1));
+    coin.properties.insert(String::from("spins"), Property::Bool(true));
+    coin
+}
+
+#[test]
+fn instances_are_numbered_per_scene() {
+    let coin = coin();
+    let mut scene = Scene::new("level", "level.json");
+    scene.object_layers.push(ObjectLayer::new("Objects"));
+
+    let first = coin.instantiate(&scene, [8.0, 8.0]);
+    assert_eq!(first.name, "coin_0");
+    assert_eq!(first.prefab.as_deref(), Some("coin"));
+    // Nothing is copied from the prefab, so editing it changes the instance
+    assert!(first.sprite.is_none());
+    assert!(first.properties.is_empty());
+
+    scene.object_layers[0].entities.push(first);
+    scene.object_layers[0].entities.push(Entity::new("coin_7", [0.0, 0.0]));
+    assert_eq!(coin.instantiate(&scene, [0.0, 0.0]).name, "coin_1");
+}
+
+#[test]
+fn instances_override_what_they_set() {
+    let mut prefabs = vec!(coin());
+    let mut entity = prefabs[0].instantiate(&Scene::new("level", "level.json"), [0.0, 0.0]);
+    assert!(entity.resolved_sprite(&prefabs) == sprite("tiles.png", (2, 0)).as_ref());
+
+    entity.sprite = sprite("tiles.png", (3, 0));
+    entity.properties.insert(String::from("value"), Property::Int(5));
+    let expected = Properties::from([
+        (String::from("spins"), Property::Bool(true)),
+        (String::from("value"), Property::Int(5))
+    ]);
+    assert!(entity.resolved_sprite(&prefabs) == sprite("tiles.png", (3, 0)).as_ref());
+    assert_eq!(entity.resolved_properties(&prefabs), expected);
+
+    // Changes to the prefab reach what the instance didn't override
+    prefabs[0].properties.insert(String::from("spins"), Property::Bool(false));
+    assert_eq!(entity.resolved_properties(&prefabs)["spins"], Property::Bool(false));
+
+    // Instances of a removed prefab keep only their own
+    entity.sprite = None;
+    assert!(entity.resolved_sprite(&[]).is_none());
+    assert_eq!(entity.resolved_properties(&[]).len(), 1);
+}
+
+#[test]
+fn prefabs_are_saved_with_the_project_once_per_name() {
+    let dir = project("prefabs_are_saved_with_the_project_once_per_name");
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+
+    app.add_prefab(coin()).unwrap();
+    app.add_prefab(Prefab::new("coin")).unwrap();
+    app.add_prefab(Prefab::new("door")).unwrap();
+    assert_eq!(app.config.prefabs.len(), 2);
+
+    let mut other = App::new();
+    other.load_project(&dir).unwrap();
+    let read = prefab::find(&other.config.prefabs, "coin").unwrap();
+    assert!(read == &coin());
+    assert_eq!(read.name_pattern, "coin_{n}");
+
+    other.remove_prefab(0).unwrap();
+    let mut again = App::new();
+    again.load_project(&dir).unwrap();
+    assert!(prefab::find(&again.config.prefabs, "coin").is_none());
+    assert!(prefab::find(&again.config.prefabs, "door").is_some());
+}
+
+#[test]
+fn instances_of_missing_prefabs_or_sheets_are_reported() {
+    let prefabs = vec!(coin());
+    let mut scene = Scene::new("level", "level.json");
+    let mut layer = ObjectLayer::new("Objects");
+    let inherits = prefabs[0].instantiate(&scene, [0.0, 0.0]);
+    let mut overrides = inherits.clone();
+    overrides.sprite = sprite("other.png", (0, 0));
+    let mut orphan = Entity::new("ghost", [0.0, 0.0]);
+    orphan.prefab = Some(String::from("ghost"));
+    layer.entities = vec!(inherits, overrides, orphan);
+    scene.object_layers.push(layer);
+
+    let problems = validate::check_instances(&scene, &prefabs);
+    assert_eq!(problems.len(), 2);
+    assert!(matches!(problems[0].location, Location::Entity { layer: 0, index: 0 }));
+    assert_eq!(
+        problems[0].message,
+        "entity coin_0 on Objects inherits a sprite from prefab coin on tile sheet tiles.png, which the scene doesn't have"
+    );
+    assert!(matches!(problems[1].location, Location::Entity { layer: 0, index: 2 }));
+    assert_eq!(problems[1].message, "entity ghost on Objects is an instance of unknown prefab ghost");
+}
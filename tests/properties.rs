@@ -0,0 +1,134 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{App, Scene, TileSheet};
+use lilah_editor::properties::{self, Property};
+use lilah_editor::validate::check_scene;
+
+/// A 4x2 sheet of 16px tiles.
+fn sheet(path: &str) -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": path,
+        "path": path,
+        "absolute_path": path,
+        "tile_size": [16, 16],
+        "sheet_size": [64, 32]
+    })).unwrap()
+}
+
+#[test]
+fn removing_the_last_property_of_a_cell_forgets_the_cell() {
+    let mut sheet = sheet("tiles.png");
+    sheet.set_property((1, 0), "friction", Some(Property::Float(0.5)));
+    sheet.set_property((1, 0), "is_ladder", Some(Property::Bool(true)));
+    assert_eq!(sheet.property((1, 0), "friction"), Some(&Property::Float(0.5)));
+
+    sheet.set_property((1, 0), "friction", None);
+    assert!(sheet.properties.contains_key(&(1, 0)));
+    sheet.set_property((1, 0), "is_ladder", None);
+    assert!(sheet.properties.is_empty());
+}
+
+#[test]
+fn properties_round_trip_with_their_type() {
+    let mut sheet = sheet("tiles.png");
+    sheet.set_property((0, 1), "name", Some(Property::String(String::from("door"))));
+    sheet.set_property((0, 1), "damage", Some(Property::Int(-3)));
+
+    let json = serde_json::to_value(&sheet).unwrap();
+    let back: TileSheet = serde_json::from_value(json).unwrap();
+    assert_eq!(back.property((0, 1), "name"), Some(&Property::String(String::from("door"))));
+    assert_eq!(back.property((0, 1), "damage"), Some(&Property::Int(-3)));
+}
+
+#[test]
+fn generated_lookup_has_an_arm_per_property() {
+    let mut ground = sheet("assets/ground.png");
+    ground.set_property((2, 1), "friction", Some(Property::Float(0.25)));
+    ground.set_property((0, 0), "is_ladder", Some(Property::Bool(true)));
+    let mut items = sheet("assets/items.png");
+    items.set_property((1, 0), "name", Some(Property::String(String::from("key \"gold\""))));
+    items.set_property((3, 1), "speed", Some(Property::Float(f64::INFINITY)));
+
+    let source = properties::generate(&[ground, items]);
+    assert!(source.contains("pub enum TileProperty"));
+    assert!(source.contains("pub fn tile_property(sheet: &str, cell: (u32, u32), key: &str) -> Option<TileProperty>"));
+
+    // Cells of each sheet come out in order, so the file doesn't change between runs
+    let arms = source.lines().filter(|l| l.contains("=> Some(")).map(str::trim).collect::<Vec<_>>();
+    assert_eq!(arms, vec!(
+        r#"("assets/ground.png", (0, 0), "is_ladder") => Some(TileProperty::Bool(true)),"#,
+        r#"("assets/ground.png", (2, 1), "friction") => Some(TileProperty::Float(0.25)),"#,
+        r#"("assets/items.png", (1, 0), "name") => Some(TileProperty::String("key \"gold\"")),"#,
+        r#"("assets/items.png", (3, 1), "speed") => Some(TileProperty::Float(f64::INFINITY)),"#
+    ));
+    assert!(source.contains("_ => None"));
+}
+
+#[test]
+fn generate_main_fills_in_the_lookup() {
+    let mut sheet = sheet("assets/ground.png");
+    sheet.set_property((1, 1), "solid", Some(Property::Bool(true)));
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet);
+
+    let mut app = App::new();
+    app.current_scene = Some(scene);
+    let main = app.generate_main().unwrap();
+    assert!(!main.contains("//TILE_PROPERTIES"));
+    assert!(main.contains(r#"("assets/ground.png", (1, 1), "solid") => Some(TileProperty::Bool(true)),"#));
+}
+
+fn project(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("properties").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn generate_main_covers_the_sheets_of_every_scene() {
+    let dir = project("generate_main_covers_the_sheets_of_every_scene");
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+
+    // Two scenes with their own copies of one sheet, and a third with another sheet
+    let mut ground = sheet("assets/ground.png");
+    ground.set_property((0, 0), "solid", Some(Property::Bool(true)));
+    let mut caves = Scene::new("caves", "levels/caves.json");
+    caves.tile_sheets.push(ground.clone());
+    fs::create_dir_all(dir.join("levels")).unwrap();
+    caves.write(&dir.join("levels/caves.json")).unwrap();
+
+    ground.set_property((0, 0), "solid", Some(Property::Bool(false)));
+    ground.set_property((1, 0), "friction", Some(Property::Float(0.5)));
+    let mut hills = Scene::new("hills", "hills.json");
+    hills.tile_sheets.push(ground);
+    hills.write(&dir.join("hills.json")).unwrap();
+
+    // The open scene counts as it is, not as saved, and comes first
+    let mut items = sheet("assets/items.png");
+    items.set_property((2, 1), "name", Some(Property::String(String::from("key"))));
+    let mut town = Scene::new("town", "town.json");
+    town.write(&dir.join("town.json")).unwrap();
+    town.tile_sheets.push(items);
+    app.current_scene = Some(town);
+
+    assert_eq!(app.other_scenes().unwrap().iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!("hills", "caves"));
+    let main = app.generate_main().unwrap();
+    let arms = main.lines().filter(|l| l.contains("=> Some(")).map(str::trim).collect::<Vec<_>>();
+    assert_eq!(arms, vec!(
+        r#"("assets/items.png", (2, 1), "name") => Some(TileProperty::String("key")),"#,
+        r#"("assets/ground.png", (0, 0), "solid") => Some(TileProperty::Bool(false)),"#,
+        r#"("assets/ground.png", (1, 0), "friction") => Some(TileProperty::Float(0.5)),"#
+    ));
+}
+
+#[test]
+fn properties_off_the_sheet_are_reported() {
+    let mut sheet = sheet("tiles.png");
+    sheet.set_property((4, 0), "solid", Some(Property::Bool(true)));
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet);
+
+    let problems = check_scene(&scene).iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    assert_eq!(problems, vec!(String::from("tiles.png has properties on cell (4, 0), it only has 4x2")));
+}
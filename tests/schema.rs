@@ -0,0 +1,176 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{App, Config, EditorError, Layer, Orientation, Scene};
+use lilah_editor::schema::{backup_path, CONFIG_VERSION, SCENE_VERSION};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schema").join(name)
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("schema").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A layer's tiles as `(key, sheet_id)`, sorted.
+fn tiles(layer: &Layer) -> Vec<((i32, i32), (u32, u32))> {
+    let mut tiles = layer.tiles.iter().map(|(key, t)| (*key, t.sheet_id)).collect::<Vec<_>>();
+    tiles.sort();
+    tiles
+}
+
+/// A project in `dir` with `scene` as its current scene, to save it the way the editor does.
+fn app_with(dir: &Path, scene: Scene) -> App {
+    let mut app = App::new();
+    app.current_project = dir.to_string_lossy().to_string();
+    app.current_scene = Some(scene);
+    app
+}
+
+#[test]
+fn v0_scene_is_upgraded_in_memory() {
+    let dir = scratch("v0_scene_is_upgraded_in_memory");
+    let file = dir.join("old.json");
+    fs::copy(fixture("v0_scene.json"), &file).unwrap();
+    let original = fs::read(&file).unwrap();
+
+    let scene = Scene::load(&file).unwrap();
+    assert_eq!(scene.upgraded_from(), Some(0));
+    assert_eq!(scene.format_version, SCENE_VERSION);
+    assert_eq!((scene.tile_sheets[0].margin, scene.tile_sheets[0].spacing), (0, 0));
+    assert_eq!(scene.layers[0].grid_size, (16, 16));
+    assert_eq!(tiles(&scene.layers[0]), vec!(
+        ((-1, 3), (3, 1)),
+        ((0, 0), (0, 0)),
+        ((1, 0), (1, 0)),
+        ((2, -1), (2, 1))
+    ));
+    assert!(scene.layers[0].tiles.values().all(|t| t.extra.is_empty()));
+    assert_eq!(scene.markers[0].name, "spawn");
+
+    // Loading leaves the file alone
+    assert_eq!(fs::read(&file).unwrap(), original);
+    assert!(!backup_path(&file, 0).exists());
+}
+
+#[test]
+fn pixel_keys_off_the_sheet_grid_get_a_finer_grid() {
+    let dir = scratch("pixel_keys_off_the_sheet_grid_get_a_finer_grid");
+    let file = dir.join("old.json");
+    fs::write(&file, serde_json::json!({
+        "format_version": 2,
+        "name": "old",
+        "path": "old.json",
+        "tile_sheets": [{
+            "filename": "tiles.png", "path": "tiles.png", "absolute_path": "tiles.png",
+            "tile_size": [16, 16], "sheet_size": [64, 32], "margin": 0, "spacing": 0
+        }],
+        "layers": [{
+            "chunks": [{ "chunk": [0, 0], "tiles": [
+                [[0, 0], { "sheet": "tiles.png", "sheet_id": [0, 0], "position": [0, 0] }],
+                [[24, 32], { "sheet": "tiles.png", "sheet_id": [1, 0], "position": [24, 32] }]
+            ]}],
+            "visible": true, "collision": false, "tile_sheet": "tiles.png", "current_tile_item": 0
+        }],
+        "markers": []
+    }).to_string()).unwrap();
+
+    let scene = Scene::load(&file).unwrap();
+    assert_eq!(scene.upgraded_from(), Some(2));
+    let layer = &scene.layers[0];
+    assert_eq!(layer.grid_size, (8, 16));
+    assert_eq!(tiles(layer), vec!(((0, 0), (0, 0)), ((3, 2), (1, 0))));
+    assert_eq!(layer.cell_position(Orientation::Orthogonal, (3, 2)), [24.0, 32.0]);
+}
+
+#[test]
+fn saving_an_upgraded_scene_backs_up_the_original() {
+    let dir = scratch("saving_an_upgraded_scene_backs_up_the_original");
+    let file = dir.join("old.json");
+    fs::copy(fixture("v0_scene.json"), &file).unwrap();
+    let original = fs::read(&file).unwrap();
+
+    let app = app_with(&dir, Scene::load(&file).unwrap());
+    app.write_current_scene().unwrap();
+    assert_eq!(app.current_scene.as_ref().unwrap().upgraded_from(), None);
+    assert_eq!(fs::read(backup_path(&file, 0)).unwrap(), original);
+
+    let reloaded = Scene::load(&file).unwrap();
+    assert_eq!(reloaded.upgraded_from(), None);
+    assert_eq!(tiles(&reloaded.layers[0]), tiles(&app.current_scene.as_ref().unwrap().layers[0]));
+
+    // A second save keeps the backup of the original rather than the upgraded file
+    app_with(&dir, reloaded).write_current_scene().unwrap();
+    assert_eq!(fs::read(backup_path(&file, 0)).unwrap(), original);
+}
+
+#[test]
+fn v0_config_is_backed_up_when_saved() {
+    let dir = scratch("v0_config_is_backed_up_when_saved");
+    let file = dir.join("config.json");
+    let original = r#"{ "assets": {}, "window_size": [640, 480] }"#;
+    fs::write(&file, original).unwrap();
+
+    let mut app = App::new();
+    app.load_project(&dir).unwrap();
+    assert_eq!(app.config.upgraded_from(), Some(0));
+    assert_eq!(app.config.format_version, CONFIG_VERSION);
+    assert_eq!(app.config.window_size, (640.0, 480.0));
+    assert!(!backup_path(&file, 0).exists());
+
+    app.write_config().unwrap();
+    assert_eq!(fs::read_to_string(backup_path(&file, 0)).unwrap(), original);
+    assert_eq!(Config::load(&file).unwrap().upgraded_from(), None);
+}
+
+#[test]
+fn files_from_a_newer_editor_are_refused() {
+    let dir = scratch("files_from_a_newer_editor_are_refused");
+    let file = dir.join("new.json");
+    fs::write(&file, format!(
+        r#"{{ "format_version": {}, "name": "", "path": "", "tile_sheets": [], "layers": [], "markers": [] }}"#,
+        SCENE_VERSION + 1
+    )).unwrap();
+
+    match Scene::load(&file) {
+        Err(EditorError::NewerFormat { version, supported, .. }) => assert_eq!((version, supported), (SCENE_VERSION + 1, SCENE_VERSION)),
+        other => panic!("expected NewerFormat, got {:?}", other.err())
+    }
+}
+
+#[test]
+fn unknown_fields_survive_a_load_and_save() {
+    let dir = scratch("unknown_fields_survive_a_load_and_save");
+    let file = dir.join("extra.json");
+    fs::write(&file, format!(r#"{{
+        "format_version": {},
+        "name": "extra",
+        "path": "extra.json",
+        "weather": "rain",
+        "tile_sheets": [],
+        "layers": [{{
+            "chunks": [{{
+                "chunk": [0, 0],
+                "tiles": [[[0, 0], {{ "sheet": "tiles.png", "sheet_id": [1, 0], "position": [0, 0], "tint": "red" }}]],
+                "biome": "snow"
+            }}],
+            "visible": true,
+            "collision": false,
+            "tile_sheet": "tiles.png",
+            "current_tile_item": 0,
+            "depth": 0.5
+        }}],
+        "markers": [{{ "position": [0, 0], "name": "spawn", "kind": "player" }}]
+    }}"#, SCENE_VERSION)).unwrap();
+
+    app_with(&dir, Scene::load(&file).unwrap()).write_current_scene().unwrap();
+    let scene = Scene::load(&file).unwrap();
+
+    assert_eq!(scene.extra["weather"], "rain");
+    assert_eq!(scene.layers[0].extra["depth"], 0.5);
+    assert_eq!(scene.layers[0].tiles[&(0, 0)].extra["tint"], "red");
+    assert_eq!(scene.markers[0].extra["kind"], "player");
+    let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(saved["layers"][0]["chunks"][0]["biome"], "snow");
+}
@@ -0,0 +1,123 @@
+use lilah_editor::{App, Layer, Scene, TileSheet};
+use lilah_editor::terrain::{self, Terrain, TerrainKind, EAST, NORTH, NORTH_EAST, SOUTH, WEST};
+
+/// A sheet of `columns` by `rows` 16px tiles.
+fn sheet(columns: u32, rows: u32) -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": "tiles.png",
+        "path": "tiles.png",
+        "absolute_path": "tiles.png",
+        "tile_size": [16, 16],
+        "sheet_size": [16 * columns, 16 * rows]
+    })).unwrap()
+}
+
+fn block_terrain(kind: TerrainKind) -> Terrain {
+    let mut terrain = Terrain::new("ground", kind);
+    terrain.set_block_rules((0, 0));
+    terrain
+}
+
+/// Paints (or erases) `terrain` at each of `cells` in turn, the way the brush does.
+fn paint(layer: &mut Layer, sheet: &TileSheet, terrain: &Terrain, cells: &[(i32, i32)], fill: bool) {
+    for cell in cells {
+        for (position, tile) in terrain::paint(layer, sheet, terrain, *cell, fill) {
+            match tile {
+                Some(tile) => { layer.tiles.insert(position, tile); }
+                None => { layer.tiles.remove(&position); }
+            }
+        }
+    }
+}
+
+#[test]
+fn sheet_id_follows_the_block_layout() {
+    let terrain = block_terrain(TerrainKind::Minimal);
+    assert_eq!(terrain.sheet_id(EAST | SOUTH), Some((0, 0)));
+    assert_eq!(terrain.sheet_id(EAST | SOUTH | WEST), Some((1, 0)));
+    assert_eq!(terrain.sheet_id(NORTH | EAST | SOUTH | WEST), Some((1, 1)));
+    assert_eq!(terrain.sheet_id(NORTH | WEST), Some((2, 2)));
+    // Minimal terrains don't look at corners
+    assert_eq!(terrain.sheet_id(NORTH | WEST | NORTH_EAST), Some((2, 2)));
+}
+
+#[test]
+fn sheet_id_falls_back_to_the_closest_rule() {
+    let mut terrain = Terrain::new("ground", TerrainKind::Minimal);
+    assert_eq!(terrain.sheet_id(0), None);
+
+    terrain.set_rule(0, (6, 0));
+    terrain.set_rule(NORTH | EAST | SOUTH | WEST, (7, 0));
+    assert_eq!(terrain.sheet_id(NORTH), Some((6, 0)));
+    assert_eq!(terrain.sheet_id(NORTH | EAST | SOUTH), Some((7, 0)));
+
+    // Setting a rule again replaces it
+    terrain.set_rule(0, (5, 0));
+    assert_eq!(terrain.sheet_id(0), Some((5, 0)));
+    assert_eq!(terrain.rules.len(), 2);
+}
+
+#[test]
+fn blob_corners_only_count_between_filled_edges() {
+    assert_eq!(TerrainKind::Blob.reduce(NORTH_EAST), 0);
+    assert_eq!(TerrainKind::Blob.reduce(NORTH | NORTH_EAST), NORTH);
+    assert_eq!(TerrainKind::Blob.reduce(NORTH | EAST | NORTH_EAST), NORTH | EAST | NORTH_EAST);
+    assert_eq!(TerrainKind::Corner.reduce(NORTH | EAST), 0);
+}
+
+#[test]
+fn painting_a_block_joins_its_edges() {
+    let sheet = sheet(3, 3);
+    let terrain = block_terrain(TerrainKind::Minimal);
+    let mut layer = Layer::new();
+
+    let block = (0..3).flat_map(|x| (0..3).map(move |y| (x, y))).collect::<Vec<(i32, i32)>>();
+    paint(&mut layer, &sheet, &terrain, &block, true);
+
+    // Rows of the sheet go down, rows of cells go up
+    assert_eq!(layer.tiles.len(), 9);
+    for (x, y) in &block {
+        assert_eq!(layer.tiles[&(*x, *y)].sheet_id, (*x as u32, 2 - *y as u32), "cell ({}, {})", x, y);
+    }
+
+    // Painting over terrain changes nothing
+    assert!(terrain::paint(&layer, &sheet, &terrain, (1, 1), true).is_empty());
+}
+
+#[test]
+fn erasing_repicks_the_neighbours() {
+    let sheet = sheet(3, 3);
+    let terrain = block_terrain(TerrainKind::Minimal);
+    let mut layer = Layer::new();
+    paint(&mut layer, &sheet, &terrain, &[(0, 0), (1, 0), (2, 0)], true);
+    assert_eq!(layer.tiles[&(1, 0)].sheet_id, terrain.sheet_id(EAST | WEST).unwrap());
+
+    let writes = terrain::paint(&layer, &sheet, &terrain, (2, 0), false);
+    assert!(writes.iter().any(|(position, tile)| *position == (2, 0) && tile.is_none()));
+    paint(&mut layer, &sheet, &terrain, &[(2, 0)], false);
+
+    assert!(!layer.tiles.contains_key(&(2, 0)));
+    assert_eq!(layer.tiles[&(1, 0)].sheet_id, terrain.sheet_id(WEST).unwrap());
+    assert_eq!(layer.tiles[&(0, 0)].sheet_id, terrain.sheet_id(EAST).unwrap());
+}
+
+#[test]
+fn a_terrain_stroke_undoes_in_one_step() {
+    let mut sheet = sheet(3, 3);
+    sheet.terrains.push(block_terrain(TerrainKind::Minimal));
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet);
+    scene.layers.push(Layer::new());
+
+    let mut app = App::new();
+    app.current_scene = Some(scene);
+    for cell in [(0, 0), (1, 0)] {
+        app.paint_terrain(0, 0, cell, true);
+    }
+    app.history.end_stroke();
+    assert_eq!(app.current_scene.as_ref().unwrap().layers[0].tiles.len(), 2);
+
+    app.undo();
+    assert!(app.current_scene.as_ref().unwrap().layers[0].tiles.is_empty());
+    assert!(!app.history.can_undo());
+}
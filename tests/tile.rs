@@ -0,0 +1,43 @@
+use lilah_editor::Tile;
+use lilah_editor::application::orient_uv;
+use lilah_editor::schema::Extra;
+
+const UV: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const TL: [f32; 2] = [0.0, 0.0];
+const TR: [f32; 2] = [1.0, 0.0];
+const BR: [f32; 2] = [1.0, 1.0];
+const BL: [f32; 2] = [0.0, 1.0];
+
+#[test]
+fn orient_uv_turns_then_mirrors() {
+    assert_eq!(orient_uv(UV, false, false, false), [TL, TR, BR, BL]);
+    assert_eq!(orient_uv(UV, true, false, false), [TR, TL, BL, BR]);
+    assert_eq!(orient_uv(UV, false, true, false), [BL, BR, TR, TL]);
+    // Turned clockwise the top left corner shows what was at the bottom left
+    assert_eq!(orient_uv(UV, false, false, true), [BL, TL, TR, BR]);
+    assert_eq!(orient_uv(UV, true, false, true), [TL, BL, BR, TR]);
+    // Both flips are half a turn
+    assert_eq!(orient_uv(UV, true, true, false), [BR, BL, TL, TR]);
+}
+
+#[test]
+fn flags_are_only_written_when_set() {
+    let mut tile = Tile {
+        sheet: String::from("tiles.png"),
+        sheet_id: (1, 0),
+        flip_x: false,
+        flip_y: false,
+        rotate: false,
+        extra: Extra::new()
+    };
+    let json = serde_json::to_value(&tile).unwrap();
+    assert!(json.get("flip_x").is_none() && json.get("rotate").is_none());
+
+    tile.flip_y = true;
+    tile.rotate = true;
+    let json = serde_json::to_value(&tile).unwrap();
+    assert_eq!((json.get("flip_x"), &json["flip_y"], &json["rotate"]), (None, &serde_json::Value::Bool(true), &serde_json::Value::Bool(true)));
+
+    let back: Tile = serde_json::from_value(json).unwrap();
+    assert!(back == tile);
+}
@@ -0,0 +1,250 @@
+use std::path::{Path, PathBuf};
+use lilah_editor::{Layer, ObjectLayer, Orientation};
+use lilah_editor::collision::CollisionShape;
+use lilah_editor::tiled::{self, TiledFormat};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiled").join(name)
+}
+
+/// A tile as `(key, sheet_id, (flip_x, flip_y, rotate))`.
+type KeyedTile = ((i32, i32), (u32, u32), (bool, bool, bool));
+
+/// A layer's tiles, sorted.
+fn tiles(layer: &Layer) -> Vec<KeyedTile> {
+    let mut tiles = layer.tiles.iter()
+        .map(|(key, t)| (*key, t.sheet_id, (t.flip_x, t.flip_y, t.rotate)))
+        .collect::<Vec<_>>();
+    tiles.sort();
+    tiles
+}
+
+#[test]
+fn tmx_import_reads_csv_layers_flags_and_point_objects() {
+    let import = tiled::import(&fixture("map.tmx")).unwrap();
+    let scene = &import.scene;
+    assert_eq!(scene.name, "map");
+
+    assert_eq!(scene.tile_sheets.len(), 1);
+    let sheet = &scene.tile_sheets[0];
+    assert_eq!(sheet.filename, "tiles.png");
+    assert_eq!(Path::new(&sheet.path), fixture("tiles.png"));
+    assert_eq!((sheet.tile_size, sheet.sheet_size), ((16, 16), (64, 32)));
+
+    // Keys are cells with y up, Tiled's rows count down from the top
+    let layer = &scene.layers[0];
+    assert_eq!(tiles(layer), vec!(
+        ((0, -1), (0, 1), (false, false, false)),
+        ((0, 0), (0, 0), (false, false, false)),
+        ((1, 0), (1, 0), (false, false, false)),
+        ((2, -2), (2, 1), (false, false, false)),
+        ((2, 0), (2, 0), (false, false, false)),
+        ((3, -2), (3, 1), (true, true, false)),
+        ((3, -1), (1, 1), (true, false, false)),
+        ((3, 0), (3, 0), (false, false, false))
+    ));
+    assert!(layer.tiles.values().all(|t| t.sheet == sheet.path));
+
+    assert_eq!(scene.markers.len(), 1);
+    assert_eq!(scene.markers[0].name, "spawn");
+    assert_eq!(scene.markers[0].position, [16.0, -32.0]);
+
+    assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+}
+
+#[test]
+fn tmj_import_reads_compressed_layers_and_external_tilesets() {
+    let import = tiled::import(&fixture("map.tmj")).unwrap();
+    let scene = &import.scene;
+
+    assert_eq!(scene.tile_sheets.len(), 1);
+    assert_eq!(Path::new(&scene.tile_sheets[0].path), fixture("tiles.png"));
+    assert_eq!(tiles(&scene.layers[0]), vec!(
+        ((0, 0), (0, 0), (false, false, false)),
+        ((1, -1), (1, 0), (false, false, false)),
+        ((2, -1), (2, 0), (false, false, false)),
+        ((3, 0), (3, 0), (false, false, false))
+    ));
+
+    assert_eq!(scene.markers.len(), 1);
+    assert_eq!(scene.markers[0].position, [48.0, 0.0]);
+    assert_eq!(import.warnings.len(), 1);
+    assert!(import.warnings[0].contains("object 'zone' was skipped"), "{:?}", import.warnings);
+}
+
+#[test]
+fn import_fails_on_files_that_are_not_maps() {
+    assert!(tiled::import(&fixture("tiles.tsx")).is_err());
+    assert!(tiled::import(&fixture("missing.tmx")).is_err());
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tiled").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn import_fails_on_tile_data_that_does_not_fill_the_layer() {
+    let dir = scratch("short_data");
+    let map = std::fs::read_to_string(fixture("map.tmx")).unwrap();
+    let data = "1,2,3,4,\n5,0,0,2147483654,\n0,0,7,3221225480\n";
+
+    let short = map.replace(data, "1,2,3,4,\n5,0,0,2147483654,\n0,0,7\n");
+    std::fs::write(dir.join("short.tmx"), short).unwrap();
+    let Err(error) = tiled::import(&dir.join("short.tmx")) else {
+        panic!("short.tmx imported");
+    };
+    let error = error.to_string();
+    assert!(error.contains("tile data has 11 gids, its size needs 12"), "{}", error);
+
+    // Five bytes is a gid and a bit
+    let ragged = map.replace("<data encoding=\"csv\">", "<data encoding=\"base64\">").replace(data, "AQIDBAU=");
+    std::fs::write(dir.join("ragged.tmx"), ragged).unwrap();
+    let Err(error) = tiled::import(&dir.join("ragged.tmx")) else {
+        panic!("ragged.tmx imported");
+    };
+    let error = error.to_string();
+    assert!(error.contains("tile data is 5 bytes, not a whole number of gids"), "{}", error);
+}
+
+#[test]
+fn export_round_trips_through_both_formats() {
+    let scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+
+    for (format, file) in [(TiledFormat::Tmx, "map.tmx"), (TiledFormat::Tmj, "map.tmj")] {
+        let dir = scratch(&format!("round_trip_{}", file.replace('.', "_")));
+        let warnings = tiled::export(&scene, &dir.join(file), format).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert!(dir.join("tiles.tsx").is_file());
+
+        let back = tiled::import(&dir.join(file)).unwrap();
+        assert!(back.warnings.is_empty(), "{:?}", back.warnings);
+        let back = back.scene;
+        assert_eq!((back.tile_sheets[0].filename.as_str(), back.tile_sheets[0].sheet_size), ("tiles.png", (64, 32)));
+        assert_eq!(tiles(&back.layers[0]), tiles(&scene.layers[0]));
+        assert_eq!(back.markers.len(), 1);
+        assert_eq!((back.markers[0].name.as_str(), back.markers[0].position), ("spawn", [16.0, -32.0]));
+    }
+}
+
+#[test]
+fn layer_attributes_round_trip_through_both_formats() {
+    let mut scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+    assert_eq!(scene.layers[0].name, "ground");
+    let layer = &mut scene.layers[0];
+    layer.name = String::from("hills");
+    layer.opacity = 0.5;
+    layer.offset = [4.0, 8.0];
+    layer.parallax = [0.5, 1.0];
+    layer.tint = [1.0, 0.0, 0.0, 1.0];
+    layer.locked = true;
+
+    for (format, file) in [(TiledFormat::Tmx, "map.tmx"), (TiledFormat::Tmj, "map.tmj")] {
+        let dir = scratch(&format!("layer_attributes_{}", file.replace('.', "_")));
+        tiled::export(&scene, &dir.join(file), format).unwrap();
+
+        let back = tiled::import(&dir.join(file)).unwrap();
+        assert!(back.warnings.is_empty(), "{:?}", back.warnings);
+        let layer = &back.scene.layers[0];
+        assert_eq!((layer.name.as_str(), layer.opacity, layer.locked), ("hills", 0.5, true));
+        assert_eq!((layer.offset, layer.parallax), ([4.0, 8.0], [0.5, 1.0]));
+        assert_eq!(layer.tint, [1.0, 0.0, 0.0, 1.0]);
+    }
+}
+
+#[test]
+fn export_warns_about_tiles_off_the_grid() {
+    let mut scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+    // Cell (5, 0) of an 8px layer sits half way along the map's 16px cells
+    let mut layer = Layer::new();
+    layer.grid_size = (8, 8);
+    layer.tiles.insert((4, 0), scene.layers[0].tiles[&(0, 0)].clone());
+    layer.tiles.insert((5, 0), scene.layers[0].tiles[&(1, 0)].clone());
+    scene.layers.push(layer);
+
+    let dir = scratch("off_grid");
+    let warnings = tiled::export(&scene, &dir.join("map.tmj"), TiledFormat::Tmj).unwrap();
+    assert_eq!(warnings, vec!(String::from("layer 'Layer 1': 1 tiles were off the 16x16 grid and were snapped to it")));
+}
+
+#[test]
+fn export_warns_about_tiles_snapped_into_one_cell() {
+    let mut scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+    // Three 8px cells in a row cover two 16px ones, the middle one lands on one of the others
+    let mut layer = Layer::new();
+    layer.grid_size = (8, 8);
+    for x in 4..7 {
+        layer.tiles.insert((x, 0), scene.layers[0].tiles[&(0, 0)].clone());
+    }
+    scene.layers.push(layer);
+
+    let dir = scratch("snapped_together");
+    let warnings = tiled::export(&scene, &dir.join("map.tmj"), TiledFormat::Tmj).unwrap();
+    assert_eq!(warnings, vec!(
+        String::from("layer 'Layer 1': 1 tiles were off the 16x16 grid and were snapped to it"),
+        String::from("layer 'Layer 1': 1 tiles were snapped into a cell another tile took and were lost")
+    ));
+}
+
+#[test]
+fn both_formats_agree_on_the_next_layer_id() {
+    // Two tile layers and the markers' object layer
+    let mut scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+    scene.layers.push(Layer::new());
+    let dir = scratch("next_layer_id");
+
+    tiled::export(&scene, &dir.join("map.tmj"), TiledFormat::Tmj).unwrap();
+    let map: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join("map.tmj")).unwrap()).unwrap();
+    assert_eq!(map["nextlayerid"], 4);
+    tiled::export(&scene, &dir.join("map.tmx"), TiledFormat::Tmx).unwrap();
+    assert!(std::fs::read_to_string(dir.join("map.tmx")).unwrap().contains("nextlayerid=\"4\""));
+
+    scene.markers.clear();
+    tiled::export(&scene, &dir.join("map.tmj"), TiledFormat::Tmj).unwrap();
+    let map: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join("map.tmj")).unwrap()).unwrap();
+    assert_eq!(map["nextlayerid"], 3);
+    tiled::export(&scene, &dir.join("map.tmx"), TiledFormat::Tmx).unwrap();
+    assert!(std::fs::read_to_string(dir.join("map.tmx")).unwrap().contains("nextlayerid=\"3\""));
+}
+
+#[test]
+fn other_orientations_round_trip_cell_for_cell() {
+    let mut scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+    scene.markers.clear();
+    // The map's top row is odd, which Tiled can't start a staggered map on
+    let mut shifted = Layer::new();
+    for (cell, tile) in scene.layers[0].tiles.iter() {
+        shifted.tiles.insert((cell.0, cell.1 - 1), tile.clone());
+    }
+    scene.layers[0] = shifted;
+
+    for orientation in [Orientation::Isometric, Orientation::Staggered, Orientation::HexPointy, Orientation::HexFlat] {
+        scene.orientation = orientation;
+        for (format, file) in [(TiledFormat::Tmx, "map.tmx"), (TiledFormat::Tmj, "map.tmj")] {
+            let dir = scratch(&format!("orientation_{:?}_{}", orientation, file.replace('.', "_")));
+            let warnings = tiled::export(&scene, &dir.join(file), format).unwrap();
+            assert!(warnings.is_empty(), "{:?}", warnings);
+
+            let back = tiled::import(&dir.join(file)).unwrap();
+            assert!(back.warnings.is_empty(), "{:?} {:?}", orientation, back.warnings);
+            assert_eq!(back.scene.orientation, orientation);
+            assert_eq!(tiles(&back.scene.layers[0]), tiles(&scene.layers[0]), "{:?} {}", orientation, file);
+        }
+    }
+}
+
+#[test]
+fn export_warns_about_what_tiled_maps_leave_out() {
+    let mut scene = tiled::import(&fixture("map.tmx")).unwrap().scene;
+    scene.tile_sheets[0].collision.insert((0, 0), vec!(CollisionShape::Box));
+    scene.object_layers.push(ObjectLayer::new("enemies"));
+
+    let dir = scratch("left_out");
+    let warnings = tiled::export(&scene, &dir.join("map.tmx"), TiledFormat::Tmx).unwrap();
+    assert_eq!(warnings, vec!(
+        String::from("tile sheet 'tiles.png': collision shapes were not exported"),
+        String::from("object layer 'enemies' and its 0 entities were not exported")
+    ));
+}
@@ -0,0 +1,101 @@
+use lilah_editor::{Layer, Orientation, Tile};
+use lilah_editor::schema::Extra;
+use lilah_editor::tools::{self, Tool};
+
+fn tile(sheet_id: (u32, u32)) -> Tile {
+    Tile { sheet: String::from("tiles.png"), sheet_id, flip_x: false, flip_y: false, rotate: false, extra: Extra::new() }
+}
+
+/// A layer with `tile` at each of `cells`.
+fn layer(cells: &[(i32, i32)], tile: &Tile) -> Layer {
+    let mut layer = Layer::new();
+    for cell in cells {
+        layer.tiles.insert(*cell, tile.clone());
+    }
+    layer
+}
+
+fn sorted(mut cells: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    cells.sort();
+    cells
+}
+
+#[test]
+fn shapes_cover_the_cells_between_their_corners() {
+    assert_eq!(tools::rect((0, 0), (2, 2), true).len(), 9);
+    assert_eq!(sorted(tools::rect((2, 2), (0, 0), false)), sorted(vec!(
+        (0, 0), (1, 0), (2, 0), (0, 1), (2, 1), (0, 2), (1, 2), (2, 2)
+    )));
+
+    // Lines step diagonally without doubling up corners
+    assert_eq!(tools::line((0, 0), (3, 1)), vec!((0, 0), (1, 0), (2, 1), (3, 1)));
+    assert_eq!(tools::line((0, 0), (0, -2)), vec!((0, 0), (0, -1), (0, -2)));
+
+    // A 5x5 ellipse leaves out the corners of its box and its middle
+    let ellipse = tools::ellipse((0, 0), (4, 4));
+    assert!(ellipse.contains(&(2, 0)) && ellipse.contains(&(0, 2)));
+    assert!(!ellipse.contains(&(0, 0)) && !ellipse.contains(&(2, 2)));
+
+    assert_eq!(Tool::Brush.shape((0, 0), (5, 5)), vec!((5, 5)));
+    assert!(!Tool::Fill.is_shape() && Tool::Ellipse.is_shape());
+}
+
+#[test]
+fn fill_stays_inside_a_closed_area() {
+    // A 5x5 square outline, so the empty inside is 3x3
+    let wall = tools::rect((0, 0), (4, 4), false);
+    let layer = layer(&wall, &tile((0, 0)));
+
+    let inside = tools::fill(&layer, Orientation::Orthogonal, (2, 2), true, 9).unwrap();
+    assert_eq!(sorted(inside), sorted(tools::rect((1, 1), (3, 3), true)));
+
+    // The wall is one contiguous area of its own
+    let filled = tools::fill(&layer, Orientation::Orthogonal, (0, 0), true, 100).unwrap();
+    assert_eq!(sorted(filled), sorted(wall));
+}
+
+#[test]
+fn fill_gives_up_past_max_area() {
+    let layer = layer(&tools::rect((0, 0), (4, 4), false), &tile((0, 0)));
+
+    assert!(tools::fill(&layer, Orientation::Orthogonal, (2, 2), true, 8).is_none());
+    // Outside the wall goes on forever
+    assert!(tools::fill(&layer, Orientation::Orthogonal, (10, 10), true, 10_000).is_none());
+}
+
+#[test]
+fn global_fill_replaces_matching_tiles_anywhere() {
+    let mut layer = layer(&[(0, 0), (5, 5), (-3, 2)], &tile((0, 0)));
+    layer.tiles.insert((1, 0), tile((1, 0)));
+
+    let cells = tools::fill(&layer, Orientation::Orthogonal, (0, 0), false, 3).unwrap();
+    assert_eq!(sorted(cells), vec!((-3, 2), (0, 0), (5, 5)));
+    assert!(tools::fill(&layer, Orientation::Orthogonal, (0, 0), false, 2).is_none());
+}
+
+#[test]
+fn fill_tells_flipped_tiles_apart() {
+    let mut layer = layer(&[(0, 0), (1, 0), (2, 0)], &tile((0, 0)));
+    let mut flipped = tile((0, 0));
+    flipped.flip_x = true;
+    layer.tiles.insert((1, 0), flipped);
+
+    let cells = tools::fill(&layer, Orientation::Orthogonal, (0, 0), false, 10).unwrap();
+    assert_eq!(sorted(cells), vec!((0, 0), (2, 0)));
+    assert_eq!(tools::fill(&layer, Orientation::Orthogonal, (1, 0), true, 10).unwrap(), vec!((1, 0)));
+}
+
+#[test]
+fn fill_follows_the_scene_orientation() {
+    // Side by side cells share an edge on a square grid, but only touch at a corner as diamonds
+    let layer = layer(&[(0, 0), (1, 0)], &tile((0, 0)));
+    assert_eq!(tools::fill(&layer, Orientation::Orthogonal, (0, 0), true, 10).unwrap().len(), 2);
+    assert_eq!(tools::fill(&layer, Orientation::Staggered, (0, 0), true, 10).unwrap(), vec!((0, 0)));
+    assert_eq!(tools::fill(&layer, Orientation::HexPointy, (0, 0), true, 10).unwrap().len(), 2);
+}
+
+#[test]
+fn global_fill_from_an_empty_cell_covers_nothing() {
+    let layer = layer(&[(0, 0)], &tile((0, 0)));
+    assert_eq!(tools::fill(&layer, Orientation::Orthogonal, (4, 4), false, 1), Some(vec!()));
+}
@@ -0,0 +1,128 @@
+use std::{fs, path::{Path, PathBuf}};
+use lilah_editor::{Asset, AssetType, Config, Layer, LoadType, Marker, Scene, Tile, TileSheet};
+use lilah_editor::schema::Extra;
+use lilah_editor::validate::{check_config, check_scene, Location};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("validate").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A 4x2 sheet of 16px tiles.
+fn sheet() -> TileSheet {
+    serde_json::from_value(serde_json::json!({
+        "filename": "tiles.png",
+        "path": "tiles.png",
+        "absolute_path": "tiles.png",
+        "tile_size": [16, 16],
+        "sheet_size": [64, 32]
+    })).unwrap()
+}
+
+fn tile(sheet: &str, sheet_id: (u32, u32)) -> Tile {
+    Tile { sheet: sheet.to_string(), sheet_id, flip_x: false, flip_y: false, rotate: false, extra: Extra::new() }
+}
+
+fn marker(name: &str) -> Marker {
+    Marker { position: [0.0, 0.0], name: name.to_string(), extra: Extra::new() }
+}
+
+fn asset(path: &str, type_of: AssetType, load_type: LoadType, load_order: Option<usize>) -> Asset {
+    Asset {
+        name: path.to_string(),
+        path: path.to_string(),
+        absolute_path: path.to_string(),
+        type_of,
+        load_type,
+        load_order,
+        extra: Extra::new()
+    }
+}
+
+fn messages(problems: &[lilah_editor::validate::Problem]) -> Vec<String> {
+    problems.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+fn clean_scene_has_no_problems() {
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet());
+    let mut layer = Layer::new();
+    layer.tile_sheet = String::from("tiles.png");
+    layer.tiles.insert((0, 0), tile("tiles.png", (3, 1)));
+    scene.layers.push(layer);
+    scene.markers.push(marker("spawn"));
+
+    assert!(check_scene(&scene).is_empty());
+}
+
+#[test]
+fn scene_problems_point_at_what_is_wrong() {
+    let mut scene = Scene::new("level", "level.json");
+    scene.tile_sheets.push(sheet());
+    let mut empty = sheet();
+    empty.filename = String::from("empty.png");
+    empty.path = String::from("empty.png");
+    empty.tile_size = (0, 16);
+    scene.tile_sheets.push(empty);
+
+    let mut layer = Layer::new();
+    layer.name = String::from("Walls");
+    layer.tile_sheet = String::from("gone.png");
+    layer.tiles.insert((1, 0), tile("tiles.png", (4, 0)));
+    layer.tiles.insert((0, 0), tile("gone.png", (0, 0)));
+    scene.layers.push(layer);
+    let mut gridless = Layer::new();
+    gridless.grid_size = (16, 0);
+    scene.layers.push(gridless);
+    scene.markers.push(marker("spawn"));
+    scene.markers.push(marker("spawn"));
+
+    let problems = check_scene(&scene);
+    assert_eq!(messages(&problems), vec!(
+        String::from("tile sheet empty.png has a tile size of 0"),
+        String::from("Walls uses tile sheet gone.png which was removed"),
+        String::from("Walls tile at cell (0, 0) uses unknown tile sheet gone.png"),
+        String::from("Walls tile at cell (1, 0) uses sheet cell (4, 0), tiles.png only has 4x2"),
+        String::from("Layer 1 has a grid size of 0"),
+        String::from("marker 1 has the same name as marker 0: spawn")
+    ));
+    assert!(matches!(problems[0].location, Location::TileSheet(1)));
+    assert!(matches!(problems[1].location, Location::Layer(0)));
+    assert!(matches!(problems[3].location, Location::Tile { layer: 0, position: (1, 0) }));
+    assert!(matches!(problems[4].location, Location::Layer(1)));
+    assert!(matches!(problems[5].location, Location::Marker(1)));
+}
+
+#[test]
+fn config_problems_cover_missing_files_and_script_settings() {
+    let dir = scratch("config_problems_cover_missing_files_and_script_settings");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("player.png"), "").unwrap();
+    fs::write(dir.join("src/main.wren"), "").unwrap();
+    fs::write(dir.join("src/other.wren"), "").unwrap();
+    fs::write(dir.join("src/late.wren"), "").unwrap();
+
+    let mut config = Config::new();
+    let mut add = |key: &str, base: &Path, asset: Asset| {
+        let mut asset = asset;
+        asset.absolute_path = base.join(&asset.path).to_string_lossy().to_string();
+        config.assets.insert(key.to_string(), asset);
+    };
+    let src = dir.join("src");
+    add("a", &dir, asset("player.png", AssetType::Texture, LoadType::External, None));
+    add("b", &src, asset("main.wren", AssetType::Script, LoadType::Emdedded, Some(0)));
+    add("c", &src, asset("other.wren", AssetType::Script, LoadType::Emdedded, Some(0)));
+    add("d", &src, asset("late.wren", AssetType::Script, LoadType::Emdedded, None));
+    add("e", &dir, asset("music.mp3", AssetType::Music, LoadType::External, None));
+
+    let problems = check_config(&config, &dir);
+    let messages = messages(&problems);
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert_eq!(messages[0], "other.wren: load order 0 is also used by main.wren");
+    assert_eq!(messages[1], "late.wren: script has no load order");
+    assert!(messages[2].starts_with("music.mp3: ") && messages[2].ends_with("music.mp3 does not exist"), "{}", messages[2]);
+    assert!(matches!(&problems[2].location, Location::Asset(key) if key == "e"));
+}